@@ -0,0 +1,583 @@
+// A JSON API for running duels and querying the card database, independent of how the requests arrive.
+// The http module (behind the http feature) serves it over HTTP, with a WebSocket stream for the events of a duel.
+//
+// - POST /duels with a NewDuel creates a duel, and returns {"id": n, "seed": n, "duel": {..}}.
+//   Given decks must follow the NewDuel's deck_rules, otherwise every violation is listed in the error.
+// - GET /duels lists the IDs of the running duels.
+// - GET /duels/{id} returns the duel, and DELETE /duels/{id} removes it.
+// - GET /duels/{id}/commands lists the valid commands of the current player. Large hands have millions of them, so at most
+//   MAX_LISTED_COMMANDS are listed, starting from ?offset=n.
+// - POST /duels/{id}/commands with a command, e.g. {"EndTurnCmd": {}}, executes it and returns {"events": [..], "duel": {..}}.
+// - GET /duels/{id}/events is a stream of DuelEvent, see Api::subscribe.
+// - POST /fusions with {"cards": [..], "glitch_fusions": true} returns what the card IDs combine into, in that order.
+// - GET /cards lists the card database, or only the cards whose name contains ?name=...
+// - GET /cards/{id} returns one card.
+//
+// Errors are returned as {"error": ".."} with a 4xx status, or 500 if handling the request panicked.
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::{
+    card_from_id, combination_kind, combine_cards_with_rules,
+    duel::{
+        command::{CommandError, DuelCommandEnum, MAX_LISTED_COMMANDS},
+        deck::{generate_random_deck_with_rng, DeckRules, DeckViolation},
+        player::Player,
+        ruleset::Ruleset,
+        PlayerEnum,
+    },
+    replay::{step_events, StepEvent},
+    simulate::panic_message,
+    Card, CardData, CombinationKind, Duel, CARDS, DUELISTS,
+};
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Not found.")]
+    NotFound,
+    #[error("Method not allowed.")]
+    MethodNotAllowed,
+    #[error("This endpoint is a WebSocket stream.")]
+    UpgradeRequired,
+    #[error("Invalid request body: {0}.")]
+    Json(#[from] serde_json::Error),
+    #[error("Unknown card ID {0}.")]
+    UnknownCard(usize),
+    #[error("Unknown duelist {0}.")]
+    UnknownDuelist(String),
+    #[error("Invalid deck for {player:?}: {}", violations.iter().map(ToString::to_string).collect::<Vec<_>>().join(" "))]
+    InvalidDeck {
+        player: PlayerEnum,
+        violations: Vec<DeckViolation>,
+    },
+    #[error("Invalid query parameter {0}.")]
+    InvalidQuery(String),
+    #[error("{0}")]
+    Command(#[from] CommandError),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    // The HTTP status code of the error.
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::NotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::UpgradeRequired => 426,
+            ApiError::Command(_) => 409,
+            ApiError::Internal(_) => 500,
+            _ => 400,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ApiResponse {
+    pub status: u16,
+    // Always JSON.
+    pub body: String,
+}
+
+// One side of a new duel.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlayerSetup {
+    // The card IDs of the deck, which is shuffled. Without one, the duelist's deck pool or a random deck is used.
+    pub deck: Option<Vec<usize>>,
+    // The name of an enemy duelist, whose hand size and mage behaviour the player takes.
+    pub duelist: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct NewDuel {
+    // Decides the decks that are not given and the order of every deck. A random seed is used without one.
+    pub seed: Option<u64>,
+    pub player1: PlayerSetup,
+    pub player2: PlayerSetup,
+    pub ruleset: Ruleset,
+    // The rules that given decks must follow. Campaign rules by default.
+    pub deck_rules: DeckRules,
+}
+
+impl NewDuel {
+    // Returns the duel along with the seed it was created with.
+    pub fn build(&self) -> Result<(Duel, u64), ApiError> {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut players = [
+            (PlayerEnum::Player1, &self.player1),
+            (PlayerEnum::Player2, &self.player2),
+        ]
+        .into_iter()
+        .map(|(player, setup)| {
+            let duelist = setup
+                .duelist
+                .as_ref()
+                .map(|name| {
+                    DUELISTS
+                        .iter()
+                        .find(|duelist| &duelist.name == name)
+                        .ok_or_else(|| ApiError::UnknownDuelist(name.clone()))
+                })
+                .transpose()?;
+            let mut deck = match (&setup.deck, duelist) {
+                (Some(ids), _) => {
+                    let deck = ids
+                        .iter()
+                        .map(|&id| card(id))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let violations = self.deck_rules.check(&deck);
+                    if !violations.is_empty() {
+                        return Err(ApiError::InvalidDeck { player, violations });
+                    }
+                    deck
+                }
+                (None, Some(duelist)) => duelist.random_deck(&mut rng),
+                (None, None) => generate_random_deck_with_rng(&mut rng),
+            };
+            deck.shuffle(&mut rng);
+            Ok(match duelist {
+                Some(duelist) => Player::from_duelist(duelist, deck, &self.ruleset),
+                None => Player::with_ruleset(deck, &self.ruleset),
+            })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+        let player2 = players.pop().unwrap();
+        let player1 = players.pop().unwrap();
+        Ok((
+            Duel::with_ruleset(player1, player2, self.ruleset.clone()),
+            seed,
+        ))
+    }
+}
+
+// Pushed to the subscribers of a duel after every command.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DuelEvent {
+    pub player: PlayerEnum,
+    pub command: DuelCommandEnum,
+    pub events: Vec<StepEvent>,
+    // The turn after the command.
+    pub turn: u32,
+}
+
+// A card as it is after combining, e.g. with the stat changes of equips.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CardSummary {
+    pub id: usize,
+    pub name: String,
+    pub attack: Option<i32>,
+    pub defense: Option<i32>,
+}
+
+impl From<Card> for CardSummary {
+    fn from(card: Card) -> Self {
+        let stats = card.get_stats_no_terrain();
+        Self {
+            id: card.id,
+            name: card.name.clone(),
+            attack: stats.map(|(attack, _)| attack),
+            defense: stats.map(|(_, defense)| defense),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FusionRequest {
+    pub cards: Vec<usize>,
+    #[serde(default = "default_glitch_fusions")]
+    pub glitch_fusions: bool,
+}
+
+fn default_glitch_fusions() -> bool {
+    Ruleset::default().glitch_fusions
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FusionStep {
+    pub card1: CardSummary,
+    pub card2: CardSummary,
+    pub result: CardSummary,
+    pub kind: CombinationKind,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FusionChain {
+    pub steps: Vec<FusionStep>,
+    // The card that ends up on the field.
+    pub result: CardSummary,
+}
+
+fn card(id: usize) -> Result<Card, ApiError> {
+    if (1..=CARDS.len()).contains(&id) {
+        Ok(card_from_id(id))
+    } else {
+        Err(ApiError::UnknownCard(id))
+    }
+}
+
+pub fn fusion_chain(request: &FusionRequest) -> Result<FusionChain, ApiError> {
+    let cards = request
+        .cards
+        .iter()
+        .map(|&id| card(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(&first) = cards.first() else {
+        return Err(CommandError::InvalidNumberOfCardsSelected.into());
+    };
+    let steps = combine_cards_with_rules(cards, request.glitch_fusions)
+        .into_iter()
+        .map(|(card1, card2, result)| FusionStep {
+            kind: combination_kind(&card1, &card2, request.glitch_fusions),
+            card1: card1.into(),
+            card2: card2.into(),
+            result: result.into(),
+        })
+        .collect::<Vec<_>>();
+    let result = steps
+        .last()
+        .map_or_else(|| first.into(), |step| step.result.clone());
+    Ok(FusionChain { steps, result })
+}
+
+struct Session {
+    duel: Duel,
+    subscribers: Vec<Sender<DuelEvent>>,
+}
+
+// The running duels. It can be shared between threads, e.g. in an Arc.
+#[derive(Default)]
+pub struct Api {
+    sessions: Mutex<BTreeMap<u64, Session>>,
+    next_id: Mutex<u64>,
+}
+
+impl Api {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A request that panics, e.g. on a bug in a command, gets a 500 response rather than taking down the caller.
+    pub fn handle(&self, method: &str, url: &str, body: &str) -> ApiResponse {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.route(method, url, body)))
+            .unwrap_or_else(|payload| Err(ApiError::Internal(panic_message(payload.as_ref()))));
+        match result {
+            Ok((status, value)) => ApiResponse {
+                status,
+                body: value.to_string(),
+            },
+            Err(error) => ApiResponse {
+                status: error.status(),
+                body: json!({ "error": error.to_string() }).to_string(),
+            },
+        }
+    }
+
+    // The events of every command executed in the duel from now on. The channel closes when the duel is removed.
+    pub fn subscribe(&self, id: u64) -> Result<Receiver<DuelEvent>, ApiError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&id).ok_or(ApiError::NotFound)?;
+        let (sender, receiver) = channel();
+        session.subscribers.push(sender);
+        Ok(receiver)
+    }
+
+    pub fn create_duel(&self, new_duel: &NewDuel) -> Result<(u64, u64), ApiError> {
+        let (duel, seed) = new_duel.build()?;
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        self.sessions.lock().unwrap().insert(
+            id,
+            Session {
+                duel,
+                subscribers: Vec::new(),
+            },
+        );
+        Ok((id, seed))
+    }
+
+    pub fn duel(&self, id: u64) -> Result<Duel, ApiError> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(&id).ok_or(ApiError::NotFound)?;
+        Ok(session.duel.clone())
+    }
+
+    // Executes a command for the current player, and pushes its events to the subscribers.
+    // The command is executed on a copy of the duel without holding the lock, so a command that panics can't poison it.
+    // If another request changed the duel in the meantime, the command is executed again on the new duel.
+    pub fn execute(&self, id: u64, command: DuelCommandEnum) -> Result<DuelEvent, ApiError> {
+        let (before, mut sessions) = loop {
+            let before = self.duel(id)?;
+            let mut after = before.clone();
+            after.execute(&command)?;
+            let mut sessions = self.sessions.lock().unwrap();
+            let session = sessions.get_mut(&id).ok_or(ApiError::NotFound)?;
+            if session.duel == before {
+                session.duel = after;
+                break (before, sessions);
+            }
+        };
+        let session = sessions.get_mut(&id).unwrap();
+        let event = DuelEvent {
+            player: before.get_player_enum(),
+            command,
+            events: step_events(&before, &session.duel),
+            turn: session.duel.turn,
+        };
+        // subscribers that went away are dropped
+        session
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        Ok(event)
+    }
+
+    fn route(&self, method: &str, url: &str, body: &str) -> Result<(u16, Value), ApiError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        match (method, segments.as_slice()) {
+            ("GET", ["cards"]) => {
+                let name = query_value(query, "name").map(|name| name.to_lowercase());
+                let cards = CARDS
+                    .iter()
+                    .filter(|card| {
+                        name.as_ref()
+                            .is_none_or(|name| card.name.to_lowercase().contains(name))
+                    })
+                    .collect::<Vec<&CardData>>();
+                Ok((200, serde_json::to_value(cards)?))
+            }
+            ("GET", ["cards", id]) => {
+                let id = id.parse().map_err(|_| ApiError::NotFound)?;
+                let card = card(id).map_err(|_| ApiError::NotFound)?;
+                Ok((200, serde_json::to_value(card.data())?))
+            }
+            ("POST", ["fusions"]) => {
+                let chain = fusion_chain(&serde_json::from_str(body)?)?;
+                Ok((200, serde_json::to_value(chain)?))
+            }
+            ("GET", ["duels"]) => {
+                let ids = self
+                    .sessions
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>();
+                Ok((200, json!(ids)))
+            }
+            ("POST", ["duels"]) => {
+                // an empty body creates a random duel
+                let new_duel: NewDuel = if body.trim().is_empty() {
+                    NewDuel::default()
+                } else {
+                    serde_json::from_str(body)?
+                };
+                let (id, seed) = self.create_duel(&new_duel)?;
+                Ok((
+                    201,
+                    json!({ "id": id, "seed": seed, "duel": self.duel(id)? }),
+                ))
+            }
+            ("GET", ["duels", id]) => Ok((200, serde_json::to_value(self.duel(parse_id(id)?)?)?)),
+            ("DELETE", ["duels", id]) => {
+                let session = self.sessions.lock().unwrap().remove(&parse_id(id)?);
+                let session = session.ok_or(ApiError::NotFound)?;
+                Ok((200, serde_json::to_value(session.duel)?))
+            }
+            ("GET", ["duels", id, "commands"]) => {
+                let offset = match query_value(query, "offset") {
+                    Some(offset) => offset
+                        .parse()
+                        .map_err(|_| ApiError::InvalidQuery("offset".to_string()))?,
+                    None => 0,
+                };
+                let valid = self.duel(parse_id(id)?)?.valid_commands();
+                let commands = (offset..valid.len())
+                    .take(MAX_LISTED_COMMANDS)
+                    .filter_map(|index| valid.get(index))
+                    .collect::<Vec<_>>();
+                Ok((200, serde_json::to_value(commands)?))
+            }
+            ("POST", ["duels", id, "commands"]) => {
+                let id = parse_id(id)?;
+                let event = self.execute(id, serde_json::from_str(body)?)?;
+                Ok((
+                    200,
+                    json!({ "events": event.events, "duel": self.duel(id)? }),
+                ))
+            }
+            ("GET", ["duels", id, "events"]) => {
+                parse_id(id).and_then(|id| self.duel(id))?;
+                Err(ApiError::UpgradeRequired)
+            }
+            (
+                _,
+                ["cards"]
+                | ["cards", _]
+                | ["fusions"]
+                | ["duels"]
+                | ["duels", _]
+                | ["duels", _, "commands" | "events"],
+            ) => Err(ApiError::MethodNotAllowed),
+            _ => Err(ApiError::NotFound),
+        }
+    }
+}
+
+// The ID in a path like /duels/{id}/events.
+pub fn duel_id_from_path(path: &str, suffix: &str) -> Option<u64> {
+    let path = path.split('?').next()?.trim_matches('/');
+    let id = path.strip_prefix("duels/")?.strip_suffix(suffix)?;
+    id.trim_end_matches('/').parse().ok()
+}
+
+fn parse_id(id: &str) -> Result<u64, ApiError> {
+    id.parse().map_err(|_| ApiError::NotFound)
+}
+
+// The decoded value of a key in a query string like name=blue+eyes&x=1.
+fn query_value(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next(), iter.next()];
+                let decoded = match hex {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                // malformed escapes are kept as they are
+                match decoded {
+                    Some(decoded) => bytes.push(decoded),
+                    None => bytes.extend(std::iter::once(b'%').chain(hex.into_iter().flatten())),
+                }
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api() {
+        let api = Api::new();
+        let response = api.handle(
+            "POST",
+            "/duels",
+            r#"{"seed": 7, "player2": {"duelist": "Heishin"}}"#,
+        );
+        assert_eq!(response.status, 201);
+        let created: Value = serde_json::from_str(&response.body).unwrap();
+        let id = created["id"].as_u64().unwrap();
+
+        // the same seed creates the same duel
+        let (duel, _) = NewDuel {
+            seed: Some(7),
+            player2: PlayerSetup {
+                duelist: Some("Heishin".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(api.duel(id).unwrap(), duel);
+        assert_eq!(duel.player2.hand_size, 20);
+
+        let events = api.subscribe(id).unwrap();
+        let response = api.handle("GET", &format!("/duels/{}/commands", id), "");
+        let commands: Vec<DuelCommandEnum> = serde_json::from_str(&response.body).unwrap();
+        let body = serde_json::to_string(&commands[0]).unwrap();
+        let response = api.handle("POST", &format!("/duels/{}/commands", id), &body);
+        dbg!(&response);
+        assert_eq!(response.status, 200);
+        assert_eq!(events.try_recv().unwrap().player, PlayerEnum::Player1);
+
+        // the duel is in the field state now
+        let response = api.handle("POST", &format!("/duels/{}/commands", id), &body);
+        assert_eq!(response.status, 409);
+
+        // every rule a given deck breaks is listed
+        let response = api.handle("POST", "/duels", r#"{"player1": {"deck": [1, 1, 1, 1]}}"#);
+        dbg!(&response);
+        assert_eq!(response.status, 400);
+        let error: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(
+            error["error"],
+            "Invalid deck for Player1: Deck must contain exactly 40 cards, found 4. \
+             Card 1 appears 4 times, but at most 3 copies are allowed."
+        );
+
+        let response = api.handle("POST", "/fusions", r#"{"cards": [1, 1]}"#);
+        let chain: FusionChain = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(chain.steps.len(), 1);
+
+        let response = api.handle("GET", "/cards?name=blue-eyes%20white", "");
+        let cards: Vec<CardData> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(cards[0].name, "Blue-eyes White Dragon");
+
+        assert_eq!(api.handle("GET", "/cards/9999", "").status, 404);
+        assert_eq!(api.handle("PUT", "/duels", "").status, 405);
+        assert_eq!(
+            api.handle("DELETE", &format!("/duels/{}", id), "").status,
+            200
+        );
+        assert!(events.recv().is_err());
+        assert_eq!(duel_id_from_path("/duels/12/events", "events"), Some(12));
+    }
+
+    #[test]
+    fn test_api_panic() {
+        let api = Api::new();
+        let mut duel = Duel::random();
+        duel.player1.hand[0] = Card {
+            id: 0,
+            stat_delta: 0,
+        };
+        api.sessions.lock().unwrap().insert(
+            1,
+            Session {
+                duel,
+                subscribers: Vec::new(),
+            },
+        );
+
+        // a command that panics is a 500, and the duel can still be used
+        let body = r#"{"HandPlaySingleCmd": {"hand_index": 0, "face_direction": "Down", "field_index": 0}}"#;
+        let response = api.handle("POST", "/duels/1/commands", body);
+        dbg!(&response);
+        assert_eq!(response.status, 500);
+        assert_eq!(api.handle("GET", "/duels/1", "").status, 200);
+        assert_eq!(
+            api.handle("GET", "/duels/1/commands?offset=x", "").status,
+            400
+        );
+    }
+}
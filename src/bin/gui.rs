@@ -2,12 +2,13 @@
 
 use dioxus::prelude::*;
 use dioxus_desktop::{LogicalSize, WindowBuilder};
-use fmsim::duel::command::{DuelCommand, DuelCommandEnum};
-use fmsim::duel::command_strategy::{CommandStrategy, RandomCommandStrategy};
-use fmsim::duel::field::{MonsterRowPosition, SpellRowPosition};
+use fmsim::duel::command::{CommandError, DuelCommand, DuelCommandEnum};
+use fmsim::duel::command_strategy::{strategy_from_name, Budget, BudgetedStrategy};
+use fmsim::duel::field::{FaceDirection, GuardianStarChoice};
 use fmsim::duel::state::DuelStateEnum;
-use fmsim::{Card, Duel};
 use fmsim::duel::PlayerEnum;
+use fmsim::replay::{Replay, Timeline};
+use fmsim::{combination_kind, combine_cards_with_rules, Card, CardVariant, Duel};
 
 pub fn default_window() -> WindowBuilder {
     let builder = WindowBuilder::new();
@@ -29,42 +30,362 @@ fn main() {
         ))
         .with_window(default_window());
     // launch the dioxus app in a webview
-    // dioxus_tui::launch(App);
+    // `gui [random|mage|search]` plays as player 1 against the given strategy, random by default.
     dioxus_desktop::launch_cfg(App, config);
 }
 
+fn opponent_from_args() -> Box<dyn BudgetedStrategy> {
+    let seed = rand::random();
+    let name = std::env::args().nth(1).unwrap_or_default();
+    strategy_from_name(&name, seed).unwrap_or_else(|| strategy_from_name("random", seed).unwrap())
+}
+
+// What the human has clicked so far for the command they are building.
+struct Selection {
+    // Hand cards in the order they were clicked, which is the order they are fused in.
+    hand_indices: Vec<usize>,
+    face_direction: FaceDirection,
+    // The monster that attacks or changes mode.
+    monster_index: Option<usize>,
+    // The equip on the field that is being played on a monster.
+    spell_index: Option<usize>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            hand_indices: Vec::new(),
+            face_direction: FaceDirection::Up,
+            monster_index: None,
+            spell_index: None,
+        }
+    }
+}
+
+// The human is always player 1, and the opponent plays its turns as soon as the human's turn ends.
+// The duel can be rewound to any earlier ply, and played on from there, dropping the plies after it.
+struct Game {
+    timeline: Timeline,
+    selection: Selection,
+    opponent: Box<dyn BudgetedStrategy>,
+    budget: Budget,
+}
+
+impl Game {
+    // `gui [random|mage|search] [replay file]` opens the replay at its start, otherwise a new duel is started.
+    fn new() -> Self {
+        let replay = match std::env::args().nth(2) {
+            Some(path) => Replay::load(path).expect("Unable to load replay"),
+            None => Replay::new(Duel::random()),
+        };
+        let mut game = Self {
+            timeline: Timeline::new(replay).expect("Unable to replay the duel"),
+            selection: Selection::default(),
+            opponent: opponent_from_args(),
+            budget: Budget::time(std::time::Duration::from_secs(1)),
+        };
+        if game.timeline.is_empty() {
+            game.play_opponent();
+        }
+        game
+    }
+
+    fn duel(&self) -> &Duel {
+        self.timeline.duel()
+    }
+
+    fn is_over(&self) -> bool {
+        matches!(self.duel().state, DuelStateEnum::EndState(_))
+    }
+
+    // Whether the duel is at its last ply, which is the only one the human can play from.
+    fn is_live(&self) -> bool {
+        self.timeline.ply() == self.timeline.len()
+    }
+
+    fn seek(&mut self, ply: usize) {
+        self.timeline.seek(ply);
+        self.selection = Selection::default();
+    }
+
+    // Drops the plies after the current one, and continues the duel from there.
+    fn branch(&mut self) {
+        self.timeline.branch();
+        self.selection = Selection::default();
+        self.play_opponent();
+    }
+
+    fn play_opponent(&mut self) {
+        while !self.is_over() && self.duel().get_player_enum() == PlayerEnum::Player2 {
+            let decision = self.opponent.decide(self.duel(), self.budget);
+            self.timeline.execute(decision.command).unwrap();
+        }
+    }
+
+    fn play(&mut self, command: DuelCommandEnum) {
+        self.timeline.execute(command).unwrap();
+        self.selection = Selection::default();
+        self.play_opponent();
+    }
+
+    // Commands are only offered to the human if they pass check_valid.
+    fn checked(&self, command: Result<DuelCommandEnum, CommandError>) -> Option<DuelCommandEnum> {
+        if !self.is_live() {
+            return None;
+        }
+        command
+            .and_then(|command| command.check_valid(self.duel()).map(|_| command))
+            .ok()
+    }
+
+    fn in_state(&self, hand: bool, field: bool) -> bool {
+        if !self.is_live() {
+            return false;
+        }
+        match self.duel().state {
+            DuelStateEnum::HandState(_) => hand,
+            DuelStateEnum::FieldState(_) => field,
+            _ => false,
+        }
+    }
+
+    // Monsters and face-up equips are played on the monster row, everything else on the spell row.
+    fn goes_to_monster_row(&self, hand_index: usize) -> bool {
+        let card = self.duel().get_player().hand[hand_index];
+        matches!(card.variant, CardVariant::Monster { .. })
+            || (matches!(card.variant, CardVariant::Equip { .. })
+                && self.selection.face_direction == FaceDirection::Up)
+    }
+
+    fn place_single(&self, field_index: usize) -> Option<DuelCommandEnum> {
+        let [hand_index] = self.selection.hand_indices[..] else {
+            return None;
+        };
+        self.checked(
+            self.duel()
+                .command_builder()
+                .hand()
+                .and_then(|builder| builder.select(hand_index))
+                .map(|builder| builder.facing(self.selection.face_direction))
+                .and_then(|builder| builder.place(field_index)),
+        )
+    }
+
+    // The command for clicking a position of the human's monster row, if it completes one.
+    fn monster_command(&self, monster_index: usize) -> Option<DuelCommandEnum> {
+        if self.in_state(true, false) {
+            match self.selection.hand_indices.len() {
+                0 => None,
+                1 if self.goes_to_monster_row(self.selection.hand_indices[0]) => {
+                    self.place_single(monster_index)
+                }
+                1 => None,
+                _ => self.checked(
+                    self.duel()
+                        .command_builder()
+                        .hand()
+                        .and_then(|builder| {
+                            builder.select_multiple(self.selection.hand_indices.clone())
+                        })
+                        .and_then(|builder| builder.place(monster_index)),
+                ),
+            }
+        } else {
+            let spell_index = self.selection.spell_index?;
+            self.checked(
+                self.duel()
+                    .command_builder()
+                    .field()
+                    .and_then(|builder| builder.play_equip(spell_index, monster_index)),
+            )
+        }
+    }
+
+    // A monster can be selected if it can attack something or change mode.
+    fn can_select_monster(&self, monster_index: usize) -> bool {
+        let builder = || {
+            self.duel()
+                .command_builder()
+                .field()
+                .and_then(|builder| builder.select_monster(monster_index))
+        };
+        self.checked(builder().map(|builder| builder.change_mode()))
+            .is_some()
+            || (0..self.duel().get_enemy().monster_row.len()).any(|enemy_index| {
+                self.checked(builder().and_then(|builder| builder.attack(enemy_index)))
+                    .is_some()
+            })
+    }
+
+    // The command for clicking a position of the human's spell row, if it completes one.
+    fn spell_command(&self, spell_index: usize) -> Option<DuelCommandEnum> {
+        if self.in_state(true, false) {
+            let [hand_index] = self.selection.hand_indices[..] else {
+                return None;
+            };
+            if self.goes_to_monster_row(hand_index) {
+                return None;
+            }
+            self.place_single(spell_index)
+        } else {
+            self.checked(
+                self.duel()
+                    .command_builder()
+                    .field()
+                    .and_then(|builder| builder.play_spell(spell_index)),
+            )
+        }
+    }
+
+    fn can_select_equip(&self, spell_index: usize) -> bool {
+        (0..self.duel().get_player().monster_row.len()).any(|monster_index| {
+            self.checked(
+                self.duel()
+                    .command_builder()
+                    .field()
+                    .and_then(|builder| builder.play_equip(spell_index, monster_index)),
+            )
+            .is_some()
+        })
+    }
+
+    // The command for clicking a position of the enemy's monster row, which attacks it, or attacks directly if it is empty.
+    fn attack_command(&self, enemy_index: usize) -> Option<DuelCommandEnum> {
+        let monster_index = self.selection.monster_index?;
+        self.checked(
+            self.duel()
+                .command_builder()
+                .field()
+                .and_then(|builder| builder.select_monster(monster_index))
+                .and_then(|builder| builder.attack(enemy_index)),
+        )
+    }
+
+    fn change_mode_command(&self) -> Option<DuelCommandEnum> {
+        let monster_index = self.selection.monster_index?;
+        self.checked(
+            self.duel()
+                .command_builder()
+                .field()
+                .and_then(|builder| builder.select_monster(monster_index))
+                .map(|builder| builder.change_mode()),
+        )
+    }
+
+    // Plays a face-up magic or ritual from the hand without placing it.
+    fn activate_command(&self) -> Option<DuelCommandEnum> {
+        let [hand_index] = self.selection.hand_indices[..] else {
+            return None;
+        };
+        self.checked(
+            self.duel()
+                .command_builder()
+                .hand()
+                .and_then(|builder| builder.select(hand_index))
+                .map(|builder| builder.facing(self.selection.face_direction))
+                .and_then(|builder| builder.play()),
+        )
+    }
+
+    fn end_turn_command(&self) -> Option<DuelCommandEnum> {
+        self.checked(
+            self.duel()
+                .command_builder()
+                .field()
+                .map(|builder| builder.end_turn()),
+        )
+    }
+
+    fn guardian_star_command(&self, choice: GuardianStarChoice) -> Option<DuelCommandEnum> {
+        self.checked(self.duel().command_builder().set_guardian_star(choice))
+    }
+}
+
+fn card_stats(card: &Card, duel: &Duel) -> Option<(i32, i32)> {
+    card.get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus)
+}
+
+// The border of a clickable card: yellow when it is selected, green when clicking it does something, grey otherwise.
+fn border(selected: bool, enabled: bool) -> String {
+    let color = if selected {
+        "yellow"
+    } else if enabled {
+        "green"
+    } else {
+        "grey"
+    };
+    format!("2px solid {}", color)
+}
+
 #[inline_props]
-fn HandComponent(cx: Scope, hand: Vec<Card>) -> Element {
-    let duel = use_shared_state::<Duel>(cx).unwrap();
+fn HandComponent(cx: Scope, player_enum: PlayerEnum) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let hidden = *player_enum == PlayerEnum::Player2;
+    let hand = game
+        .read()
+        .duel()
+        .get_player_by_enum(*player_enum)
+        .hand
+        .clone();
+    let selectable = !hidden && game.read().in_state(true, false);
 
-    let cards = hand.iter().map(|card| {
+    let cards = hand.iter().enumerate().map(|(hand_index, card)| {
+        let order = game
+            .read()
+            .selection
+            .hand_indices
+            .iter()
+            .position(|&index| index == hand_index);
+        let border = border(order.is_some(), selectable);
+        let name = match order {
+            // the order the selected cards are fused in
+            Some(order) => format!("[{}] {} ({})", order + 1, card.name, card.id),
+            None => format!("{} ({})", card.name, card.id),
+        };
+        let stats = card_stats(card, game.read().duel());
+        let content = if hidden {
+            rsx! { div { "?" } }
+        } else {
+            rsx! {
+                div {
+                    "{name}"
+                }
+                div {
+                    if let Some((attack, _)) = stats {
+                        format!("Attack: {}", attack)
+                    } else {
+                        "".to_string()
+                    }
+                }
+                div {
+                    if let Some((_, defense)) = stats {
+                        format!("Defense: {}", defense)
+                    } else {
+                        "".to_string()
+                    }
+                }
+            }
+        };
         rsx! { div {
-            // background_color: "green",
             justify_content: "center",
             align_items: "center",
             min_height: "100px",
             max_height: "100px",
             min_width: "220px",
             max_width: "220px",
-            border: "2px solid grey",
+            border: "{border}",
             margin: "2px",
-            div {
-                format!("{} ({})", card.name, card.id)
-            }
-            div {
-                if let fmsim::CardVariant::Monster{ .. } = card.variant {
-                    format!("Attack: {}", card.get_stats_with_terrain(duel.read().terrain_type).unwrap().0)
-                } else {
-                    "".to_string()
-                }
-            }
-            div {
-                if let fmsim::CardVariant::Monster{ .. } = card.variant {
-                    format!("Defense: {}", card.get_stats_with_terrain(duel.read().terrain_type).unwrap().1)
-                } else {
-                    "".to_string()
+            onclick: move |_| {
+                if selectable {
+                    let mut game = game.write();
+                    let hand_indices = &mut game.selection.hand_indices;
+                    match hand_indices.iter().position(|&index| index == hand_index) {
+                        Some(position) => { hand_indices.remove(position); }
+                        None => hand_indices.push(hand_index),
+                    }
                 }
-            }
+            },
+            content
         }}
     });
 
@@ -95,30 +416,48 @@ fn HandComponent(cx: Scope, hand: Vec<Card>) -> Element {
 }
 
 #[inline_props]
-fn MonsterRowComponent(cx: Scope, monster_row: Vec<Option<MonsterRowPosition>>) -> Element {
-    let duel = use_shared_state::<Duel>(cx).unwrap();
+fn MonsterRowComponent(cx: Scope, player_enum: PlayerEnum) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let hidden = *player_enum == PlayerEnum::Player2;
+    let monster_row = game
+        .read()
+        .duel()
+        .get_player_by_enum(*player_enum)
+        .monster_row
+        .clone();
 
     cx.render(rsx! { div {
         display: "flex",
         justify_content: "center",
         align_items: "center",
-        monster_row.iter().map(|monster| {
-            let mut border_color = "grey";
+        monster_row.iter().enumerate().map(|(index, monster)| {
+            let game_ref = game.read();
+            // clicking the human's monster either completes a command or selects the monster, and clicking the enemy's attacks it
+            let command = if hidden {
+                game_ref.attack_command(index)
+            } else {
+                game_ref.monster_command(index)
+            };
+            let can_select = !hidden && command.is_none() && game_ref.in_state(false, true) && game_ref.can_select_monster(index);
+            let selected = !hidden && game_ref.selection.monster_index == Some(index);
+            let border = border(selected, command.is_some() || can_select);
             let content = match monster {
+                Some(monster) if hidden && monster.face_direction == FaceDirection::Down => {
+                    rsx! { div { "Face-down monster" } }
+                }
                 Some(monster) => {
-                    border_color = if monster.disabled { "red" } else { "green" };
-                    border_color = if duel.read().get_player().sorl_effect_countdown.is_some() { "blue" } else { border_color };
-                    rsx! { 
+                    let (attack, defense) = card_stats(&monster.card, game_ref.duel()).unwrap();
+                    let sorl = game_ref.duel().get_player_by_enum(*player_enum).sorl_effect_countdown;
+                    rsx! {
                         div {
-                            border: "{border_color}",
                             div {
                                 format!("{} ({})", monster.card.name, monster.card.id)
                             }
                             div {
-                                format!("Attack: {:?}", monster.card.get_stats_with_terrain(duel.read().terrain_type).unwrap().0)
+                                format!("Attack: {:?}", attack)
                             }
                             div {
-                                format!("Defense: {:?}", monster.card.get_stats_with_terrain(duel.read().terrain_type).unwrap().1)
+                                format!("Defense: {:?}", defense)
                             }
                             div {
                                 format!("Star: {:?}", monster.get_selected_gs())
@@ -126,9 +465,20 @@ fn MonsterRowComponent(cx: Scope, monster_row: Vec<Option<MonsterRowPosition>>)
                             div {
                                 format!("Mode: {:?}", monster.card_mode)
                             }
+                            if monster.disabled {
+                                rsx! { div { "Used this turn" } }
+                            }
+                            if let Some(countdown) = sorl {
+                                rsx! { div { format!("Swords of Revealing Light: {}", countdown) } }
+                            }
                         }
-                    } 
+                    }
                 },
+                None if hidden && command.is_some() => {
+                    rsx! { div {
+                        "Attack directly"
+                    }}
+                }
                 None => {
                     rsx! { div {
                         "empty"
@@ -136,18 +486,25 @@ fn MonsterRowComponent(cx: Scope, monster_row: Vec<Option<MonsterRowPosition>>)
                 }
             };
 
-            let border_color = format!("{}{}", "2px solid ", border_color);
-
             rsx! { div {
                 justify_content: "center",
                 align_items: "center",
-                border: "{border_color}",
+                border: "{border}",
                 min_height: "100px",
                 max_height: "100px",
-                max_height: "100px",
                 min_width: "200px",
                 max_width: "200px",
                 margin: "2px",
+                onclick: move |_| {
+                    if let Some(command) = command.clone() {
+                        game.write().play(command);
+                    } else if can_select {
+                        let mut game = game.write();
+                        let monster_index = &mut game.selection.monster_index;
+                        *monster_index = if *monster_index == Some(index) { None } else { Some(index) };
+                        game.selection.spell_index = None;
+                    }
+                },
                 content
             }}
         })
@@ -155,13 +512,31 @@ fn MonsterRowComponent(cx: Scope, monster_row: Vec<Option<MonsterRowPosition>>)
 }
 
 #[inline_props]
-fn SpellRowComponent(cx: Scope, spell_row: Vec<Option<SpellRowPosition>>) -> Element {
+fn SpellRowComponent(cx: Scope, player_enum: PlayerEnum) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let hidden = *player_enum == PlayerEnum::Player2;
+    let spell_row = game
+        .read()
+        .duel()
+        .get_player_by_enum(*player_enum)
+        .spell_row
+        .clone();
+
     cx.render(rsx! { div {
         display: "flex",
         justify_content: "center",
         align_items: "center",
-        spell_row.iter().map(|spell| {
+        spell_row.iter().enumerate().map(|(index, spell)| {
+            let game_ref = game.read();
+            // clicking the human's spell either completes a command or selects the equip
+            let command = (!hidden).then(|| game_ref.spell_command(index)).flatten();
+            let can_select = !hidden && command.is_none() && game_ref.in_state(false, true) && game_ref.can_select_equip(index);
+            let selected = !hidden && game_ref.selection.spell_index == Some(index);
+            let border = border(selected, command.is_some() || can_select);
             let content = match spell {
+                Some(_) if hidden => rsx! { div {
+                    "Set card"
+                }},
                 Some(spell) => rsx! { div {
                     div {
                         format!("{} ({})", spell.card.name, spell.card.id)
@@ -181,17 +556,179 @@ fn SpellRowComponent(cx: Scope, spell_row: Vec<Option<SpellRowPosition>>) -> Ele
                 max_height: "100px",
                 min_width: "200px",
                 max_width: "200px",
-                border: "2px solid grey",
+                border: "{border}",
                 margin: "2px",
+                onclick: move |_| {
+                    if let Some(command) = command.clone() {
+                        game.write().play(command);
+                    } else if can_select {
+                        let mut game = game.write();
+                        let spell_index = &mut game.selection.spell_index;
+                        *spell_index = if *spell_index == Some(index) { None } else { Some(index) };
+                        game.selection.monster_index = None;
+                    }
+                },
                 content
             }}
         })
     }})
 }
 
+// The steps of fusing the selected hand cards in the order they were selected, before they are played.
+// The monster slot they are played on is only picked afterwards, so a monster already there is not part of the preview.
+fn FusionPreviewComponent(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game = game.read();
+    let duel = game.duel();
+    if game.selection.hand_indices.len() < 2 || !game.in_state(true, false) {
+        return None;
+    }
+
+    let cards = game
+        .selection
+        .hand_indices
+        .iter()
+        .map(|&index| duel.get_player().hand[index])
+        .collect::<Vec<_>>();
+    let glitch_fusions = duel.ruleset.glitch_fusions;
+    let steps = combine_cards_with_rules(cards, glitch_fusions);
+    let result = steps.last().unwrap().2;
+    let result_stats = match card_stats(&result, duel) {
+        Some((attack, defense)) => format!("Attack: {} Defense: {}", attack, defense),
+        None => "".to_string(),
+    };
+
+    cx.render(rsx! { div {
+        margin: "2px",
+        steps.iter().map(|(card1, card2, combined_card)| {
+            rsx! { div {
+                format!(
+                    "{} + {} = {} ({:?})",
+                    card1.name,
+                    card2.name,
+                    combined_card.name,
+                    combination_kind(card1, card2, glitch_fusions)
+                )
+            }}
+        })
+        div {
+            border: "2px solid yellow",
+            format!("Result: {} ({}) {}", result.name, result.id, result_stats)
+        }
+    }})
+}
+
+// The actions that are not done by clicking a card. Actions that are not valid right now are disabled.
+fn ActionsComponent(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game_ref = game.read();
+    let in_hand = game_ref.in_state(true, false);
+    let face_direction = game_ref.selection.face_direction;
+    let activate = game_ref.activate_command();
+    let change_mode = game_ref.change_mode_command();
+    let end_turn = game_ref.end_turn_command();
+    let (can_activate, can_change_mode, can_end_turn) = (
+        activate.is_some(),
+        change_mode.is_some(),
+        end_turn.is_some(),
+    );
+    let guardian_stars = match &game_ref.duel().state {
+        DuelStateEnum::SetGuardianStarState(state) => match state.monster_row_position.card.variant
+        {
+            CardVariant::Monster {
+                guardian_star_a,
+                guardian_star_b,
+                ..
+            } => vec![
+                (
+                    format!("{:?}", guardian_star_a),
+                    game_ref.guardian_star_command(GuardianStarChoice::A),
+                ),
+                (
+                    format!("{:?}", guardian_star_b),
+                    game_ref.guardian_star_command(GuardianStarChoice::B),
+                ),
+            ],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    let status = match &game_ref.duel().state {
+        DuelStateEnum::EndState(end) => format!("{:?} wins by {:?}.", end.winner, end.win_condition),
+        DuelStateEnum::HandState(_) => "Select a card to play, or several cards in the order to fuse them, then where to play them.".to_string(),
+        DuelStateEnum::FieldState(_) => "Select a monster to attack with or change the mode of, or an equip to play.".to_string(),
+        DuelStateEnum::SetGuardianStarState(_) => "Select a guardian star.".to_string(),
+    };
+    drop(game_ref);
+
+    cx.render(rsx! {
+        div {
+            div { "{status}" }
+            div {
+                display: "flex",
+                button {
+                    disabled: !in_hand,
+                    onclick: move |_| {
+                        let mut game = game.write();
+                        game.selection.face_direction = match face_direction {
+                            FaceDirection::Up => FaceDirection::Down,
+                            FaceDirection::Down => FaceDirection::Up,
+                        };
+                    },
+                    format!("Face {:?}", face_direction)
+                }
+                button {
+                    disabled: !can_activate,
+                    onclick: move |_| {
+                        if let Some(command) = activate.clone() {
+                            game.write().play(command);
+                        }
+                    },
+                    "Activate"
+                }
+                button {
+                    disabled: !can_change_mode,
+                    onclick: move |_| {
+                        if let Some(command) = change_mode.clone() {
+                            game.write().play(command);
+                        }
+                    },
+                    "Change mode"
+                }
+                guardian_stars.into_iter().map(|(label, command)| {
+                    let enabled = command.is_some();
+                    rsx! { button {
+                        disabled: !enabled,
+                        onclick: move |_| {
+                            if let Some(command) = command.clone() {
+                                game.write().play(command);
+                            }
+                        },
+                        "{label}"
+                    }}
+                })
+                button {
+                    onclick: move |_| game.write().selection = Selection::default(),
+                    "Clear selection"
+                }
+                button {
+                    disabled: !can_end_turn,
+                    onclick: move |_| {
+                        if let Some(command) = end_turn.clone() {
+                            game.write().play(command);
+                        }
+                    },
+                    "End turn"
+                }
+            }
+        }
+    })
+}
+
 fn DuelComponent(cx: Scope) -> Element {
-    let duel = use_shared_state::<Duel>(cx).unwrap();
-    let commands = use_shared_state::<Vec<DuelCommandEnum>>(cx).unwrap();
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game = game.read();
+    let duel = game.duel();
     cx.render(rsx! {
         div {
             div {
@@ -199,93 +736,153 @@ fn DuelComponent(cx: Scope) -> Element {
                 div {
                     style: "flex: 1;",
                     div {
-                        format!("Terrain: {:?}", duel.read().terrain_type)
+                        format!("Terrain: {:?}", duel.terrain_type)
                     }
                     div {
-                        format!("Turn: {}", duel.read().turn)
+                        format!("Turn: {}", duel.turn)
                     }
                 }
                 div {
                     style: "text-align: center; flex: 1;",
-                    format!("Current player: {:?}", duel.read().get_player_enum())
+                    format!("Current player: {:?}", duel.get_player_enum())
                 }
                 div {
                     style: "text-align: right; flex: 1;",
                     div {
-                        format!("Player 2 Life Points: {}", duel.read().get_player_by_enum(PlayerEnum::Player2).life_points)
+                        format!("Player 2 Life Points: {}", duel.get_player_by_enum(PlayerEnum::Player2).life_points)
                     }
                     div {
-                        format!("Player 1 Remaining Cards: {}", duel.read().get_player_by_enum(PlayerEnum::Player1).deck.len())
+                        format!("Player 2 Remaining Cards: {}", duel.get_player_by_enum(PlayerEnum::Player2).deck.len())
                     }
                     div {
-                        format!("Player 1 Life Points: {}", duel.read().get_player_by_enum(PlayerEnum::Player1).life_points)
+                        format!("Player 1 Life Points: {}", duel.get_player_by_enum(PlayerEnum::Player1).life_points)
                     }
                     div {
-                        format!("Player 2 Remaining Cards: {}", duel.read().get_player_by_enum(PlayerEnum::Player2).deck.len())
+                        format!("Player 1 Remaining Cards: {}", duel.get_player_by_enum(PlayerEnum::Player1).deck.len())
                     }
                 }
             }
             div { style: "height: 5px;" }
             HandComponent {
-                hand: duel.read().get_enemy().hand.clone()
+                player_enum: PlayerEnum::Player2
             }
             div { style: "height: 5px;" }
             SpellRowComponent {
-                spell_row: duel.read().get_enemy().spell_row.clone()
+                player_enum: PlayerEnum::Player2
             }
             MonsterRowComponent {
-                monster_row: duel.read().get_enemy().monster_row.clone()
+                player_enum: PlayerEnum::Player2
             }
             div { style: "height: 20px;" }
             MonsterRowComponent {
-                monster_row: duel.read().get_player().monster_row.clone()
+                player_enum: PlayerEnum::Player1
             }
             SpellRowComponent {
-                spell_row: duel.read().get_player().spell_row.clone()
+                player_enum: PlayerEnum::Player1
             }
             div { style: "height: 5px;" }
             HandComponent {
-                hand: duel.read().get_player().hand.clone()
-            }
-            div {
-                // print the next command
-                format!("Next command: {:?}", commands.read().last().unwrap())
-            }
-            button {
-                onclick: move |_| {
-                    if !matches!(duel.read().state, DuelStateEnum::EndState(_)) {
-                        let duel_state = duel.read().state.clone();
-                        let strategy = RandomCommandStrategy;
-                        if !matches!(duel_state, DuelStateEnum::EndState(_)) {
-                            let command = commands.read().last().unwrap().clone();
-                            command.execute(&mut *duel.write()).unwrap();
-                        }
-
-                        let duel_state = duel.read().state.clone();
-                        if !matches!(duel_state, DuelStateEnum::EndState(_)) {
-                            let command = strategy.get_command(&duel.read());
-                            let mut commands_ref = commands.write();
-                            commands_ref.push(command.clone());
-                        }
-                    }
-                }
+                player_enum: PlayerEnum::Player1
             }
+            FusionPreviewComponent {}
+            ActionsComponent {}
         }
     })
 }
 
-fn CommandsComponent(cx: Scope) -> Element {
-    let commands = use_shared_state::<Vec<DuelCommandEnum>>(cx).unwrap();
+// Every ply of the duel, which can be stepped through or jumped to, with what changed in the current one.
+fn TimelineComponent(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game_ref = game.read();
+    let timeline = &game_ref.timeline;
+    let (ply, len) = (timeline.ply(), timeline.len());
+    // the human can only play on from a ply where it is their turn
+    let can_branch =
+        !game_ref.is_live() || game_ref.duel().get_player_enum() == PlayerEnum::Player2;
+    let events = timeline
+        .events()
+        .iter()
+        .map(|event| event.to_string())
+        .collect::<Vec<_>>();
+    let commands = timeline
+        .replay()
+        .commands_list
+        .iter()
+        .map(|command| format!("{:?}", command))
+        .collect::<Vec<_>>();
+    drop(game_ref);
+
+    let entry_style = |entry_ply: usize| {
+        if entry_ply == ply {
+            "border: 1px solid yellow; cursor: pointer;"
+        } else {
+            "border: 1px solid transparent; cursor: pointer;"
+        }
+    };
+
+    let start_style = entry_style(0);
+
     cx.render(rsx! {
         div {
             div {
+                display: "flex",
+                button {
+                    disabled: ply == 0,
+                    onclick: move |_| game.write().seek(0),
+                    "|<"
+                }
+                button {
+                    disabled: ply == 0,
+                    onclick: move |_| game.write().seek(ply.saturating_sub(1)),
+                    "<"
+                }
+                button {
+                    disabled: ply == len,
+                    onclick: move |_| game.write().seek(ply + 1),
+                    ">"
+                }
+                button {
+                    disabled: ply == len,
+                    onclick: move |_| game.write().seek(len),
+                    ">|"
+                }
+                button {
+                    disabled: !can_branch,
+                    onclick: move |_| game.write().branch(),
+                    "Play from here"
+                }
+            }
+            input {
+                r#type: "range",
+                min: "0",
+                max: "{len}",
+                value: "{ply}",
+                oninput: move |event| {
+                    if let Ok(ply) = event.value.parse() {
+                        game.write().seek(ply);
+                    }
+                }
+            }
+            div {
+                format!("Ply {} of {}", ply, len)
+            }
+            div {
+                margin: "5px",
+                events.iter().map(|event| rsx! { div { "{event}" } })
+            }
+            div {
+                style: "{start_style}",
+                onclick: move |_| game.write().seek(0),
                 // start state
                 "start"
             }
-            commands.read().iter().take(commands.read().len() - 1).map(|command| {
+            commands.iter().enumerate().map(|(index, command)| {
+                let style = entry_style(index + 1);
                 rsx! { div {
+                    style: "{style}",
+                    onclick: move |_| game.write().seek(index + 1),
                     div {
-                        format!("{:?}", command)
+                        "{command}"
                     }
                 }}
             })
@@ -294,17 +891,9 @@ fn CommandsComponent(cx: Scope) -> Element {
 }
 
 fn App(cx: Scope) -> Element {
-    let duel = fmsim::Duel::random();
-    let mut commands: Vec<DuelCommandEnum> = Vec::new();
-    // generate the first command
-    let strategy = RandomCommandStrategy;
-    let command = strategy.get_command(&duel);
-    commands.push(command.clone());
-    
-    use_shared_state_provider(cx, || duel);
-    use_shared_state_provider(cx, || commands);
-
-    cx.render(rsx! { 
+    use_shared_state_provider(cx, Game::new);
+
+    cx.render(rsx! {
         div {
             display: "flex",
             justify_content: "center",
@@ -321,7 +910,7 @@ fn App(cx: Scope) -> Element {
                 width: "50px", // adjust this value as needed
             }
             div {
-                CommandsComponent {}
+                TimelineComponent {}
             }
         }
     })
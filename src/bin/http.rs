@@ -0,0 +1,24 @@
+use std::sync::Arc;
+
+use fmsim::api::Api;
+use fmsim::http::HttpServer;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+fn main() {
+    // `http [address]` serves the API described in src/api.rs. Needs the http feature.
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+    let server = match HttpServer::bind(&address, Arc::new(Api::new())) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    if let Some(address) = server.address() {
+        eprintln!("Listening on http://{}.", address);
+    }
+    server.run();
+}
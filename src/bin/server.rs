@@ -0,0 +1,74 @@
+use std::net::TcpListener;
+
+use fmsim::duel::command_strategy::{strategy_from_name, Budget};
+use fmsim::protocol::{host_duel_or_abort, Connection, HostConfig, ProtocolError, Seat};
+use fmsim::Duel;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7777";
+
+fn main() {
+    // `server stdio [opponent]` plays one duel against a bot on stdin/stdout.
+    // `server tcp [address] [opponent]` plays a duel against every bot that connects, one at a time.
+    // The opponent is random, mage or search, or remote to have the next bot that connects play player 2.
+    // With --perfect-information, bots can see the enemy's hand and face-down cards.
+    // The protocol is described in src/protocol.rs. Since stdout may be the protocol, results are printed to stderr.
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let perfect_information = args.iter().any(|arg| arg == "--perfect-information");
+    args.retain(|arg| arg != "--perfect-information");
+    let config = HostConfig {
+        perfect_information,
+        budget: Budget::time(std::time::Duration::from_secs(1)),
+    };
+
+    let result = match args.first().map(String::as_str) {
+        Some("tcp") => serve_tcp(
+            args.get(1).map_or(DEFAULT_ADDRESS, String::as_str),
+            args.get(2).map_or("random", String::as_str),
+            &config,
+        ),
+        _ => {
+            let opponent = args.get(1).map_or("random", String::as_str);
+            let seats = [Seat::Remote(Connection::stdio()), local_seat(opponent)];
+            play(seats, &config)
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn local_seat(opponent: &str) -> Seat {
+    let seed = rand::random();
+    let strategy = strategy_from_name(opponent, seed)
+        .unwrap_or_else(|| strategy_from_name("random", seed).unwrap());
+    Seat::Local(strategy)
+}
+
+fn play(mut seats: [Seat; 2], config: &HostConfig) -> Result<(), ProtocolError> {
+    let end = host_duel_or_abort(Duel::random(), &mut seats, config)?;
+    eprintln!("{:?} won by {:?}.", end.winner, end.win_condition);
+    Ok(())
+}
+
+fn serve_tcp(address: &str, opponent: &str, config: &HostConfig) -> Result<(), ProtocolError> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("Listening on {}.", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept()?;
+        eprintln!("{} connected as player 1.", peer);
+        let player1 = Seat::Remote(Connection::tcp(stream)?);
+        let player2 = if opponent == "remote" {
+            let (stream, peer) = listener.accept()?;
+            eprintln!("{} connected as player 2.", peer);
+            Seat::Remote(Connection::tcp(stream)?)
+        } else {
+            local_seat(opponent)
+        };
+
+        // a bot that misbehaves, or a duel that panics, only ends its own duel
+        if let Err(error) = play([player1, player2], config) {
+            eprintln!("{}", error);
+        }
+    }
+}
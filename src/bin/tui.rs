@@ -0,0 +1,382 @@
+#![allow(non_snake_case)]
+
+use std::time::Duration;
+
+use dioxus::html::input_data::keyboard_types::Key;
+use dioxus::prelude::*;
+use fmsim::duel::command::DuelCommandEnum;
+use fmsim::duel::command_strategy::{strategy_from_name, Budget, BudgetedStrategy};
+use fmsim::duel::field::{FaceDirection, MonsterRowPosition, SpellRowPosition};
+use fmsim::duel::human::MenuStack;
+use fmsim::duel::player::Player;
+use fmsim::duel::state::DuelStateEnum;
+use fmsim::duel::PlayerEnum;
+use fmsim::{Card, Duel};
+
+// How many lines of the log are shown at once.
+const LOG_LINES: usize = 20;
+
+fn main() {
+    // `tui [random|mage|search]` plays as player 1 against the given strategy, random by default.
+    dioxus_tui::launch(App);
+}
+
+fn opponent_from_args() -> (String, Box<dyn BudgetedStrategy>) {
+    let seed = rand::random();
+    let name = std::env::args().nth(1).unwrap_or_default();
+    match strategy_from_name(&name, seed) {
+        Some(strategy) => (name, strategy),
+        None => (
+            "random".to_string(),
+            strategy_from_name("random", seed).unwrap(),
+        ),
+    }
+}
+
+// The human is always player 1, and the opponent plays its turns as soon as the human's turn ends.
+struct Game {
+    duel: Duel,
+    menus: MenuStack,
+    opponent_name: String,
+    opponent: Box<dyn BudgetedStrategy>,
+    budget: Budget,
+    log: Vec<String>,
+    // How many lines the log is scrolled up from the latest line.
+    scroll: usize,
+    error: Option<String>,
+    // The digits of an option number typed so far, for menus with 10 or more options.
+    typed: String,
+}
+
+impl Game {
+    fn new() -> Self {
+        let (opponent_name, opponent) = opponent_from_args();
+        let duel = Duel::random();
+        let mut game = Self {
+            menus: MenuStack::new(&duel),
+            duel,
+            opponent_name,
+            opponent,
+            budget: Budget::time(Duration::from_secs(1)),
+            log: vec!["The duel begins.".to_string()],
+            scroll: 0,
+            error: None,
+            typed: String::new(),
+        };
+        game.play_opponent();
+        game
+    }
+
+    fn is_over(&self) -> bool {
+        matches!(self.duel.state, DuelStateEnum::EndState(_))
+    }
+
+    fn execute(&mut self, command: &DuelCommandEnum) {
+        let player = self.duel.get_player_enum();
+        self.log.push(format!("{:?}: {:?}", player, command));
+        self.duel.execute(command).unwrap();
+
+        if let DuelStateEnum::EndState(end) = &self.duel.state {
+            self.log
+                .push(format!("{:?} wins by {:?}.", end.winner, end.win_condition));
+        } else if self.duel.get_player_enum() != player {
+            self.log.push(format!(
+                "Turn {}: {:?} draws.",
+                self.duel.turn,
+                self.duel.get_player_enum()
+            ));
+        }
+    }
+
+    fn play_opponent(&mut self) {
+        while !self.is_over() && self.duel.get_player_enum() == PlayerEnum::Player2 {
+            let decision = self.opponent.decide(&self.duel, self.budget);
+            self.execute(&decision.command);
+            if !self.is_over() {
+                self.opponent
+                    .on_enemy_command(&self.duel, &decision.command);
+            }
+        }
+        if self.is_over() {
+            self.opponent.on_duel_end(&self.duel);
+        }
+        self.menus = MenuStack::new(&self.duel);
+    }
+
+    // Follows an option of the current menu, or goes back with None.
+    fn choose(&mut self, index: Option<usize>) {
+        if self.is_over() {
+            return;
+        }
+        self.error = None;
+        let Some(index) = index else {
+            self.menus.back();
+            return;
+        };
+        match self.menus.choose(&self.duel, index) {
+            Some(Ok(command)) => {
+                self.execute(&command);
+                if !self.is_over() {
+                    self.opponent.on_enemy_command(&self.duel, &command);
+                }
+                self.scroll = 0;
+                self.play_opponent();
+            }
+            Some(Err(error)) => self.error = Some(error.to_string()),
+            None => {}
+        }
+    }
+
+    // An option is chosen as soon as its number can't be the start of a larger option number, otherwise on Enter.
+    fn type_digits(&mut self, digits: &str) {
+        self.typed.push_str(digits);
+        let options = self.menus.prompt(&self.duel).options.len();
+        match self.typed.parse::<usize>() {
+            Ok(choice) if choice == 0 || choice.saturating_mul(10) > options => self.choose_typed(),
+            Ok(_) => {}
+            Err(_) => self.typed.clear(),
+        }
+    }
+
+    fn choose_typed(&mut self) {
+        match std::mem::take(&mut self.typed).parse::<usize>() {
+            Ok(0) => self.choose(None),
+            Ok(choice) => self.choose(Some(choice - 1)),
+            Err(_) => {}
+        }
+    }
+
+    fn on_key(&mut self, key: Key) {
+        match key {
+            Key::Character(character) if character.chars().all(|c| c.is_ascii_digit()) => {
+                self.type_digits(&character)
+            }
+            Key::Enter => self.choose_typed(),
+            Key::Backspace if !self.typed.is_empty() => {
+                self.typed.pop();
+            }
+            Key::Escape | Key::Backspace => {
+                self.typed.clear();
+                self.choose(None)
+            }
+            Key::PageUp | Key::ArrowUp => {
+                self.scroll = (self.scroll + 1).min(self.log.len().saturating_sub(LOG_LINES))
+            }
+            Key::PageDown | Key::ArrowDown => self.scroll = self.scroll.saturating_sub(1),
+            _ => {}
+        }
+    }
+}
+
+fn card_label(card: &Card, duel: &Duel) -> String {
+    match card.get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus) {
+        Some((attack, defense)) => format!("{} {}/{}", card.name, attack, defense),
+        None => card.name.clone(),
+    }
+}
+
+fn monster_label(monster: &Option<MonsterRowPosition>, duel: &Duel, hidden: bool) -> String {
+    match monster {
+        Some(monster) if hidden && monster.face_direction == FaceDirection::Down => {
+            "Face-down monster".to_string()
+        }
+        Some(monster) => format!(
+            "{} {:?} {:?}{}",
+            card_label(&monster.card, duel),
+            monster.card_mode,
+            monster.get_selected_gs(),
+            if monster.disabled { " (used)" } else { "" }
+        ),
+        None => "empty".to_string(),
+    }
+}
+
+fn spell_label(spell: &Option<SpellRowPosition>, hidden: bool) -> String {
+    match spell {
+        Some(_) if hidden => "Set card".to_string(),
+        Some(spell) => spell.card.name.clone(),
+        None => "empty".to_string(),
+    }
+}
+
+#[inline_props]
+fn RowComponent(cx: Scope, labels: Vec<String>) -> Element {
+    cx.render(rsx! { div {
+        display: "flex",
+        flex_direction: "row",
+        labels.iter().map(|label| rsx! { div {
+            width: "20%",
+            height: "3px",
+            border_width: "1px",
+            border_style: "solid",
+            border_color: "grey",
+            "{label}"
+        }})
+    }})
+}
+
+// One side of the board. The cards of the opponent that the human could not see are hidden.
+#[inline_props]
+fn PlayerComponent(cx: Scope, player_enum: PlayerEnum) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game = game.read();
+    let duel = &game.duel;
+    let player: &Player = duel.get_player_by_enum(*player_enum);
+    let hidden = *player_enum == PlayerEnum::Player2;
+
+    let status = format!(
+        "{:?} ({}) | LP: {} | Deck: {}{}",
+        player_enum,
+        if hidden {
+            game.opponent_name.as_str()
+        } else {
+            "you"
+        },
+        player.life_points,
+        player.deck.len(),
+        match player.sorl_effect_countdown {
+            Some(countdown) => format!(" | Swords of Revealing Light: {} turns", countdown),
+            None => String::new(),
+        }
+    );
+    let hand = if hidden {
+        vec![format!("{} cards in hand", player.hand.len())]
+    } else {
+        player
+            .hand
+            .iter()
+            .enumerate()
+            .map(|(index, card)| format!("{}. {}", index + 1, card_label(card, duel)))
+            .collect()
+    };
+    let monsters = player
+        .monster_row
+        .iter()
+        .map(|monster| monster_label(monster, duel, hidden))
+        .collect::<Vec<_>>();
+    let spells = player
+        .spell_row
+        .iter()
+        .map(|spell| spell_label(spell, hidden))
+        .collect::<Vec<_>>();
+
+    // the rows closest to the middle of the screen are the monster rows
+    let rows = if hidden {
+        rsx! {
+            div { "{status}" }
+            RowComponent { labels: hand }
+            RowComponent { labels: spells }
+            RowComponent { labels: monsters }
+        }
+    } else {
+        rsx! {
+            RowComponent { labels: monsters }
+            RowComponent { labels: spells }
+            RowComponent { labels: hand }
+            div { "{status}" }
+        }
+    };
+
+    cx.render(rsx! { div {
+        display: "flex",
+        flex_direction: "column",
+        rows
+    }})
+}
+
+fn MenuComponent(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let (title, options, error, typed) = {
+        let game = game.read();
+        if let DuelStateEnum::EndState(end) = &game.duel.state {
+            (
+                format!("{:?} wins by {:?}.", end.winner, end.win_condition),
+                Vec::new(),
+                None,
+                String::new(),
+            )
+        } else {
+            let prompt = game.menus.prompt(&game.duel);
+            let options = prompt
+                .options
+                .iter()
+                .map(|option| option.label.clone())
+                .collect::<Vec<_>>();
+            (
+                prompt.title,
+                options,
+                game.error.clone(),
+                game.typed.clone(),
+            )
+        }
+    };
+
+    cx.render(rsx! { div {
+        display: "flex",
+        flex_direction: "column",
+        border_width: "1px",
+        border_style: "solid",
+        border_color: "white",
+        div { "{title}" }
+        options.iter().enumerate().map(|(index, label)| rsx! { div {
+            onclick: move |_| game.write().choose(Some(index)),
+            format!("{}. {}", index + 1, label)
+        }})
+        div {
+            onclick: move |_| game.write().choose(None),
+            "0. Back"
+        }
+        (!typed.is_empty()).then(|| rsx! { div { "> {typed} (Enter to choose)" } })
+        error.map(|error| rsx! { div { color: "red", "{error}" } })
+    }})
+}
+
+fn LogComponent(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game = game.read();
+    let end = game.log.len() - game.scroll.min(game.log.len());
+    let start = end.saturating_sub(LOG_LINES);
+
+    cx.render(rsx! { div {
+        display: "flex",
+        flex_direction: "column",
+        width: "35%",
+        border_width: "1px",
+        border_style: "solid",
+        border_color: "grey",
+        div { "Log (up/down to scroll)" }
+        game.log[start..end].iter().map(|line| rsx! { div { "{line}" } })
+    }})
+}
+
+fn App(cx: Scope) -> Element {
+    use_shared_state_provider(cx, Game::new);
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let header = {
+        let game = game.read();
+        format!(
+            "Terrain: {:?} | Turn: {} | Current player: {:?}",
+            game.duel.terrain_type,
+            game.duel.turn,
+            game.duel.get_player_enum()
+        )
+    };
+
+    cx.render(rsx! { div {
+        display: "flex",
+        flex_direction: "row",
+        width: "100%",
+        height: "100%",
+        onkeydown: move |event| game.write().on_key(event.key()),
+        div {
+            display: "flex",
+            flex_direction: "column",
+            width: "65%",
+            div { "{header}" }
+            PlayerComponent { player_enum: PlayerEnum::Player2 }
+            PlayerComponent { player_enum: PlayerEnum::Player1 }
+            MenuComponent {}
+        }
+        LogComponent {}
+    }})
+}
@@ -1,13 +1,15 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::Deref;
 
 use crate::{
     monster_terrain_relation, AdvantageRelation, GuardianStarType, MagicEffectEnum, MonsterType,
     TerrainType, TrapEffectEnum, CARDS,
 };
 
+// The data of a card in the card database (CARDS). The stats of a monster are its base stats.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-pub struct Card {
+pub struct CardData {
     pub id: usize,
     pub name: String,
     pub description: String,
@@ -48,12 +50,74 @@ pub enum CardVariant {
     Trap(TrapEffectEnum),
 }
 
+// A card in a duel: a handle into the card database, plus how much equips and magic changed its attack and defense.
+// Simulations copy duels constantly, so this is kept small. The rest of the card data is available through Deref, or with data().
+// Note that the stats in card.variant are the base stats; use get_stats_no_terrain() for the current stats.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
+#[serde(try_from = "CardRepr")]
+pub struct Card {
+    pub id: usize,
+    pub stat_delta: i32,
+}
+
+// Duels saved before cards were handles, e.g. old crash files, contain the full card data with the stats already changed.
+// Only the ID and the variant are needed to recover the stat delta. The fusions can't be read here anyway, since untagged enums only support string map keys.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CardRepr {
+    Full {
+        id: usize,
+        variant: CardVariant,
+    },
+    Handle {
+        id: usize,
+        #[serde(default)]
+        stat_delta: i32,
+    },
+}
+
+impl TryFrom<CardRepr> for Card {
+    type Error = String;
+
+    // Unknown IDs are rejected here, rather than panicking when the card is first used.
+    fn try_from(repr: CardRepr) -> Result<Self, String> {
+        let id = match &repr {
+            CardRepr::Full { id, .. } | CardRepr::Handle { id, .. } => *id,
+        };
+        if !(1..=CARDS.len()).contains(&id) {
+            return Err(format!("Unknown card ID {}.", id));
+        }
+        Ok(match repr {
+            CardRepr::Full { id, variant } => {
+                let card = card_from_id(id);
+                let stat_delta = match (variant, card.get_base_stats()) {
+                    (CardVariant::Monster { attack, .. }, Some((base_attack, _))) => {
+                        attack - base_attack
+                    }
+                    _ => 0,
+                };
+                Self { stat_delta, ..card }
+            }
+            CardRepr::Handle { id, stat_delta } => Self { id, stat_delta },
+        })
+    }
+}
+
+impl Deref for Card {
+    type Target = CardData;
+
+    fn deref(&self) -> &CardData {
+        self.data()
+    }
+}
+
 impl Card {
+    pub fn data(&self) -> &'static CardData {
+        &CARDS[self.id - 1]
+    }
+
     pub fn get_base_stats(&self) -> Option<(i32, i32)> {
-        // we need to get_card_from_id to get the base stats of the card
-        // the stats on our card may have been modified e.g. by equips
-        let base_card = card_from_id(self.id);
-        match &base_card.variant {
+        match &self.variant {
             CardVariant::Monster {
                 attack, defense, ..
             } => Some((*attack, *defense)),
@@ -62,29 +126,29 @@ impl Card {
     }
 
     pub fn get_stats_no_terrain(&self) -> Option<(i32, i32)> {
-        match &self.variant {
-            CardVariant::Monster {
-                attack, defense, ..
-            } => Some((*attack, *defense)),
-            _ => None,
-        }
+        self.get_base_stats()
+            .map(|(attack, defense)| (attack + self.stat_delta, defense + self.stat_delta))
     }
 
     pub fn get_stats_with_terrain(&self, terrain_type: TerrainType) -> Option<(i32, i32)> {
-        // use monster_terrain_relation to check if advantageous (+500), disadvantageous (-500), or neutral (no change)
+        self.get_stats_with_terrain_bonus(terrain_type, 500)
+    }
+
+    pub fn get_stats_with_terrain_bonus(
+        &self,
+        terrain_type: TerrainType,
+        terrain_bonus: i32,
+    ) -> Option<(i32, i32)> {
+        // use monster_terrain_relation to check if advantageous (+terrain_bonus), disadvantageous (-terrain_bonus), or neutral (no change)
         match &self.variant {
-            CardVariant::Monster {
-                attack,
-                defense,
-                monster_type,
-                ..
-            } => {
+            CardVariant::Monster { monster_type, .. } => {
                 let terrain_boost = match monster_terrain_relation(*monster_type, terrain_type) {
-                    AdvantageRelation::Advantaged => 500,
-                    AdvantageRelation::Disadvantaged => -500,
+                    AdvantageRelation::Advantaged => terrain_bonus,
+                    AdvantageRelation::Disadvantaged => -terrain_bonus,
                     AdvantageRelation::Neutral => 0,
                 };
-                Some((*attack + terrain_boost, *defense as i32 + terrain_boost))
+                let (attack, defense) = self.get_stats_no_terrain().unwrap();
+                Some((attack + terrain_boost, defense + terrain_boost))
             }
             _ => None,
         }
@@ -92,91 +156,58 @@ impl Card {
 
     pub fn reset_stats_to_base(&mut self) {
         // panic if not a Monster
-        // set the stats to the base stats
-        let (base_attack, base_defense) = self.get_base_stats().unwrap();
-        match &mut self.variant {
-            CardVariant::Monster {
-                attack, defense, ..
-            } => {
-                *attack = base_attack;
-                *defense = base_defense;
-            }
-            _ => panic!("Attempted to reset stats of a non-Monster card"),
-        }
+        assert!(
+            matches!(self.variant, CardVariant::Monster { .. }),
+            "Attempted to reset stats of a non-Monster card"
+        );
+        self.stat_delta = 0;
     }
 
     pub fn modify_stats(&mut self, delta: i32) {
-        // Get the current difference between the base stats and the current stats.
-        // We need to assert that the difference is the same in both attack and defense.
-        // Then, after the modification is done, we need to assert that the difference is still the same.
-
-        // get the base stats
-        let (base_attack, base_defense) = self.get_base_stats().unwrap();
-        // get the current stats
-        let (current_attack, current_defense) = self.get_stats_no_terrain().unwrap();
-        // assert that the difference is the same in both attack and defense
-        assert_eq!(base_attack - current_attack, base_defense - current_defense);
-        // also assert that the difference is a multiple of 500
-        assert_eq!((base_attack - current_attack).abs() % 500, 0);
-
+        // Equips and magic always change attack and defense by a multiple of 500.
         // panic if not a Monster
-        // modify attack and defense by delta
-        match &mut self.variant {
-            CardVariant::Monster {
-                attack, defense, ..
-            } => {
-                *attack = *attack + delta;
-                *defense = *defense + delta;
-
-                // assert that the difference is still the same
-                let (current_attack, current_defense) = self.get_stats_no_terrain().unwrap();
-                assert_eq!(base_attack - current_attack, base_defense - current_defense);
-                assert_eq!((base_attack - current_attack).abs() % 500, 0);
-            }
-            _ => panic!("Attempted to modify stats of a non-Monster card"),
-        }
+        assert!(
+            matches!(self.variant, CardVariant::Monster { .. }),
+            "Attempted to modify stats of a non-Monster card"
+        );
+        self.stat_delta += delta;
+        assert_eq!(self.stat_delta.abs() % 500, 0);
     }
 
     pub fn get_stats_no_terrain_base_delta(&self) -> Option<i32> {
-        match &self.variant {
-            CardVariant::Monster { .. } => {
-                // get the base stats
-                let (base_attack, base_defense) = self.get_base_stats().unwrap();
-                // get the current stats
-                let (current_attack, current_defense) = self.get_stats_no_terrain().unwrap();
-                // assert that the difference is the same in both attack and defense
-                assert_eq!(base_attack - current_attack, base_defense - current_defense);
-                // also assert that the difference is a multiple of 500
-                assert_eq!((base_attack - current_attack).abs() % 500, 0);
-
-                Some(base_attack - current_attack)
-            }
-            _ => None,
-        }
+        // the difference between the base stats and the current stats
+        self.get_base_stats().map(|_| -self.stat_delta)
     }
 }
 
 pub fn card_from_id(id: usize) -> Card {
-    CARDS.get(id - 1).unwrap().clone()
+    // panic on unknown IDs, rather than when the card is first used
+    assert!((1..=CARDS.len()).contains(&id), "Unknown card ID {}", id);
+    Card { id, stat_delta: 0 }
 }
 
 pub fn card_from_name(name: &str) -> Card {
-    CARDS.iter().find(|card| card.name == name).unwrap().clone()
+    card_from_id(CARDS.iter().find(|card| card.name == name).unwrap().id)
 }
 
-fn combine_cards_internal(cards: Vec<Card>) -> Vec<Card> {
+fn combine_cards_internal(cards: Vec<Card>, glitch_fusions: bool) -> Vec<Card> {
     let mut combined_cards = Vec::new();
-    let mut combined_card = cards[0].clone();
+    let mut combined_card = cards[0];
     // combined_cards.push(combined_card.clone());
     for card in cards.iter().skip(1) {
-        combined_card = combine(&combined_card, card);
-        combined_cards.push(combined_card.clone());
+        combined_card = combine_with_rules(&combined_card, card, glitch_fusions);
+        combined_cards.push(combined_card);
     }
     combined_cards
 }
 
 pub fn combine_cards(cards: Vec<Card>) -> Vec<(Card, Card, Card)> {
-    let combined_cards = combine_cards_internal(cards.clone());
+    combine_cards_with_rules(cards, true)
+}
+
+// Same as combine_cards, but glitch fusions (fusions that result in a non-monster card) can be turned off.
+pub fn combine_cards_with_rules(cards: Vec<Card>, glitch_fusions: bool) -> Vec<(Card, Card, Card)> {
+    let combined_cards = combine_cards_internal(cards.clone(), glitch_fusions);
     // We need to create a new iterator, using chain, to iterate over both cards and combined_cards,
     // If we represent cards as X and combined_cards as Y, we want the following: X1 X2 Y1 X3 Y2 X4 Y3
     // And so on until only a Y remains.
@@ -209,7 +240,7 @@ pub fn combine_cards(cards: Vec<Card>) -> Vec<(Card, Card, Card)> {
         let combined_card = iter.next();
         match (card1, card2, combined_card) {
             (Some(card1), Some(card2), Some(combined_card)) => {
-                combined_cards_io_pairs.push((card1.clone(), card2.clone(), combined_card.clone()))
+                combined_cards_io_pairs.push((*card1, *card2, *combined_card))
             }
             _ => break,
         }
@@ -218,7 +249,7 @@ pub fn combine_cards(cards: Vec<Card>) -> Vec<(Card, Card, Card)> {
     combined_cards_io_pairs
 }
 
-pub fn check_all_successful_equips(io_pairs: Vec<(Card, Card, Card)>) -> bool {
+pub fn check_all_successful_equips(io_pairs: &[(Card, Card, Card)]) -> bool {
     // check if all the combined cards are the same as the second card in the io_pair
     // if so, return true
     // otherwise, return false
@@ -240,7 +271,7 @@ pub fn check_all_successful_equips(io_pairs: Vec<(Card, Card, Card)>) -> bool {
     })
 }
 
-pub fn get_amount_of_equip_boosts(io_pairs: Vec<(Card, Card, Card)>) -> u32 {
+pub fn get_amount_of_equip_boosts(io_pairs: &[(Card, Card, Card)]) -> u32 {
     // for each io_pair, we need to check if equip(card1, card2) is Some
     // if so, that means that out of card1 and card2, one of them is a monster and the other is an equip
     // we need to find which one is the monster, and then check the difference between its stats and the combined card's stats
@@ -267,32 +298,59 @@ pub fn get_amount_of_equip_boosts(io_pairs: Vec<(Card, Card, Card)>) -> u32 {
 }
 
 pub fn combine(card1: &Card, card2: &Card) -> Card {
+    combine_with_rules(card1, card2, true)
+}
+
+pub fn combine_with_rules(card1: &Card, card2: &Card, glitch_fusions: bool) -> Card {
     // First we attempt to fuse the cards. If this fails, we then attempt to equip.
     // If this fails again, and both cards are monsters, we return the second card.
     // If one of the cards is a monster but the other is not, we return whichever is the monster.
     // In any other case, we return the second card.
     use CardVariant::*;
 
-    fuse(card1, card2)
+    fuse_with_rules(card1, card2, glitch_fusions)
         .or_else(|| equip(card1, card2))
         .unwrap_or_else(|| match (&card1.variant, &card2.variant) {
-            (Monster { .. }, Monster { .. }) => card2.clone(),
-            (Monster { .. }, _) => card1.clone(),
-            (_, Monster { .. }) => card2.clone(),
-            (_, _) => card2.clone(),
+            (Monster { .. }, Monster { .. }) => *card2,
+            (Monster { .. }, _) => *card1,
+            (_, Monster { .. }) => *card2,
+            (_, _) => *card2,
         })
 }
 
-pub fn fuse(card1: &Card, card2: &Card) -> Option<Card> {
-    if card1.fusions.contains_key(&card2.id) {
-        Some(card_from_id(card1.fusions[&card2.id]))
-    } else if card2.fusions.contains_key(&card1.id) {
-        Some(card_from_id(card2.fusions[&card1.id]))
+// How combine_with_rules combined two cards.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CombinationKind {
+    Fusion,
+    Equip,
+    // The cards neither fuse nor equip, so one of them replaces the other.
+    Replacement,
+}
+
+pub fn combination_kind(card1: &Card, card2: &Card, glitch_fusions: bool) -> CombinationKind {
+    if fuse_with_rules(card1, card2, glitch_fusions).is_some() {
+        CombinationKind::Fusion
+    } else if equip(card1, card2).is_some() {
+        CombinationKind::Equip
     } else {
-        None
+        CombinationKind::Replacement
     }
 }
 
+pub fn fuse(card1: &Card, card2: &Card) -> Option<Card> {
+    card1
+        .fusions
+        .get(&card2.id)
+        .or_else(|| card2.fusions.get(&card1.id))
+        .map(|&id| card_from_id(id))
+}
+
+// Same as fuse, but when glitch fusions are turned off, a fusion that results in a non-monster card fails instead.
+pub fn fuse_with_rules(card1: &Card, card2: &Card, glitch_fusions: bool) -> Option<Card> {
+    fuse(card1, card2)
+        .filter(|card| glitch_fusions || matches!(card.variant, CardVariant::Monster { .. }))
+}
+
 pub fn equip(card1: &Card, card2: &Card) -> Option<Card> {
     let (equip_card, monster_card) = match (&card1.variant, &card2.variant) {
         (CardVariant::Equip { .. }, CardVariant::Monster { .. }) => (card1, card2),
@@ -300,22 +358,20 @@ pub fn equip(card1: &Card, card2: &Card) -> Option<Card> {
         (_, _) => return None,
     };
 
-    let mut monster_clone = monster_card.clone();
+    let mut monster = *monster_card;
     if let CardVariant::Equip { equips } = &equip_card.variant {
-        if equips.contains(&monster_clone.id) {
-            if let CardVariant::Monster { .. } = &mut monster_clone.variant {
-                let boost_amount = if equip_card.name == "Megamorph" {
-                    1000
-                } else {
-                    500
-                };
-                monster_clone.modify_stats(boost_amount);
-            }
+        if equips.contains(&monster.id) {
+            let boost_amount = if equip_card.name == "Megamorph" {
+                1000
+            } else {
+                500
+            };
+            monster.modify_stats(boost_amount);
         } else {
             return None;
         }
     }
-    Some(monster_clone)
+    Some(monster)
 }
 
 #[cfg(test)]
@@ -330,17 +386,7 @@ mod tests {
         let pugm = card_from_name("Perfectly Ultimate Great Moth");
         let sorl = card_from_name("Swords of Revealing Light");
         let mm = card_from_name("Megamorph");
-        let cards_to_combine = vec![
-            td.clone(),
-            td.clone(),
-            mm.clone(),
-            sorl.clone(),
-            pugm.clone(),
-            dt.clone(),
-            mm.clone(),
-            thtd.clone(),
-            dt.clone(),
-        ];
+        let cards_to_combine = vec![td, td, mm, sorl, pugm, dt, mm, thtd, dt];
         let io_pairs = combine_cards(cards_to_combine.clone());
         // dbg print combined_card tuples, but only print the names
         for (card1, card2, combined_card) in &io_pairs {
@@ -349,12 +395,73 @@ mod tests {
                 card1.name, card2.name, combined_card.name
             );
         }
-        let combined_cards = combine_cards_internal(cards_to_combine);
+        let combined_cards = combine_cards_internal(cards_to_combine, true);
         for (i, combined_card) in combined_cards.iter().enumerate() {
             println!("Combined Card {}: {}", i, combined_card.name);
         }
 
         // assert the amoiunt of equip boosts is 2500
-        assert_eq!(get_amount_of_equip_boosts(io_pairs), 2500);
+        assert_eq!(get_amount_of_equip_boosts(&io_pairs), 2500);
+    }
+
+    #[test]
+    fn test_deserialize_full_card_data() {
+        // cards used to be saved with all of their data, and the stats already boosted
+        let mut data = card_from_name("Thunder Dragon").data().clone();
+        if let CardVariant::Monster {
+            attack, defense, ..
+        } = &mut data.variant
+        {
+            *attack += 1000;
+            *defense += 1000;
+        }
+        let card: Card = serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+        dbg!(&card);
+        assert_eq!(card.stat_delta, 1000);
+        assert_eq!(card.get_stats_no_terrain(), Some((2600, 2500)));
+
+        // the compact form round trips
+        let json = serde_json::to_string(&card).unwrap();
+        assert_eq!(serde_json::from_str::<Card>(&json).unwrap(), card);
+    }
+
+    #[test]
+    fn test_deserialize_unknown_card() {
+        for json in [
+            r#"{"id": 0}"#.to_string(),
+            format!(r#"{{"id": {}, "stat_delta": 0}}"#, CARDS.len() + 1),
+        ] {
+            let error = serde_json::from_str::<Card>(&json).unwrap_err();
+            dbg!(&error);
+            assert!(error.to_string().contains("Unknown card ID"));
+        }
+
+        // the full card data of an unknown card is rejected too
+        let mut data = card_from_name("Thunder Dragon").data().clone();
+        data.id = CARDS.len() + 1;
+        assert!(serde_json::from_str::<Card>(&serde_json::to_string(&data).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_combination_kind() {
+        let td = card_from_name("Thunder Dragon");
+        let thtd = card_from_name("Twin-headed Thunder Dragon");
+        let mm = card_from_name("Megamorph");
+        let sorl = card_from_name("Swords of Revealing Light");
+        let io_pairs = combine_cards(vec![td, td, mm, sorl]);
+        let kinds = io_pairs
+            .iter()
+            .map(|(card1, card2, _)| combination_kind(card1, card2, true))
+            .collect::<Vec<_>>();
+        dbg!(&kinds);
+        assert_eq!(
+            kinds,
+            vec![
+                CombinationKind::Fusion,
+                CombinationKind::Equip,
+                CombinationKind::Replacement
+            ]
+        );
+        assert_eq!(io_pairs[0].2, thtd);
     }
 }
@@ -1,6 +1,12 @@
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::{card_from_id, duel::deck::DECK_SIZE, Card, DUELISTS};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Duelist {
     pub id: u32,
@@ -24,3 +30,25 @@ pub struct Duelist {
     pub bcd_pool: HashMap<u32, u32>,
     pub sa_tec_pool: HashMap<u32, u32>,
 }
+
+impl Duelist {
+    // Draws a deck the way the original game does: each of the 40 cards is picked independently, weighted by the deck pool.
+    // The pool weights of each duelist add up to 2048.
+    pub fn random_deck<R: Rng>(&self, rng: &mut R) -> Vec<Card> {
+        let mut pool = self.deck_pool.iter().collect::<Vec<_>>();
+        // HashMap iteration order is random, so sort the pool to keep decks reproducible for a seeded rng.
+        pool.sort();
+        let weights = WeightedIndex::new(pool.iter().map(|(_, &weight)| weight))
+            .expect("Duelist deck pool has no cards");
+        (0..DECK_SIZE)
+            .map(|_| card_from_id(*pool[weights.sample(rng)].0 as usize))
+            .collect()
+    }
+}
+
+pub fn duelist_from_name(name: &str) -> &'static Duelist {
+    DUELISTS
+        .iter()
+        .find(|duelist| duelist.name == name)
+        .unwrap()
+}
@@ -3,7 +3,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::AdvantageRelation;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, FromPrimitive, ToPrimitive, Copy, Clone)]
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Eq, Hash, FromPrimitive, ToPrimitive, Copy, Clone,
+)]
 pub enum GuardianStarType {
     Mars = 0,
     Jupiter = 1,
@@ -20,7 +20,7 @@ pub enum AdvantageRelation {
     Disadvantaged,
 }
 
-pub static CARDS: LazyLock<Vec<Card>> = LazyLock::new(|| {
+pub static CARDS: LazyLock<Vec<CardData>> = LazyLock::new(|| {
     let card_data = include_bytes!("../../data/cards.json");
     serde_json::from_slice(card_data).expect("Error while reading cards")
 });
@@ -1,7 +1,9 @@
 use num_derive::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, FromPrimitive, ToPrimitive, Copy, Clone)]
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Eq, Hash, FromPrimitive, ToPrimitive, Copy, Clone,
+)]
 pub enum MonsterType {
     Dragon = 0,
     Spellcaster = 1,
@@ -1,4 +1,4 @@
-use crate::{Duel, MonsterType, TerrainType};
+use crate::{duel::graveyard::DiscardReason, Duel, MonsterType, TerrainType};
 use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
 
@@ -39,16 +39,16 @@ pub struct MonsterDestroyerEffect {
 
 impl MagicEffect for MonsterDestroyerEffect {
     fn execute_effect(&self, duel: &mut Duel) {
-        // set all to None
-        for monster_row_pos in duel.get_enemy_mut().monster_row.iter_mut() {
-            if let Some(monster) = monster_row_pos {
-                if let crate::CardVariant::Monster { monster_type, .. } = &monster.card.variant {
-                    if self.monster_type == *monster_type {
-                        *monster_row_pos = None;
-                    }
+        // send all enemy monsters of the type to the graveyard
+        duel.get_enemy_mut().discard_monsters_where(
+            |monster| match &monster.card.variant {
+                crate::CardVariant::Monster { monster_type, .. } => {
+                    self.monster_type == *monster_type
                 }
-            }
-        }
+                _ => false,
+            },
+            DiscardReason::MagicEffect,
+        );
     }
 }
 
@@ -57,21 +57,13 @@ pub struct DarkHoleEffect;
 impl MagicEffect for DarkHoleEffect {
     fn execute_effect(&self, duel: &mut Duel) {
         duel.get_enemy_mut()
-            .monster_row
-            .iter_mut()
-            .for_each(|monster| *monster = None);
+            .discard_all_monsters(DiscardReason::MagicEffect);
         duel.get_enemy_mut()
-            .spell_row
-            .iter_mut()
-            .for_each(|spell| *spell = None);
+            .discard_all_spells(DiscardReason::MagicEffect);
         duel.get_player_mut()
-            .monster_row
-            .iter_mut()
-            .for_each(|monster| *monster = None);
+            .discard_all_monsters(DiscardReason::MagicEffect);
         duel.get_player_mut()
-            .spell_row
-            .iter_mut()
-            .for_each(|spell| *spell = None);
+            .discard_all_spells(DiscardReason::MagicEffect);
     }
 }
 
@@ -80,9 +72,7 @@ pub struct RaigekiEffect;
 impl MagicEffect for RaigekiEffect {
     fn execute_effect(&self, duel: &mut Duel) {
         duel.get_enemy_mut()
-            .monster_row
-            .iter_mut()
-            .for_each(|monster| *monster = None);
+            .discard_all_monsters(DiscardReason::MagicEffect);
     }
 }
 
@@ -91,9 +81,7 @@ pub struct HarpiesFeatherDusterEffect;
 impl MagicEffect for HarpiesFeatherDusterEffect {
     fn execute_effect(&self, duel: &mut Duel) {
         duel.get_enemy_mut()
-            .spell_row
-            .iter_mut()
-            .for_each(|spell| *spell = None);
+            .discard_all_spells(DiscardReason::MagicEffect);
     }
 }
 
@@ -102,17 +90,19 @@ pub struct CrushCardEffect;
 impl MagicEffect for CrushCardEffect {
     fn execute_effect(&self, duel: &mut Duel) {
         let terrain_type = duel.terrain_type;
-        // Set all enemy monster cards to None if their attack is 1500 or higher
-        duel.get_enemy_mut()
-            .monster_row
-            .iter_mut()
-            .for_each(|monster_row_pos| {
-                if let Some(monster) = monster_row_pos {
-                    if monster.card.get_stats_with_terrain(terrain_type).unwrap().0 >= 1500 {
-                        *monster_row_pos = None;
-                    }
-                }
-            });
+        let terrain_bonus = duel.ruleset.terrain_bonus;
+        // Destroy all enemy monster cards if their attack is 1500 or higher
+        duel.get_enemy_mut().discard_monsters_where(
+            |monster| {
+                monster
+                    .card
+                    .get_stats_with_terrain_bonus(terrain_type, terrain_bonus)
+                    .unwrap()
+                    .0
+                    >= 1500
+            },
+            DiscardReason::MagicEffect,
+        );
     }
 }
 
@@ -128,11 +118,12 @@ impl MagicEffect for LifePointHealerEffect {
         // If so, turn the trap to None. Then negate self.amount.
         let mut amount = self.amount;
 
-        for spell_row_pos in duel.get_enemy_mut().spell_row.iter_mut() {
-            if let Some(spell) = spell_row_pos {
+        for index in 0..duel.get_enemy().spell_row.len() {
+            if let Some(spell) = &duel.get_enemy().spell_row[index] {
                 if let crate::CardVariant::Trap(trap_effect) = &spell.card.variant {
                     if let TrapEffectEnum::BadReactionToSimochi = trap_effect {
-                        *spell_row_pos = None;
+                        duel.get_enemy_mut()
+                            .discard_spell(index, DiscardReason::Activated);
                         amount = -amount;
                         break;
                     }
@@ -140,7 +131,9 @@ impl MagicEffect for LifePointHealerEffect {
             }
         }
 
-        duel.get_player_mut().modify_life_points(amount);
+        let max_life_points = duel.ruleset.max_life_points;
+        duel.get_player_mut()
+            .modify_life_points(amount, max_life_points);
     }
 }
 
@@ -155,11 +148,12 @@ impl MagicEffect for LifePointDamagerEffect {
         // Check enemy spells for GoblinFan
         // If present, modify the player's life points instead of the enemy's. Also set the GoblinFan to None.
         let mut goblin_fan_activated = false;
-        for spell_row_pos in duel.get_enemy_mut().spell_row.iter_mut() {
-            if let Some(spell) = spell_row_pos {
+        for index in 0..duel.get_enemy().spell_row.len() {
+            if let Some(spell) = &duel.get_enemy().spell_row[index] {
                 if let crate::CardVariant::Trap(trap_effect) = &spell.card.variant {
                     if let TrapEffectEnum::GoblinFan = trap_effect {
-                        *spell_row_pos = None;
+                        duel.get_enemy_mut()
+                            .discard_spell(index, DiscardReason::Activated);
                         goblin_fan_activated = true;
                         break;
                     }
@@ -167,13 +161,14 @@ impl MagicEffect for LifePointDamagerEffect {
             }
         }
 
+        let max_life_points = duel.ruleset.max_life_points;
         let player_to_damage = if goblin_fan_activated {
             duel.get_player_mut()
         } else {
             duel.get_enemy_mut()
         };
 
-        player_to_damage.modify_life_points(-self.amount);
+        player_to_damage.modify_life_points(-self.amount, max_life_points);
     }
 }
 
@@ -264,7 +259,7 @@ impl MagicEffect for CursebreakerEffect {
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
 pub struct SwordsOfRevealingLightEffect;
-// Set all enemy monster cards to faceup. Set sorl_effect_countdown to Some(3).
+// Set all enemy monster cards to faceup. Set sorl_effect_countdown to Some(sorl_turns), which is 3 in the original game.
 impl MagicEffect for SwordsOfRevealingLightEffect {
     fn execute_effect(&self, duel: &mut Duel) {
         duel.get_enemy_mut()
@@ -275,7 +270,7 @@ impl MagicEffect for SwordsOfRevealingLightEffect {
                     monster.face_direction = crate::duel::field::FaceDirection::Up;
                 }
             });
-        duel.get_enemy_mut().sorl_effect_countdown = Some(3);
+        duel.get_enemy_mut().sorl_effect_countdown = Some(duel.ruleset.sorl_turns);
     }
 }
 
@@ -0,0 +1,361 @@
+use itertools::{Either, Itertools};
+use std::collections::HashSet;
+
+use crate::{
+    check_all_successful_equips, combine_with_rules, get_amount_of_equip_boosts, Card, Duel,
+};
+
+use super::{
+    command::{DuelCommandEnum, HandPlayMultipleCmd, MAX_HAND_PLAY_CARDS},
+    graveyard::{combination_step_discards, DiscardReason},
+    state::DuelStateEnum,
+};
+
+// Everything the duel after a HandPlayMultipleCmd depends on, apart from the order in which cards reach the graveyard.
+// A combination that is only partly played has an outcome too, and everything played after it only depends on that outcome.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+struct FusionOutcome {
+    field_index: usize,
+    // Sorted, since the rest of the hand keeps its order regardless of the order the cards were played in.
+    hand_indices: Vec<usize>,
+    result: Card,
+    // Sorted, so the order of the combination does not matter.
+    discards: Vec<(Card, DiscardReason)>,
+    cards_created: usize,
+    all_successful_equips: bool,
+    applied_equips_amount: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CanonicalCommand {
+    // The command to execute for this outcome, which is the first of the equivalent commands in the order of generate_all_valid.
+    pub command: DuelCommandEnum,
+    // None for commands other than HandPlayMultipleCmds, which are only equivalent to themselves.
+    outcome: Option<FusionOutcome>,
+}
+
+impl CanonicalCommand {
+    // Every valid command that leads to the same outcome, including the command itself.
+    // They are found when needed, since a large hand has millions of them.
+    pub fn equivalent<'a>(&'a self, duel: &'a Duel) -> impl Iterator<Item = DuelCommandEnum> + 'a {
+        match &self.outcome {
+            None => Either::Left(std::iter::once(self.command.clone())),
+            // equivalent commands play the same cards in a different order
+            Some(outcome) => Either::Right(
+                outcome
+                    .hand_indices
+                    .iter()
+                    .copied()
+                    .permutations(outcome.hand_indices.len())
+                    .map(|hand_indices| HandPlayMultipleCmd {
+                        hand_indices,
+                        field_index: outcome.field_index,
+                    })
+                    .filter(move |command| fusion_outcome(duel, command) == *outcome)
+                    .map(DuelCommandEnum::from),
+            ),
+        }
+    }
+}
+
+// What a single (card1, card2, result) step of a combination contributes to its outcome.
+struct StepSummary {
+    discards: Vec<(Card, DiscardReason)>,
+    created: bool,
+    successful_equip: bool,
+    equip_boost: u32,
+}
+
+impl StepSummary {
+    fn new(step: &(Card, Card, Card), glitch_fusions: bool) -> Self {
+        let (card1, card2, result) = step;
+        let (entries, created) = combination_step_discards(card1, card2, result, glitch_fusions);
+        let step = std::slice::from_ref(step);
+        Self {
+            discards: entries
+                .iter()
+                .map(|entry| (entry.card, entry.reason))
+                .collect(),
+            created,
+            successful_equip: check_all_successful_equips(step),
+            equip_boost: get_amount_of_equip_boosts(step),
+        }
+    }
+}
+
+// A combination of hand cards, played one at a time onto a field index.
+struct Combination<'a> {
+    hand: &'a [Card],
+    field_index: usize,
+    glitch_fusions: bool,
+    hand_indices: Vec<usize>,
+    // One summary per step of the combination so far, so a prefix shared by many orderings is only combined once.
+    steps: Vec<StepSummary>,
+}
+
+impl<'a> Combination<'a> {
+    fn new(duel: &'a Duel, field_index: usize) -> Self {
+        Self {
+            hand: &duel.get_player().hand,
+            field_index,
+            glitch_fusions: duel.ruleset.glitch_fusions,
+            hand_indices: Vec::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    // Plays the hand card onto the current card, and returns the new current card.
+    fn play(&mut self, current: Option<&Card>, hand_index: usize) -> Card {
+        let card = &self.hand[hand_index];
+        self.hand_indices.push(hand_index);
+        match current {
+            Some(current) => {
+                let result = combine_with_rules(current, card, self.glitch_fusions);
+                let step = (*current, *card, result);
+                self.steps
+                    .push(StepSummary::new(&step, self.glitch_fusions));
+                result
+            }
+            None => *card,
+        }
+    }
+
+    // Takes back the last card played, which was played onto a card if combined is true.
+    fn undo(&mut self, combined: bool) {
+        self.hand_indices.pop();
+        if combined {
+            self.steps.pop();
+        }
+    }
+
+    fn outcome(&self, result: &Card) -> FusionOutcome {
+        let mut discards = self
+            .steps
+            .iter()
+            .flat_map(|step| step.discards.iter().copied())
+            .collect::<Vec<_>>();
+        discards.sort();
+        let mut hand_indices = self.hand_indices.clone();
+        hand_indices.sort();
+
+        FusionOutcome {
+            field_index: self.field_index,
+            hand_indices,
+            result: *result,
+            discards,
+            cards_created: self.steps.iter().filter(|step| step.created).count(),
+            all_successful_equips: self.steps.iter().all(|step| step.successful_equip),
+            applied_equips_amount: self.steps.iter().map(|step| step.equip_boost).sum(),
+        }
+    }
+}
+
+// An existing monster is the start of the combination.
+fn existing_card(duel: &Duel, field_index: usize) -> Option<Card> {
+    duel.get_player().monster_row[field_index]
+        .as_ref()
+        .map(|monster| monster.card)
+}
+
+fn fusion_outcome(duel: &Duel, command: &HandPlayMultipleCmd) -> FusionOutcome {
+    let mut combination = Combination::new(duel, command.field_index);
+    let mut current = existing_card(duel, command.field_index);
+    for &hand_index in &command.hand_indices {
+        current = Some(combination.play(current.as_ref(), hand_index));
+    }
+    combination.outcome(&current.unwrap())
+}
+
+// An ordering of hand cards that is being extended with every hand card in turn.
+struct Frame {
+    current: Option<Card>,
+    combined: bool,
+    next_hand_index: usize,
+}
+
+// The canonical commands of a duel, which are found one at a time with a depth-first search over the orderings of hand cards.
+// An ordering is only extended if no earlier ordering had the same outcome, since both would be extended into the same outcomes.
+pub struct CanonicalCommands<'a> {
+    duel: &'a Duel,
+    others: std::vec::IntoIter<DuelCommandEnum>,
+    field_indices: std::ops::Range<usize>,
+    combination: Option<Combination<'a>>,
+    frames: Vec<Frame>,
+    // The outcomes of the orderings so far for the current field index.
+    visited: HashSet<FusionOutcome>,
+}
+
+impl Iterator for CanonicalCommands<'_> {
+    type Item = CanonicalCommand;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(command) = self.others.next() {
+            return Some(CanonicalCommand {
+                command,
+                outcome: None,
+            });
+        }
+
+        loop {
+            let Some(combination) = &mut self.combination else {
+                let field_index = self.field_indices.next()?;
+                self.combination = Some(Combination::new(self.duel, field_index));
+                self.frames.push(Frame {
+                    current: existing_card(self.duel, field_index),
+                    combined: false,
+                    next_hand_index: 0,
+                });
+                self.visited.clear();
+                continue;
+            };
+            let Some(frame) = self.frames.last_mut() else {
+                self.combination = None;
+                continue;
+            };
+
+            let hand_index = frame.next_hand_index;
+            if hand_index == combination.hand.len()
+                || combination.hand_indices.len() == MAX_HAND_PLAY_CARDS
+            {
+                let frame = self.frames.pop().unwrap();
+                if !self.frames.is_empty() {
+                    combination.undo(frame.combined);
+                }
+                continue;
+            }
+            frame.next_hand_index += 1;
+            if combination.hand_indices.contains(&hand_index) {
+                continue;
+            }
+
+            let current = frame.current;
+            let result = combination.play(current.as_ref(), hand_index);
+            let frame = Frame {
+                current: Some(result),
+                combined: current.is_some(),
+                next_hand_index: 0,
+            };
+            if combination.hand_indices.len() < 2 {
+                self.frames.push(frame);
+                continue;
+            }
+
+            let outcome = combination.outcome(&result);
+            if self.visited.contains(&outcome) {
+                combination.undo(frame.combined);
+                continue;
+            }
+            self.visited.insert(outcome.clone());
+            let command = HandPlayMultipleCmd {
+                hand_indices: combination.hand_indices.clone(),
+                field_index: combination.field_index,
+            };
+            self.frames.push(frame);
+            return Some(CanonicalCommand {
+                command: command.into(),
+                outcome: Some(outcome),
+            });
+        }
+    }
+}
+
+impl DuelCommandEnum {
+    // Same as generate_all_valid, but HandPlayMultipleCmds with the same outcome are grouped into one canonical command.
+    // For example, playing monsters that cannot fuse only keeps the last one, so the order of the others is irrelevant.
+    // Every valid command is equivalent to exactly one canonical command.
+    pub fn generate_canonical(duel: &Duel) -> CanonicalCommands<'_> {
+        let field_indices = if matches!(duel.state, DuelStateEnum::HandState { .. }) {
+            0..duel
+                .ruleset
+                .row_size
+                .min(duel.get_player().monster_row.len())
+        } else {
+            0..0
+        };
+        CanonicalCommands {
+            duel,
+            others: Self::generate_valid(duel, false)
+                .iter()
+                .collect::<Vec<_>>()
+                .into_iter(),
+            field_indices,
+            combination: None,
+            frames: Vec::new(),
+            visited: HashSet::new(),
+        }
+    }
+}
+
+impl Duel {
+    pub fn generate_canonical_commands(&self) -> CanonicalCommands<'_> {
+        DuelCommandEnum::generate_canonical(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::command::DuelCommand;
+    use test::Bencher;
+
+    // The duel after a command, with the graveyards sorted so the order of the combination does not matter.
+    fn outcome_of(duel: &Duel, command: &DuelCommandEnum) -> Duel {
+        let mut duel = duel.clone();
+        command.execute(&mut duel).unwrap();
+        for player in [&mut duel.player1, &mut duel.player2] {
+            player
+                .graveyard
+                .sort_by_key(|entry| (entry.card, entry.reason));
+        }
+        duel
+    }
+
+    #[test]
+    fn test_generate_canonical() {
+        let duel = Duel::random();
+        let all_commands = duel.generate_all_valid_commands();
+        let canonical = duel.generate_canonical_commands().collect::<Vec<_>>();
+        dbg!(all_commands.len(), canonical.len());
+
+        // every valid command is in exactly one group
+        assert_eq!(
+            canonical
+                .iter()
+                .map(|command| command.equivalent(&duel).count())
+                .sum::<usize>(),
+            all_commands.len()
+        );
+        assert!(canonical.len() <= all_commands.len());
+
+        // and every command in a group has the same outcome
+        for group in &canonical {
+            let expected = outcome_of(&duel, &group.command);
+            for command in group.equivalent(&duel) {
+                assert_eq!(outcome_of(&duel, &command), expected);
+            }
+        }
+    }
+
+    // Heishin's 20-card hand has millions of orderings, but only the first of each outcome is extended
+    #[test]
+    fn test_generate_canonical_large_hand() {
+        let mut duel = Duel::random();
+        duel.player1.hand_size = 20;
+        duel.player1.draw();
+        let canonical = duel.generate_canonical_commands();
+        let first = canonical
+            .take(1000)
+            .filter(|group| matches!(group.command, DuelCommandEnum::HandPlayMultipleCmd(_)))
+            .collect::<Vec<_>>();
+        assert!(!first.is_empty());
+        for group in &first {
+            assert!(group.command.check_valid(&duel).is_ok());
+        }
+    }
+
+    #[bench]
+    fn bench_generate_canonical(b: &mut Bencher) {
+        let duel = Duel::random();
+        b.iter(|| DuelCommandEnum::generate_canonical(&duel).count());
+    }
+}
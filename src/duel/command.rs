@@ -6,7 +6,7 @@ use std::collections::HashSet;
 use thiserror::Error;
 
 use crate::{
-    card_from_id, check_all_successful_equips, combine, combine_cards,
+    card_from_id, check_all_successful_equips, combine_cards_with_rules, combine_with_rules,
     duel::field::{MonsterRowPosition, SpellRowPosition},
     get_amount_of_equip_boosts, guardian_star_relation, AdvantageRelation, Card, CardVariant,
     MagicEffect, TrapEffectEnum,
@@ -14,10 +14,14 @@ use crate::{
 
 use super::{
     field::{CardMode, FaceDirection, GuardianStarChoice},
+    graveyard::DiscardReason,
     state::*,
     Duel,
 };
 
+// The most cards that can be played from the hand at once, in a fusion chain.
+pub const MAX_HAND_PLAY_CARDS: usize = 5;
+
 pub fn exodia_check(duel: &mut Duel) {
     // The cards with IDs 17-21 are the 5 pieces of Exodia.
     // We need to get the first 5 cards in the player's hand, and check if they are the 5 pieces of Exodia.
@@ -26,7 +30,7 @@ pub fn exodia_check(duel: &mut Duel) {
     // We need to check we have all the IDs UNIQUELY. Not just a total count of 5.
     // We can do this by putting the IDs in a set, and checking the set has 5 elements.
     let mut exodia_ids = std::collections::HashSet::new();
-    for card in &duel.get_player().hand[0..5] {
+    for card in duel.get_player().hand.iter().take(5) {
         if card.id >= 17 && card.id <= 21 {
             exodia_ids.insert(card.id);
         }
@@ -34,6 +38,7 @@ pub fn exodia_check(duel: &mut Duel) {
     if exodia_ids.len() == 5 {
         duel.state = EndState {
             winner: duel.get_player_enum(),
+            win_condition: WinCondition::Exodia,
         }
         .into()
     }
@@ -47,6 +52,7 @@ fn end_game_lp_check(duel: &mut Duel) {
             } else {
                 duel.get_player_enum()
             },
+            win_condition: WinCondition::LifePoints,
         }
         .into();
     }
@@ -55,6 +61,8 @@ fn end_game_lp_check(duel: &mut Duel) {
 fn execute_spell(card: Card, duel: &mut Duel) {
     match card.variant {
         CardVariant::Magic(magic_effect) => {
+            duel.get_player_mut()
+                .discard(card, DiscardReason::Activated);
             magic_effect.execute_effect(duel);
 
             end_game_lp_check(duel);
@@ -70,6 +78,9 @@ fn execute_spell(card: Card, duel: &mut Duel) {
             card3_id,
             result_card_id,
         } => {
+            duel.get_player_mut()
+                .discard(card, DiscardReason::Activated);
+
             // Loop through the player's monster row and check if the three cards are present.
             // If so, remove all of them from the field. Then, enter SetGuardianStarState with the ritual card.
             let mut found_cards = vec![];
@@ -89,10 +100,12 @@ fn execute_spell(card: Card, duel: &mut Duel) {
             if found_cards.len() == 3 {
                 // Remove the cards from the field
                 for index in &found_cards {
-                    duel.get_player_mut().monster_row[*index] = None;
+                    duel.get_player_mut()
+                        .discard_monster(*index, DiscardReason::RitualTribute);
                 }
 
                 let ritual_card = card_from_id(result_card_id);
+                duel.get_player_mut().cards_created += 1;
 
                 // Create the monster_row_pos to hold the ritual card
                 let monster_row_pos = MonsterRowPosition {
@@ -124,6 +137,8 @@ fn execute_spell(card: Card, duel: &mut Duel) {
             }
         }
         CardVariant::Equip { .. } | CardVariant::Trap { .. } => {
+            duel.get_player_mut()
+                .discard(card, DiscardReason::Activated);
             duel.state = FieldState.into();
         }
         _ => panic!("execute_spell: Called on a monster card."),
@@ -141,13 +156,16 @@ fn reverse_trap(monster_index: usize, equip_amount: u32, duel: &mut Duel) {
             if let CardVariant::Trap(effect) = &spell.card.variant {
                 if *effect == TrapEffectEnum::ReverseTrap {
                     // Remove the trap
-                    *spell_row_pos = None;
+                    let trap = spell_row_pos.take().unwrap();
 
                     // Negate the monster's attack
                     let monster = &mut duel.get_player_mut().monster_row[monster_index]
                         .as_mut()
                         .unwrap();
                     monster.card.modify_stats(-(equip_amount as i32) * 2);
+
+                    duel.get_enemy_mut()
+                        .discard(trap.card, DiscardReason::Activated);
                     break;
                 }
             }
@@ -155,7 +173,7 @@ fn reverse_trap(monster_index: usize, equip_amount: u32, duel: &mut Duel) {
     }
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum CommandError {
     #[error("Invalid Duel State.")]
     InvalidDuelState,
@@ -272,18 +290,19 @@ impl DuelCommand for HandPlaySingleCmd {
                 let card_to_play = match existing_position {
                     Some(existing_card) => {
                         face_direction = FaceDirection::Up;
-                        let ret = combine(&card, &existing_card.card);
-                        // destructure ret and existing_card variants as monster to extract attack
-                        if let (
-                            CardVariant::Monster {
-                                attack: existing_attack,
-                                ..
-                            },
-                            CardVariant::Monster {
-                                attack: new_attack, ..
-                            },
-                        ) = (&existing_card.card.variant, &ret.variant)
-                        {
+                        let glitch_fusions = duel.ruleset.glitch_fusions;
+                        let ret = combine_with_rules(&card, &existing_card.card, glitch_fusions);
+                        duel.get_player_mut().discard_combination_step(
+                            &card,
+                            &existing_card.card,
+                            &ret,
+                            glitch_fusions,
+                        );
+                        // get the attack of ret and existing_card, if both are monsters
+                        if let (Some((existing_attack, _)), Some((new_attack, _))) = (
+                            existing_card.card.get_stats_no_terrain(),
+                            ret.get_stats_no_terrain(),
+                        ) {
                             // if the new card is the same but with increased attack, we know we successfully applied an equip.
                             // in this case, we need to preserve the mode of the existing card and go to FieldState instead of SetGuardianStarState.
                             if ret.id == existing_card.card.id && new_attack > existing_attack {
@@ -294,7 +313,7 @@ impl DuelCommand for HandPlaySingleCmd {
                         }
                         ret
                     }
-                    None => card.clone(),
+                    None => card,
                 };
 
                 // in some rare cases, an equip played faceup can fuse with an existing monster and create a spell.
@@ -313,7 +332,7 @@ impl DuelCommand for HandPlaySingleCmd {
                 }
 
                 let monster_row_position = MonsterRowPosition {
-                    card: card_to_play.clone(),
+                    card: card_to_play,
                     face_direction: face_direction,
                     disabled: false,
                     card_mode: card_mode,
@@ -340,14 +359,23 @@ impl DuelCommand for HandPlaySingleCmd {
                 };
             } else {
                 let existing_position = duel.get_player().spell_row[field_index].clone();
-                if existing_position.is_some() {
-                    let card = combine(&card, &existing_position.as_ref().unwrap().card);
+                if let Some(existing_position) = existing_position {
+                    let glitch_fusions = duel.ruleset.glitch_fusions;
+                    let combined_card =
+                        combine_with_rules(&card, &existing_position.card, glitch_fusions);
+                    duel.get_player_mut().discard_combination_step(
+                        &card,
+                        &existing_position.card,
+                        &combined_card,
+                        glitch_fusions,
+                    );
+                    let card = combined_card;
                     duel.get_player_mut().spell_row[field_index] = None;
 
                     execute_spell(card, duel);
                 } else {
                     duel.get_player_mut().spell_row[field_index] = Some(SpellRowPosition {
-                        card: card.clone(),
+                        card,
                         face_direction: self.face_direction,
                     });
                 }
@@ -374,7 +402,7 @@ impl DuelCommand for HandPlayMultipleCmd {
         }
 
         // Check that there are a valid number of cards selected
-        if self.hand_indices.len() < 2 || self.hand_indices.len() > 5 {
+        if self.hand_indices.len() < 2 || self.hand_indices.len() > MAX_HAND_PLAY_CARDS {
             return Err(CommandError::InvalidNumberOfCardsSelected);
         }
 
@@ -384,6 +412,12 @@ impl DuelCommand for HandPlayMultipleCmd {
             return Err(CommandError::DuplicateHandSelection);
         }
 
+        // Check that every selected card is in the hand
+        let hand_size = duel.get_player().hand.len();
+        if self.hand_indices.iter().any(|&index| index >= hand_size) {
+            return Err(CommandError::OutOfBoundsHandSelection);
+        }
+
         // Check if the field index is valid
         if self.field_index >= duel.get_player().monster_row.len() {
             return Err(CommandError::OutOfBoundsFieldSelection);
@@ -399,7 +433,7 @@ impl DuelCommand for HandPlayMultipleCmd {
         let mut cards: Vec<_> = self
             .hand_indices
             .iter()
-            .map(|&index| duel.get_player_mut().hand[index].clone())
+            .map(|&index| duel.get_player_mut().hand[index])
             .collect();
 
         // remove all hand_indices from the hand. note that hand_indices is not sorted.
@@ -412,12 +446,17 @@ impl DuelCommand for HandPlayMultipleCmd {
 
         // check if the field_index is occupied. if so, take the card and append it to the beginning of the cards vector.
         if let Some(existing_card) = &duel.get_player_mut().monster_row[self.field_index] {
-            cards.insert(0, existing_card.card.clone());
+            cards.insert(0, existing_card.card);
         }
 
         // combine all the cards into a single card
-        let combined_cards = combine_cards(cards.clone());
-        let combined_card_result = combined_cards.last().unwrap().2.clone();
+        let glitch_fusions = duel.ruleset.glitch_fusions;
+        let combined_cards = combine_cards_with_rules(cards.clone(), glitch_fusions);
+        let combined_card_result = combined_cards.last().unwrap().2;
+        for (card1, card2, result) in &combined_cards {
+            duel.get_player_mut()
+                .discard_combination_step(card1, card2, result, glitch_fusions);
+        }
 
         // match on whether the card is a monster or otherwise
         match combined_card_result.variant {
@@ -431,7 +470,7 @@ impl DuelCommand for HandPlayMultipleCmd {
             CardVariant::Monster { .. } => {
                 let mut card_mode = CardMode::Attack;
                 let mut guardian_star_choice = GuardianStarChoice::A;
-                let all_successful_equips = check_all_successful_equips(combined_cards.clone());
+                let all_successful_equips = check_all_successful_equips(&combined_cards);
 
                 if all_successful_equips {
                     if let Some(monster_row) =
@@ -446,7 +485,7 @@ impl DuelCommand for HandPlayMultipleCmd {
                 // if all_successful_equips is true, we place the card and then go to FieldState, preserving the mode of the existing card.
                 // otherwise, we go to SetGuardianStarState.
                 let monster_row_position = MonsterRowPosition {
-                    card: combined_card_result.clone(),
+                    card: combined_card_result,
                     face_direction: FaceDirection::Up,
                     disabled: false,
                     card_mode: card_mode,
@@ -456,7 +495,7 @@ impl DuelCommand for HandPlayMultipleCmd {
                 // We need to check how much equips were applied during the combination.
                 // For each window, we need to check if the previous card was the same as the current card, and if the attack increased.
                 // If so, increase applied_equips_amount by the difference in attack.
-                let applied_equips_amount = get_amount_of_equip_boosts(combined_cards.clone());
+                let applied_equips_amount = get_amount_of_equip_boosts(&combined_cards);
 
                 // Change the duel state based on whether all equips were successful
                 if all_successful_equips {
@@ -481,7 +520,9 @@ impl DuelCommand for HandPlayMultipleCmd {
                 }
             }
             _ => {
-                execute_spell(combined_card_result.clone(), duel);
+                // The existing monster was used up in the combination, just like when playing a single card.
+                duel.get_player_mut().monster_row[self.field_index] = None;
+                execute_spell(combined_card_result, duel);
             }
         }
 
@@ -548,7 +589,7 @@ impl DuelCommand for FieldAttackCmd {
         }
 
         // Check if it's the first turn. If so, the player cannot attack.
-        if duel.turn == 0 {
+        if duel.turn == 0 && !duel.ruleset.first_turn_attack {
             return Err(CommandError::CannotAttackOnFirstTurn);
         }
 
@@ -606,7 +647,8 @@ impl DuelCommand for FieldAttackCmd {
                 if let CardVariant::Trap(trap_effect) = spell.card.variant {
                     match trap_effect {
                         TrapEffectEnum::FakeTrap => {
-                            duel.get_enemy_mut().spell_row[index] = None;
+                            duel.get_enemy_mut()
+                                .discard_spell(index, DiscardReason::Activated);
                             let monster = duel.get_player_mut().monster_row[self.monster_row_index]
                                 .as_mut()
                                 .unwrap();
@@ -622,13 +664,18 @@ impl DuelCommand for FieldAttackCmd {
                                 .as_ref()
                                 .unwrap()
                                 .card
-                                .get_stats_with_terrain(duel.terrain_type)
+                                .get_stats_with_terrain_bonus(
+                                    duel.terrain_type,
+                                    duel.ruleset.terrain_bonus,
+                                )
                                 .unwrap();
                             if attack_factor_threshold.is_none()
                                 || attacker_attack <= attack_factor_threshold.unwrap() as i32
                             {
-                                duel.get_enemy_mut().spell_row[index] = None;
-                                duel.get_player_mut().monster_row[self.monster_row_index] = None;
+                                duel.get_enemy_mut()
+                                    .discard_spell(index, DiscardReason::Activated);
+                                duel.get_player_mut()
+                                    .discard_monster(self.monster_row_index, DiscardReason::Trap);
                                 return Ok(());
                             }
                         }
@@ -655,10 +702,12 @@ impl DuelCommand for FieldAttackCmd {
                 let attacking_monster = duel.get_player_mut().monster_row[self.monster_row_index]
                     .clone()
                     .unwrap();
-                if let CardVariant::Monster { attack, .. } = attacking_monster.card.variant {
+                if let Some((attack, _)) = attacking_monster.card.get_stats_no_terrain() {
                     // Attack can be negative. If so, we need to round up to 0 before inflicting damage.
                     let damage = -(attack.max(0));
-                    duel.get_enemy_mut().modify_life_points(damage);
+                    let max_life_points = duel.ruleset.max_life_points;
+                    duel.get_enemy_mut()
+                        .modify_life_points(damage, max_life_points);
                 }
             }
         } else {
@@ -681,11 +730,11 @@ impl DuelCommand for FieldAttackCmd {
 
             let (mut attacker_attack, mut _attacker_defense) = attacking_monster
                 .card
-                .get_stats_with_terrain(duel.terrain_type)
+                .get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus)
                 .unwrap();
             let (mut enemy_attack, mut enemy_defense) = enemy_monster
                 .card
-                .get_stats_with_terrain(duel.terrain_type)
+                .get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus)
                 .unwrap();
 
             // All stats can be negative. Round them all to 0.
@@ -694,19 +743,21 @@ impl DuelCommand for FieldAttackCmd {
             enemy_attack = enemy_attack.max(0);
             enemy_defense = enemy_defense.max(0);
 
-            // for both monsters, use guardian_star_relation function to check if advantageous (+500), disadvantageous (-500), or neutral (no change)
+            // for both monsters, use guardian_star_relation function to check if advantageous (+guardian_star_bonus), disadvantageous (-guardian_star_bonus), or neutral (no change)
+            let guardian_star_bonus = duel.ruleset.guardian_star_bonus;
+            let max_life_points = duel.ruleset.max_life_points;
             let (attacker_gs, enemy_gs) = (
                 attacking_monster.get_selected_gs(),
                 enemy_monster.get_selected_gs(),
             );
             match guardian_star_relation(attacker_gs, enemy_gs) {
                 AdvantageRelation::Advantaged => {
-                    attacker_attack += 500;
-                    _attacker_defense += 500;
+                    attacker_attack += guardian_star_bonus;
+                    _attacker_defense += guardian_star_bonus;
                 }
                 AdvantageRelation::Disadvantaged => {
-                    enemy_attack += 500;
-                    enemy_defense += 500;
+                    enemy_attack += guardian_star_bonus;
+                    enemy_defense += guardian_star_bonus;
                 }
                 AdvantageRelation::Neutral => {
                     // no change
@@ -718,29 +769,37 @@ impl DuelCommand for FieldAttackCmd {
                     let damage = (attacker_attack - enemy_attack).abs();
                     if attacker_attack > enemy_attack {
                         // Attacker wins, enemy monster is destroyed and difference in attack is taken as life point damage
-                        duel.get_enemy_mut().monster_row[self.enemy_monster_row_index] = None;
-                        duel.get_enemy_mut().modify_life_points(-damage);
+                        duel.get_enemy_mut()
+                            .discard_monster(self.enemy_monster_row_index, DiscardReason::Battle);
+                        duel.get_enemy_mut()
+                            .modify_life_points(-damage, max_life_points);
                     } else if attacker_attack < enemy_attack {
                         // Enemy wins, attacking monster is destroyed and difference in attack is taken as life point damage
-                        duel.get_player_mut().monster_row[self.monster_row_index] = None;
-                        duel.get_player_mut().modify_life_points(-damage);
+                        duel.get_player_mut()
+                            .discard_monster(self.monster_row_index, DiscardReason::Battle);
+                        duel.get_player_mut()
+                            .modify_life_points(-damage, max_life_points);
                     } else {
                         // Both monsters are destroyed
-                        duel.get_enemy_mut().monster_row[self.enemy_monster_row_index] = None;
-                        duel.get_player_mut().monster_row[self.monster_row_index] = None;
+                        duel.get_enemy_mut()
+                            .discard_monster(self.enemy_monster_row_index, DiscardReason::Battle);
+                        duel.get_player_mut()
+                            .discard_monster(self.monster_row_index, DiscardReason::Battle);
                     }
                 }
                 CardMode::Defense => {
                     if attacker_attack > enemy_defense {
                         // Attacker wins, enemy monster is destroyed
-                        duel.get_enemy_mut().monster_row[self.enemy_monster_row_index] = None;
+                        duel.get_enemy_mut()
+                            .discard_monster(self.enemy_monster_row_index, DiscardReason::Battle);
                     } else {
                         // Defender wins (or draw)
                         // attacking monster is disabled and difference of enemy_defense - attacker_attack is taken as life point damage
                         // if it's a draw, that means the difference is 0, so no damage is taken.
                         let damage = (enemy_defense - attacker_attack).abs();
                         // duel.get_player_mut().monster_row[self.monster_row_index] = None;
-                        duel.get_player_mut().modify_life_points(-damage);
+                        duel.get_player_mut()
+                            .modify_life_points(-damage, max_life_points);
                     }
                 }
             }
@@ -845,7 +904,7 @@ impl DuelCommand for FieldPlaySpellCmd {
             .as_ref()
             .unwrap();
 
-        let card = spell.card.clone();
+        let card = spell.card;
         duel.get_player_mut().spell_row[self.spell_row_index] = None;
 
         execute_spell(card, duel);
@@ -911,20 +970,18 @@ impl DuelCommand for FieldPlayEquipCmd {
         let mut monster = duel.get_player_mut().monster_row[self.monster_row_index]
             .clone()
             .unwrap();
-        let combined_card = combine(&monster.card, &equip_card.card);
+        let glitch_fusions = duel.ruleset.glitch_fusions;
+        let combined_card = combine_with_rules(&monster.card, &equip_card.card, glitch_fusions);
+        duel.get_player_mut().discard_combination_step(
+            &monster.card,
+            &equip_card.card,
+            &combined_card,
+            glitch_fusions,
+        );
 
         // If the combined card has a higher attack than the original monster, then the equip was successful.
-        // We need to destructure the card.variant as monster to extract the attack.
-        if let CardVariant::Monster {
-            attack: combined_attack,
-            ..
-        } = combined_card.variant
-        {
-            if let CardVariant::Monster {
-                attack: original_attack,
-                ..
-            } = monster.card.variant
-            {
+        if let Some((combined_attack, _)) = combined_card.get_stats_no_terrain() {
+            if let Some((original_attack, _)) = monster.card.get_stats_no_terrain() {
                 monster.face_direction = FaceDirection::Up;
                 // monster.card = combined_card;
                 if combined_attack > original_attack && combined_card.id == monster.card.id {
@@ -991,12 +1048,15 @@ impl DuelCommand for EndTurnCmd {
             }
         }
 
+        let deck_out_hand_size = duel.ruleset.deck_out_hand_size;
         let player = duel.get_player_mut();
         player.draw();
 
-        if player.hand.len() < 5 {
+        // a player with a smaller hand than the deck out size could never avoid decking out, so their full hand is enough.
+        if player.hand.len() < deck_out_hand_size.min(player.hand_size) {
             duel.state = EndState {
                 winner: duel.get_enemy_enum(),
+                win_condition: WinCondition::DeckOut,
             }
             .into()
         } else {
@@ -1024,7 +1084,17 @@ pub enum DuelCommandEnum {
 }
 
 impl DuelCommandEnum {
+    // Every valid command at once. For large hands, valid gives the same commands without building all of them.
     pub fn generate_all_valid(duel: &Duel) -> Vec<DuelCommandEnum> {
+        Self::valid(duel).iter().collect()
+    }
+
+    pub fn valid(duel: &Duel) -> ValidCommands {
+        Self::generate_valid(duel, true)
+    }
+
+    // Generates the valid commands, optionally leaving out the HandPlayMultipleCmds, which canonical.rs groups by outcome instead.
+    pub(super) fn generate_valid(duel: &Duel, hand_play_multiple: bool) -> ValidCommands {
         // To generate all HandPlaySingleCmds, we need to generate all possible combinations (cartesian product) of hand_index, face_direction, and field_index.
         // hand_index ranges between 0 and the number of cards in the hand.
         // FaceDirection is FaceDirection::Up or FaceDirection::Down.
@@ -1034,7 +1104,8 @@ impl DuelCommandEnum {
 
         let hand_indices = 0..duel.get_player().hand.len();
         let face_directions = vec![FaceDirection::Up, FaceDirection::Down];
-        let field_indices = (0..5).map(Some).chain(std::iter::once(None));
+        let row_size = duel.ruleset.row_size;
+        let field_indices = (0..row_size).map(Some).chain(std::iter::once(None));
 
         let hand_play_single_cmds = iproduct!(hand_indices, face_directions, field_indices)
             .filter_map(|(hand_index, face_direction, field_index)| {
@@ -1056,28 +1127,18 @@ impl DuelCommandEnum {
         // To do this, we need to get the hand length. Then, we need to generate all possible combinations of hand indices of length 2 to 5 inclusive.
         // For example, if the hand length is 5, we need to generate all combinations of length 2, 3, 4, and 5.
         // This would include [0, 1], [0, 2], [0, 3], [0, 4], [1, 2], [1, 3], [1, 4], [2, 3], [2, 4], [3, 4], [0, 1, 2], [0, 1, 3], [0, 1, 4], [0, 2, 3], [0, 2, 4], [0, 3, 4], [1, 2, 3], [1, 2, 4], [1, 3, 4], [2, 3, 4], [0, 1, 2, 3], [0, 1, 2, 4], [0, 1, 3, 4], [0, 2, 3, 4], [1, 2, 3, 4], [0, 1, 2, 3, 4], etc
+        // Permutations never repeat an index or leave the hand, so whether a command is valid only depends on the duel state and the field index.
+        // That is checked once per field index, rather than once for each of the thousands of permutations.
         let hand_length = duel.get_player().hand.len();
-        let hand_indices = 0..hand_length;
-        let hand_indices_combinations = (2..=5)
-            .flat_map(|n| hand_indices.clone().permutations(n))
-            .collect::<Vec<_>>();
-
-        // now, get the cartesian product of hand_indices_combinations and field_indices
-        // this type, None is not an option for field_index
-        let field_indices = 0..5;
-        let hand_play_multiple_cmds = iproduct!(hand_indices_combinations, field_indices)
-            .filter_map(|(hand_indices, field_index)| {
-                let cmd = HandPlayMultipleCmd {
-                    hand_indices,
-                    field_index,
-                };
-                if cmd.check_valid(duel).is_ok() {
-                    Some(DuelCommandEnum::HandPlayMultipleCmd(cmd))
-                } else {
-                    None
-                }
+        let valid_field_indices = (0..row_size)
+            .filter(|&field_index| {
+                hand_play_multiple
+                    && matches!(duel.state, DuelStateEnum::HandState { .. })
+                    && field_index < duel.get_player().monster_row.len()
             })
             .collect::<Vec<_>>();
+        // The permutations themselves are not built here: there are millions of them for the larger hands of some duelists,
+        // so ValidCommands only creates the ones that are asked for.
 
         // To generate all SetGuardianStarCmd:
         // We need to generate all possible combinations of guardian_star_choice.
@@ -1103,8 +1164,8 @@ impl DuelCommandEnum {
         // monster_row_index ranges between 0 and 4 inclusive.
         // enemy_monster_row_index ranges between 0 and 4 inclusive.
         // We need to filter out invalid combinations.
-        let monster_row_indices = 0..5;
-        let enemy_monster_row_indices = 0..5;
+        let monster_row_indices = 0..row_size;
+        let enemy_monster_row_indices = 0..row_size;
 
         let field_attack_cmds = iproduct!(monster_row_indices, enemy_monster_row_indices)
             .filter_map(|(monster_row_index, enemy_monster_row_index)| {
@@ -1124,7 +1185,7 @@ impl DuelCommandEnum {
         // We need to generate all possible combinations of monster_row_index.
         // monster_row_index ranges between 0 and 4 inclusive.
         // We need to filter out invalid combinations.
-        let monster_row_indices = 0..5;
+        let monster_row_indices = 0..row_size;
         let field_change_mode_cmds = monster_row_indices
             .into_iter()
             .filter_map(|monster_row_index| {
@@ -1143,7 +1204,7 @@ impl DuelCommandEnum {
         // We need to generate all possible combinations of spell_row_index.
         // spell_row_index ranges between 0 and 4 inclusive.
         // We need to filter out invalid combinations.
-        let spell_row_indices = 0..5;
+        let spell_row_indices = 0..row_size;
         let field_play_spell_cmds = spell_row_indices
             .into_iter()
             .filter_map(|spell_row_index| {
@@ -1160,8 +1221,8 @@ impl DuelCommandEnum {
         // We need to generate all possible combinations of spell_row_index monster_row_index.
         // They range between 0 and 4 inclusive.
         // We need to filter out invalid combinations.
-        let monster_row_indices = 0..5;
-        let spell_row_indices = 0..5;
+        let monster_row_indices = 0..row_size;
+        let spell_row_indices = 0..row_size;
         let field_play_equip_cmds = iproduct!(spell_row_indices, monster_row_indices)
             .filter_map(|(spell_row_index, monster_row_index)| {
                 let cmd = FieldPlayEquipCmd {
@@ -1190,24 +1251,171 @@ impl DuelCommandEnum {
             })
             .collect::<Vec<_>>();
 
-        // Combine all the commands into a single vector
-        let mut commands = Vec::new();
-        commands.extend(hand_play_single_cmds);
-        commands.extend(hand_play_multiple_cmds);
-        commands.extend(set_guardian_star_cmds);
-        commands.extend(field_attack_cmds);
-        commands.extend(field_change_mode_cmds);
-        commands.extend(field_play_spell_cmds);
-        commands.extend(field_play_equip_cmds);
-        commands.extend(end_turn_cmds);
-
-        commands
+        // Combine all the other commands, which go after the HandPlayMultipleCmds
+        let mut after = Vec::new();
+        after.extend(set_guardian_star_cmds);
+        after.extend(field_attack_cmds);
+        after.extend(field_change_mode_cmds);
+        after.extend(field_play_spell_cmds);
+        after.extend(field_play_equip_cmds);
+        after.extend(end_turn_cmds);
+
+        ValidCommands {
+            before: hand_play_single_cmds,
+            hand_length,
+            field_indices: valid_field_indices,
+            after,
+        }
+    }
+}
+
+// The most valid commands that are listed to bots and API clients. Only large hands have more, and the commands
+// that are left out are always plays of several cards, which can still be sent.
+pub const MAX_LISTED_COMMANDS: usize = 10_000;
+
+// The valid commands of a duel, in the order of generate_all_valid, where the HandPlayMultipleCmds are only created when needed.
+// Every order of 2-5 cards of a 20-card hand is almost 2 million commands per field index, too many to keep in memory.
+#[derive(Debug, Clone)]
+pub struct ValidCommands {
+    before: Vec<DuelCommandEnum>,
+    hand_length: usize,
+    // The field indices a HandPlayMultipleCmd can target, for every permutation of hand indices.
+    field_indices: Vec<usize>,
+    after: Vec<DuelCommandEnum>,
+}
+
+// The number of ordered selections of k out of n items.
+fn permutation_count(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (n - k + 1..=n).product()
+}
+
+// The hand indices of every HandPlayMultipleCmd of a hand are ranked by their length, then lexicographically,
+// like Itertools::permutations. This is the number of them.
+pub fn hand_permutation_count(hand_length: usize) -> usize {
+    (2..=MAX_HAND_PLAY_CARDS)
+        .map(|n| permutation_count(hand_length, n))
+        .sum()
+}
+
+// The hand indices with the given rank, or None if the rank is out of range.
+pub fn hand_permutation(hand_length: usize, rank: usize) -> Option<Vec<usize>> {
+    let mut rank = rank;
+    for n in 2..=MAX_HAND_PLAY_CARDS {
+        let count = permutation_count(hand_length, n);
+        if rank >= count {
+            rank -= count;
+            continue;
+        }
+        let mut remaining = (0..hand_length).collect::<Vec<_>>();
+        return Some(
+            (0..n)
+                .map(|position| {
+                    let block = permutation_count(hand_length - position - 1, n - position - 1);
+                    let chosen = remaining.remove(rank / block);
+                    rank %= block;
+                    chosen
+                })
+                .collect(),
+        );
+    }
+    None
+}
+
+// The rank of the hand indices, or None if they are not 2-5 different indices into the hand.
+pub fn hand_permutation_rank(hand_length: usize, hand_indices: &[usize]) -> Option<usize> {
+    let n = hand_indices.len();
+    if !(2..=MAX_HAND_PLAY_CARDS).contains(&n)
+        || hand_indices.iter().any(|&index| index >= hand_length)
+        || !hand_indices.iter().all_unique()
+    {
+        return None;
+    }
+    let shorter = (2..n)
+        .map(|m| permutation_count(hand_length, m))
+        .sum::<usize>();
+    let rank = hand_indices
+        .iter()
+        .enumerate()
+        .map(|(position, &index)| {
+            // the indices that are still left and would come first
+            let smaller = index
+                - hand_indices[..position]
+                    .iter()
+                    .filter(|&&earlier| earlier < index)
+                    .count();
+            smaller * permutation_count(hand_length - position - 1, n - position - 1)
+        })
+        .sum::<usize>();
+    Some(shorter + rank)
+}
+
+impl ValidCommands {
+    fn hand_play_multiple_len(&self) -> usize {
+        hand_permutation_count(self.hand_length) * self.field_indices.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.before.len() + self.hand_play_multiple_len() + self.after.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<DuelCommandEnum> {
+        if let Some(command) = self.before.get(index) {
+            return Some(command.clone());
+        }
+        let index = index - self.before.len();
+        let multiple = self.hand_play_multiple_len();
+        if index >= multiple {
+            return self.after.get(index - multiple).cloned();
+        }
+
+        // Each permutation of hand indices is repeated for every field index.
+        let field_index = self.field_indices[index % self.field_indices.len()];
+        let hand_indices = hand_permutation(self.hand_length, index / self.field_indices.len())?;
+        Some(
+            HandPlayMultipleCmd {
+                hand_indices,
+                field_index,
+            }
+            .into(),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = DuelCommandEnum> + '_ {
+        (0..self.len()).map(|index| self.get(index).unwrap())
+    }
+    // The valid commands that are not HandPlayMultipleCmds, which are few enough to search through.
+    pub fn others(&self) -> impl Iterator<Item = &DuelCommandEnum> {
+        self.before.iter().chain(self.after.iter())
+    }
+
+    // Whether a valid HandPlayMultipleCmd starts with the hand indices, and targets the field index if one is given.
+    pub fn any_hand_play_multiple(
+        &self,
+        hand_indices: &[usize],
+        field_index: Option<usize>,
+    ) -> bool {
+        hand_indices.len() <= MAX_HAND_PLAY_CARDS
+            && self.hand_length >= hand_indices.len().max(2)
+            && hand_indices.iter().all(|&index| index < self.hand_length)
+            && hand_indices.iter().all_unique()
+            && match field_index {
+                Some(field_index) => self.field_indices.contains(&field_index),
+                None => !self.field_indices.is_empty(),
+            }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::duel::command_strategy::{CommandStrategy, SeededRandomCommandStrategy};
     use test::Bencher;
 
     // create a default duel, generate all valid moves, and dbg print them
@@ -1237,6 +1445,64 @@ mod tests {
         }
     }
 
+    // the permutations of hand indices are created in the same order as Itertools::permutations
+    #[test]
+    fn test_valid_commands_order() {
+        let duel = Duel::random();
+        let commands = duel.valid_commands();
+        let hand_indices = commands
+            .iter()
+            .filter_map(|command| match command {
+                DuelCommandEnum::HandPlayMultipleCmd(cmd) if cmd.field_index == 0 => {
+                    Some(cmd.hand_indices)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let expected = (2..=MAX_HAND_PLAY_CARDS)
+            .flat_map(|n| (0..5).permutations(n))
+            .collect::<Vec<_>>();
+        assert_eq!(hand_indices, expected);
+        assert_eq!(commands.iter().count(), commands.len());
+    }
+
+    // a 20-card hand, like Heishin's, has millions of valid commands, which are never all built
+    #[test]
+    fn test_valid_commands_large_hand() {
+        let mut duel = Duel::random();
+        duel.player1.hand_size = 20;
+        duel.player1.draw();
+        let commands = duel.valid_commands();
+        dbg!(commands.len());
+
+        let permutations = 20 * 19 + 20 * 19 * 18 + 20 * 19 * 18 * 17 + 20 * 19 * 18 * 17 * 16;
+        assert!(commands.len() > permutations * 5);
+        for index in [0, 1000, commands.len() / 2, commands.len() - 1] {
+            let command = commands.get(index).unwrap();
+            assert!(command.check_valid(&duel).is_ok());
+        }
+        assert!(commands.get(commands.len()).is_none());
+
+        let command = SeededRandomCommandStrategy::new(0).get_command(&duel);
+        assert!(command.check_valid(&duel).is_ok());
+    }
+
+    #[test]
+    fn test_hand_permutation_rank() {
+        let permutations = (2..=MAX_HAND_PLAY_CARDS)
+            .flat_map(|n| (0..7).permutations(n))
+            .collect::<Vec<_>>();
+        assert_eq!(hand_permutation_count(7), permutations.len());
+        for (rank, permutation) in permutations.iter().enumerate() {
+            assert_eq!(hand_permutation(7, rank).as_ref(), Some(permutation));
+            assert_eq!(hand_permutation_rank(7, permutation), Some(rank));
+        }
+        assert_eq!(hand_permutation(7, permutations.len()), None);
+        assert_eq!(hand_permutation_rank(7, &[0]), None);
+        assert_eq!(hand_permutation_rank(7, &[0, 7]), None);
+        assert_eq!(hand_permutation_rank(7, &[1, 1]), None);
+    }
+
     // create a default duel, then benchmark the generation of all valid commands
     #[bench]
     fn bench_generate_all_valid(b: &mut Bencher) {
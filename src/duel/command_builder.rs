@@ -92,7 +92,7 @@ impl<'a> CommandBuilder<'a, Hand> {
         if unique_indices.len() != hand_indices.len() {
             return Err(CommandError::DuplicateHandSelection);
         }
-        if hand_indices.len() < 2 || hand_indices.len() > 5 {
+        if hand_indices.len() < 2 || hand_indices.len() > MAX_HAND_PLAY_CARDS {
             return Err(CommandError::InvalidNumberOfCardsSelected);
         }
         for &index in &hand_indices {
@@ -189,12 +189,15 @@ impl<'a> CommandBuilder<'a, HandMultipleSelected> {
         let mut cards = Vec::new();
         // if field_index already has a monster, we need to prepend it to cards.
         if let Some(monster) = self.duel.get_player().monster_row[field_index].as_ref() {
-            cards.push(monster.card.clone());
+            cards.push(monster.card);
         }
         for index in &self.state.hand_indices {
-            cards.push(self.duel.get_player().hand[*index].clone());
+            cards.push(self.duel.get_player().hand[*index]);
         }
-        let card = crate::combine_cards(cards).last().unwrap().2.clone();
+        let card = crate::combine_cards_with_rules(cards, self.duel.ruleset.glitch_fusions)
+            .last()
+            .unwrap()
+            .2;
 
         // if the card is a monster, we need to check that the field_index is within the length of the monster row.
         if let CardVariant::Monster { .. } = card.variant {
@@ -1,19 +1,287 @@
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
 
-use crate::Duel;
+use crate::{Card, CardVariant, Duel, MagicEffectEnum};
 
-use super::command::DuelCommandEnum;
+use super::{
+    command::{DuelCommand, DuelCommandEnum, FieldPlaySpellCmd, HandPlaySingleCmd},
+    evaluate::HandTunedEvaluator,
+    field::FaceDirection,
+    search::{SearchCommandStrategy, SearchConfig},
+};
 
 pub trait CommandStrategy {
     fn get_command(&self, duel: &Duel) -> DuelCommandEnum;
 }
 
+// How much a strategy may think about a single command. It stops at whichever limit is reached first,
+// but always returns a command, even if that means going over the budget.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Default, Copy, Clone)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    // How many duel states a search may visit.
+    pub nodes: Option<usize>,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn time(time: Duration) -> Self {
+        Self {
+            time: Some(time),
+            nodes: None,
+        }
+    }
+
+    pub fn nodes(nodes: usize) -> Self {
+        Self {
+            time: None,
+            nodes: Some(nodes),
+        }
+    }
+
+    // The moment the time runs out, for a command that started being thought about at start.
+    pub fn deadline(&self, start: Instant) -> Option<Instant> {
+        self.time.map(|time| start + time)
+    }
+}
+
+// A command, and what the strategy found out while choosing it.
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub command: DuelCommandEnum,
+    // The commands the strategy expects to be played from here, starting with the command itself.
+    pub principal_variation: Vec<DuelCommandEnum>,
+    // How good the duel is for the strategy's player, if the strategy evaluates duels. Higher is better.
+    pub evaluation: Option<f64>,
+    pub nodes: usize,
+}
+
+impl Decision {
+    // A decision without any diagnostics.
+    pub fn new(command: DuelCommandEnum) -> Self {
+        Self {
+            principal_variation: vec![command.clone()],
+            command,
+            evaluation: None,
+            nodes: 0,
+        }
+    }
+}
+
+// A strategy that is given a budget for every command, and can keep state across the duel, e.g. a search tree.
+// It is told about every command the enemy plays, and about the end of the duel.
+pub trait BudgetedStrategy {
+    fn decide(&mut self, duel: &Duel, budget: Budget) -> Decision;
+
+    // Called with the duel after the enemy's command was executed.
+    fn on_enemy_command(&mut self, _duel: &Duel, _command: &DuelCommandEnum) {}
+
+    fn on_duel_end(&mut self, _duel: &Duel) {}
+}
+
+// Lets any CommandStrategy be used as a BudgetedStrategy. The budget is ignored, and there are no diagnostics.
+pub struct CommandStrategyAdapter<S: CommandStrategy>(pub S);
+
+impl<S: CommandStrategy> BudgetedStrategy for CommandStrategyAdapter<S> {
+    fn decide(&mut self, duel: &Duel, _budget: Budget) -> Decision {
+        Decision::new(self.0.get_command(duel))
+    }
+}
+
 pub struct RandomCommandStrategy;
 impl CommandStrategy for RandomCommandStrategy {
     fn get_command(&self, duel: &Duel) -> DuelCommandEnum {
-        let mut commands = duel.generate_all_valid_commands();
+        let commands = duel.valid_commands();
         let mut rng = rand::thread_rng();
         let random_index = rng.gen_range(0..commands.len());
-        commands.remove(random_index)
+        commands.get(random_index).unwrap()
+    }
+}
+
+// Same as RandomCommandStrategy, but reproducible for a given seed.
+pub struct SeededRandomCommandStrategy {
+    rng: RefCell<StdRng>,
+}
+
+impl SeededRandomCommandStrategy {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl CommandStrategy for SeededRandomCommandStrategy {
+    fn get_command(&self, duel: &Duel) -> DuelCommandEnum {
+        let commands = duel.valid_commands();
+        let random_index = self.rng.borrow_mut().gen_range(0..commands.len());
+        commands.get(random_index).unwrap()
+    }
+}
+
+// Mage duelists, e.g. Ocean Mage, change the terrain to their own field before anything else.
+// Otherwise, and for players that are not mages, the inner strategy decides.
+pub struct MageCommandStrategy<S: CommandStrategy> {
+    pub inner: S,
+}
+
+impl<S: CommandStrategy> MageCommandStrategy<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    fn changes_terrain(card: &Card, duel: &Duel) -> bool {
+        matches!(
+            &card.variant,
+            CardVariant::Magic(MagicEffectEnum::ChangeTerrainEffect(effect))
+                if effect.terrain_type != duel.terrain_type
+        )
+    }
+
+    // Activates a field card that is already set, or sets one from the hand so it can be activated next.
+    // The commands are built directly instead of filtering generate_all_valid_commands, which is slow for the large hands of mages.
+    fn terrain_command(duel: &Duel) -> Option<DuelCommandEnum> {
+        let player = duel.get_player();
+        let set_terrain_indices = player
+            .spell_row
+            .iter()
+            .enumerate()
+            .filter(|(_, spell)| {
+                spell
+                    .as_ref()
+                    .is_some_and(|spell| Self::changes_terrain(&spell.card, duel))
+            })
+            .map(|(spell_row_index, _)| spell_row_index)
+            .collect::<Vec<_>>();
+
+        // a field card that is already set only needs to wait for the field phase
+        if !set_terrain_indices.is_empty() {
+            return set_terrain_indices
+                .into_iter()
+                .map(|spell_row_index| FieldPlaySpellCmd { spell_row_index }.into())
+                .find(|command: &DuelCommandEnum| command.check_valid(duel).is_ok());
+        }
+
+        // prefer empty spell row positions, so no set card is overwritten
+        let mut spell_row_indices = (0..player.spell_row.len()).collect::<Vec<_>>();
+        spell_row_indices.sort_by_key(|&index| player.spell_row[index].is_some());
+        player
+            .hand
+            .iter()
+            .enumerate()
+            .filter(|(_, card)| Self::changes_terrain(card, duel))
+            .flat_map(|(hand_index, _)| {
+                spell_row_indices.iter().map(move |&field_index| {
+                    HandPlaySingleCmd {
+                        hand_index,
+                        face_direction: FaceDirection::Down,
+                        field_index: Some(field_index),
+                    }
+                    .into()
+                })
+            })
+            .find(|command: &DuelCommandEnum| command.check_valid(duel).is_ok())
+    }
+}
+
+impl<S: CommandStrategy> CommandStrategy for MageCommandStrategy<S> {
+    fn get_command(&self, duel: &Duel) -> DuelCommandEnum {
+        if duel.get_player().is_mage {
+            if let Some(command) = Self::terrain_command(duel) {
+                return command;
+            }
+        }
+        self.inner.get_command(duel)
+    }
+}
+
+// The strategies the front ends let a human play against, by the name given on the command line.
+pub const STRATEGY_NAMES: [&str; 3] = ["random", "mage", "search"];
+
+// One of the STRATEGY_NAMES, or None for any other name. The seed makes its choices reproducible.
+pub fn strategy_from_name(name: &str, seed: u64) -> Option<Box<dyn BudgetedStrategy>> {
+    let strategy: Box<dyn BudgetedStrategy> = match name {
+        "random" => Box::new(CommandStrategyAdapter(SeededRandomCommandStrategy::new(
+            seed,
+        ))),
+        "mage" => Box::new(CommandStrategyAdapter(MageCommandStrategy::new(
+            SeededRandomCommandStrategy::new(seed),
+        ))),
+        "search" => Box::new(SearchCommandStrategy::new(
+            HandTunedEvaluator::default(),
+            SearchConfig::default(),
+            seed,
+        )),
+        _ => return None,
+    };
+    Some(strategy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name,
+        duel::{player::Player, ruleset::Ruleset},
+        duelist_from_name, TerrainType,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_mage_changes_terrain() {
+        let ocean_mage = duelist_from_name("Ocean Mage");
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut player = Player::from_duelist(
+            ocean_mage,
+            ocean_mage.random_deck(&mut rng),
+            &Ruleset::default(),
+        );
+        player.deck.push(card_from_name("Umi"));
+        let mut duel = Duel::new(player, Player::random());
+        dbg!(&duel.player1.hand.len());
+        assert_eq!(duel.player1.hand.len(), 14);
+
+        let strategy = MageCommandStrategy::new(SeededRandomCommandStrategy::new(0));
+        // Umi is set first, then activated as soon as the field phase starts
+        let command = strategy.get_command(&duel);
+        assert!(matches!(
+            command,
+            DuelCommandEnum::HandPlaySingleCmd(HandPlaySingleCmd { hand_index: 0, .. })
+        ));
+        duel.execute(&command).unwrap();
+        while duel.turn == 0 && duel.terrain_type == TerrainType::Default {
+            let command = strategy.get_command(&duel);
+            dbg!(&command);
+            duel.execute(&command).unwrap();
+        }
+        assert_eq!(duel.terrain_type, TerrainType::Sea);
+    }
+
+    #[test]
+    fn test_command_strategy_adapter() {
+        let duel = Duel::random();
+        let mut strategy = CommandStrategyAdapter(RandomCommandStrategy);
+        let decision = strategy.decide(&duel, Budget::nodes(0));
+        assert!(decision.command.check_valid(&duel).is_ok());
+        assert_eq!(decision.principal_variation.len(), 1);
+        assert_eq!(decision.evaluation, None);
+    }
+
+    #[test]
+    fn test_strategy_from_name() {
+        let duel = Duel::random();
+        for name in STRATEGY_NAMES {
+            let mut strategy = strategy_from_name(name, 0).unwrap();
+            let decision = strategy.decide(&duel, Budget::nodes(100));
+            assert!(decision.command.check_valid(&duel).is_ok());
+        }
+        assert!(strategy_from_name("remote", 0).is_none());
     }
 }
@@ -0,0 +1,277 @@
+use itertools::Itertools;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{combine_cards, Card, CardVariant, GuardianStarType, MonsterType, CARDS};
+
+// The amount of random opening hands drawn by analyze() when estimating the strongest play.
+pub const DEFAULT_OPENING_HAND_SAMPLES: usize = 200;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct EquipCoverage {
+    pub card_id: usize,
+    // How many equip cards in the deck (counting copies) can be applied to this monster.
+    pub equips_in_deck: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RitualCompleteness {
+    pub ritual_card_id: usize,
+    pub result_card_id: usize,
+    // How many of the 3 distinct monsters needed by the ritual are in the deck.
+    pub materials_in_deck: usize,
+}
+
+impl RitualCompleteness {
+    pub fn is_complete(&self) -> bool {
+        self.materials_in_deck == 3
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DeckReport {
+    pub card_count: usize,
+    pub monster_count: usize,
+    pub magic_count: usize,
+    pub equip_count: usize,
+    pub ritual_count: usize,
+    pub trap_count: usize,
+    pub monster_types: HashMap<MonsterType, usize>,
+    // Both guardian stars of every monster are counted.
+    pub guardian_stars: HashMap<GuardianStarType, usize>,
+    pub average_monster_attack: f64,
+    pub max_monster_attack: i32,
+    // Distinct pairs of cards in the deck that fuse together.
+    pub two_card_fusions: usize,
+    // Distinct (pair, third card) combinations in the deck where the third card fuses with the result of the pair.
+    pub three_card_fusions: usize,
+    // The average attack of the strongest monster that can be played from a random 5-card opening hand.
+    pub expected_opening_attack: f64,
+    pub equip_coverage: Vec<EquipCoverage>,
+    // How many of the 5 distinct pieces of Exodia are in the deck.
+    pub exodia_pieces: usize,
+    pub rituals: Vec<RitualCompleteness>,
+}
+
+impl DeckReport {
+    pub fn has_exodia(&self) -> bool {
+        self.exodia_pieces == 5
+    }
+}
+
+// Looks up a fusion by card ID, without cloning any cards.
+fn fusion_result_id(card1_id: usize, card2_id: usize) -> Option<usize> {
+    let card1 = &CARDS[card1_id - 1];
+    let card2 = &CARDS[card2_id - 1];
+    card1
+        .fusions
+        .get(&card2_id)
+        .or_else(|| card2.fusions.get(&card1_id))
+        .copied()
+}
+
+fn count_fusions(card_counts: &HashMap<usize, usize>) -> (usize, usize) {
+    let card_ids = card_counts.keys().copied().sorted().collect::<Vec<_>>();
+
+    // A pair (or triple) can only be used if the deck holds enough copies of every card in it.
+    let available = |ids: &[usize]| {
+        ids.iter()
+            .counts()
+            .iter()
+            .all(|(id, &needed)| card_counts[*id] >= needed)
+    };
+
+    let mut two_card_fusions = 0;
+    let mut three_card_fusions = 0;
+    for (i, &card1_id) in card_ids.iter().enumerate() {
+        for &card2_id in &card_ids[i..] {
+            if !available(&[card1_id, card2_id]) {
+                continue;
+            }
+            if let Some(result_id) = fusion_result_id(card1_id, card2_id) {
+                two_card_fusions += 1;
+                for &card3_id in &card_ids {
+                    if available(&[card1_id, card2_id, card3_id])
+                        && fusion_result_id(result_id, card3_id).is_some()
+                    {
+                        three_card_fusions += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    (two_card_fusions, three_card_fusions)
+}
+
+// Tries every ordering of every subset of the hand, and returns the highest attack of a resulting monster.
+pub fn best_attack_in_hand(hand: &[Card]) -> i32 {
+    (1..=hand.len())
+        .flat_map(|n| hand.iter().cloned().permutations(n))
+        .filter_map(|cards| {
+            let card = if cards.len() == 1 {
+                cards[0]
+            } else {
+                combine_cards(cards).last().unwrap().2
+            };
+            card.get_stats_no_terrain().map(|(attack, _)| attack)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// Draws random 5-card opening hands, returning the attack of the strongest play from each.
+pub fn sample_opening_attacks<R: Rng>(deck: &[Card], samples: usize, rng: &mut R) -> Vec<i32> {
+    (0..samples)
+        .map(|_| {
+            let hand = deck
+                .choose_multiple(rng, 5.min(deck.len()))
+                .cloned()
+                .collect::<Vec<_>>();
+            best_attack_in_hand(&hand)
+        })
+        .collect()
+}
+
+pub fn expected_opening_attack<R: Rng>(deck: &[Card], samples: usize, rng: &mut R) -> f64 {
+    let attacks = sample_opening_attacks(deck, samples, rng);
+    if attacks.is_empty() {
+        return 0.0;
+    }
+    attacks.iter().map(|&attack| attack as f64).sum::<f64>() / attacks.len() as f64
+}
+
+pub fn analyze(deck: &[Card]) -> DeckReport {
+    analyze_with_samples(deck, DEFAULT_OPENING_HAND_SAMPLES, &mut rand::thread_rng())
+}
+
+pub fn analyze_with_samples<R: Rng>(deck: &[Card], samples: usize, rng: &mut R) -> DeckReport {
+    let mut report = DeckReport {
+        card_count: deck.len(),
+        monster_count: 0,
+        magic_count: 0,
+        equip_count: 0,
+        ritual_count: 0,
+        trap_count: 0,
+        monster_types: HashMap::new(),
+        guardian_stars: HashMap::new(),
+        average_monster_attack: 0.0,
+        max_monster_attack: 0,
+        two_card_fusions: 0,
+        three_card_fusions: 0,
+        expected_opening_attack: 0.0,
+        equip_coverage: Vec::new(),
+        exodia_pieces: 0,
+        rituals: Vec::new(),
+    };
+
+    let mut total_attack = 0;
+    for card in deck {
+        match &card.variant {
+            CardVariant::Monster {
+                monster_type,
+                attack,
+                guardian_star_a,
+                guardian_star_b,
+                ..
+            } => {
+                report.monster_count += 1;
+                *report.monster_types.entry(*monster_type).or_insert(0) += 1;
+                *report.guardian_stars.entry(*guardian_star_a).or_insert(0) += 1;
+                *report.guardian_stars.entry(*guardian_star_b).or_insert(0) += 1;
+                total_attack += attack;
+                report.max_monster_attack = report.max_monster_attack.max(*attack);
+            }
+            CardVariant::Magic(_) => report.magic_count += 1,
+            CardVariant::Equip { .. } => report.equip_count += 1,
+            CardVariant::Ritual { .. } => report.ritual_count += 1,
+            CardVariant::Trap(_) => report.trap_count += 1,
+        }
+    }
+    if report.monster_count > 0 {
+        report.average_monster_attack = total_attack as f64 / report.monster_count as f64;
+    }
+
+    let card_counts = deck.iter().map(|card| card.id).counts();
+    (report.two_card_fusions, report.three_card_fusions) = count_fusions(&card_counts);
+
+    report.expected_opening_attack = expected_opening_attack(deck, samples, rng);
+
+    // Equip coverage is reported once per distinct monster, in the order monsters first appear in the deck.
+    let deck_ids = deck.iter().map(|card| card.id).collect::<HashSet<_>>();
+    for card in deck.iter().unique_by(|card| card.id) {
+        match &card.variant {
+            CardVariant::Monster { .. } => {
+                let equips_in_deck = deck
+                    .iter()
+                    .filter(|equip| match &equip.variant {
+                        CardVariant::Equip { equips } => equips.contains(&card.id),
+                        _ => false,
+                    })
+                    .count();
+                report.equip_coverage.push(EquipCoverage {
+                    card_id: card.id,
+                    equips_in_deck,
+                });
+            }
+            CardVariant::Ritual {
+                card1_id,
+                card2_id,
+                card3_id,
+                result_card_id,
+            } => {
+                let materials_in_deck = [card1_id, card2_id, card3_id]
+                    .iter()
+                    .filter(|id| deck_ids.contains(id))
+                    .count();
+                report.rituals.push(RitualCompleteness {
+                    ritual_card_id: card.id,
+                    result_card_id: *result_card_id,
+                    materials_in_deck,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // The cards with IDs 17-21 are the 5 pieces of Exodia.
+    report.exodia_pieces = (17..=21).filter(|id| deck_ids.contains(id)).count();
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card_from_name;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_analyze_deck() {
+        let mut deck = vec![card_from_name("Thunder Dragon"); 2];
+        deck.extend((17..=21).map(crate::card_from_id));
+        deck.push(card_from_name("Megamorph"));
+
+        let report = analyze_with_samples(&deck, 20, &mut StdRng::seed_from_u64(0));
+        dbg!(&report);
+        assert_eq!(report.card_count, 8);
+        assert_eq!(report.monster_count, 7);
+        assert_eq!(report.equip_count, 1);
+        assert!(report.has_exodia());
+        assert_eq!(report.magic_count, 0);
+        assert_eq!(report.ritual_count, 0);
+        assert_eq!(report.trap_count, 0);
+        assert_eq!(report.max_monster_attack, 1600);
+        assert_eq!(report.exodia_pieces, 5);
+        assert!(report.rituals.is_empty());
+        // Each Exodia piece + Thunder Dragon = Kaminari Attack, and
+        // Thunder Dragon + Thunder Dragon = Twin-headed Thunder Dragon
+        assert_eq!(report.two_card_fusions, 6);
+        assert_eq!(report.three_card_fusions, 5);
+        // Megamorph equips Thunder Dragon and every Exodia piece
+        assert_eq!(report.equip_coverage.len(), 6);
+        // Fixed by the seed: 20 sampled opening hands
+        assert_eq!(report.expected_opening_attack, 2920.0);
+    }
+}
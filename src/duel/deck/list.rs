@@ -0,0 +1,175 @@
+use thiserror::Error;
+
+use crate::{card_from_id, Card, CARDS};
+
+use super::{deck_is_valid, deck_size_is_valid, DECK_SIZE};
+
+// A deck list is a plain text file with one entry per line, e.g.
+//
+//     // My Thunder deck
+//     3x Thunder Dragon
+//     2x #425
+//     #017 Right Leg of the Forbidden One
+//     Raigeki
+//
+// Each entry is an optional count ("3x") followed by either a card name or a card ID prefixed with '#'.
+// Anything after a card ID is ignored, so the name can be kept next to the ID for readability.
+// Blank lines and lines starting with "//" are skipped.
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DeckListError {
+    #[error("Line {line}: invalid card count `{count}`.")]
+    InvalidCount { line: usize, count: String },
+    #[error("Line {line}: missing card name or ID.")]
+    MissingCard { line: usize },
+    #[error("Line {line}: invalid card ID `{id}`.")]
+    InvalidCardId { line: usize, id: String },
+    #[error("Line {line}: unknown card ID {id}.")]
+    UnknownCardId { line: usize, id: usize },
+    #[error("Line {line}: unknown card name `{name}`.")]
+    UnknownCardName { line: usize, name: String },
+    #[error("Deck must contain exactly {expected} cards, found {actual}.")]
+    InvalidDeckSize { expected: usize, actual: usize },
+    #[error("A card appears more than 3 times in the deck.")]
+    TooManyCopies,
+}
+
+fn parse_line(line_number: usize, line: &str) -> Result<Option<(usize, Card)>, DeckListError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("//") {
+        return Ok(None);
+    }
+
+    // Split off the count if the first token looks like "3x".
+    // Card names can start with a number (e.g. "7 Colored Fish"), so the trailing 'x' is required.
+    let (count, rest) = match line.split_once(char::is_whitespace) {
+        Some((token, rest))
+            if token.len() > 1
+                && token.ends_with(['x', 'X'])
+                && token[..token.len() - 1].chars().all(|c| c.is_ascii_digit()) =>
+        {
+            let count = token[..token.len() - 1]
+                .parse::<usize>()
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| DeckListError::InvalidCount {
+                    line: line_number,
+                    count: token.to_string(),
+                })?;
+            (count, rest.trim())
+        }
+        _ => (1, line),
+    };
+
+    if rest.is_empty() {
+        return Err(DeckListError::MissingCard { line: line_number });
+    }
+
+    let card = if let Some(id_and_name) = rest.strip_prefix('#') {
+        // Everything after the ID is treated as a comment.
+        let id_str = id_and_name
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+        let id = id_str
+            .parse::<usize>()
+            .map_err(|_| DeckListError::InvalidCardId {
+                line: line_number,
+                id: id_str.to_string(),
+            })?;
+        if id == 0 || id > CARDS.len() {
+            return Err(DeckListError::UnknownCardId {
+                line: line_number,
+                id,
+            });
+        }
+        card_from_id(id)
+    } else {
+        CARDS
+            .iter()
+            .find(|card| card.name.eq_ignore_ascii_case(rest))
+            .map(|card| card_from_id(card.id))
+            .ok_or_else(|| DeckListError::UnknownCardName {
+                line: line_number,
+                name: rest.to_string(),
+            })?
+    };
+
+    Ok(Some((count, card)))
+}
+
+pub fn parse_deck_list(text: &str) -> Result<Vec<Card>, DeckListError> {
+    let mut deck = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if let Some((count, card)) = parse_line(index + 1, line)? {
+            deck.extend(vec![card; count]);
+        }
+    }
+
+    if !deck_size_is_valid(&deck) {
+        return Err(DeckListError::InvalidDeckSize {
+            expected: DECK_SIZE,
+            actual: deck.len(),
+        });
+    }
+    if !deck_is_valid(&deck) {
+        return Err(DeckListError::TooManyCopies);
+    }
+
+    Ok(deck)
+}
+
+pub fn write_deck_list(deck: &[Card]) -> String {
+    // Only consecutive copies are grouped together, so that parsing the output gives back the deck in the same order.
+    let mut output = String::new();
+    for group in deck.chunk_by(|a, b| a.id == b.id) {
+        let card = &group[0];
+        if group.len() > 1 {
+            output.push_str(&format!("{}x ", group.len()));
+        }
+        output.push_str(&format!("#{:03} {}\n", card.id, card.name));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::deck::generate_random_deck;
+
+    #[test]
+    fn test_deck_list_round_trip() {
+        let deck = generate_random_deck();
+        let text = write_deck_list(&deck);
+        assert_eq!(parse_deck_list(&text).unwrap(), deck);
+    }
+
+    #[test]
+    fn test_parse_deck_list_errors() {
+        let text = "// comment\n3x Thunder Dragon\n\n2x Not A Real Card\n";
+        assert_eq!(
+            parse_deck_list(text),
+            Err(DeckListError::UnknownCardName {
+                line: 4,
+                name: "Not A Real Card".to_string()
+            })
+        );
+
+        assert_eq!(
+            parse_deck_list("#999 Nothing"),
+            Err(DeckListError::UnknownCardId { line: 1, id: 999 })
+        );
+
+        // names are case-insensitive, and "7 Colored Fish" is a name rather than a count
+        let text = "40x thunder dragon\n";
+        assert_eq!(parse_deck_list(text), Err(DeckListError::TooManyCopies));
+        let text = "3x 7 Colored Fish\n#017 Right Leg...\n";
+        assert_eq!(
+            parse_deck_list(text),
+            Err(DeckListError::InvalidDeckSize {
+                expected: 40,
+                actual: 4
+            })
+        );
+    }
+}
@@ -1,17 +1,33 @@
+use rand::Rng;
+
 use crate::{card_from_id, Card};
 
+pub mod analysis;
+pub mod list;
+pub mod optimizer;
+pub mod rules;
+
+pub use analysis::*;
+pub use list::*;
+pub use optimizer::*;
+pub use rules::*;
+
+// Every deck in the original game holds exactly 40 cards.
+pub const DECK_SIZE: usize = 40;
+
 pub fn generate_random_deck() -> Vec<Card> {
+    generate_random_deck_with_rng(&mut rand::thread_rng())
+}
+
+// Same as generate_random_deck, but the deck is reproducible for a seeded rng.
+pub fn generate_random_deck_with_rng<R: Rng>(rng: &mut R) -> Vec<Card> {
     // a deck is a list of 40 cards. cards can be attained with fmsim::card_from_id function, where ID ranges between 1 and 722.
     // the same card cannot appear more than 3 times.
-    // use rand crate to generate a random deck.
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-
     let mut deck = Vec::new();
     let mut card_counts = [0; 722];
     let mut available_cards: Vec<usize> = (1..723).collect();
 
-    while deck.len() < 40 {
+    while deck.len() < DECK_SIZE {
         let card_index = rng.gen_range(0..available_cards.len());
         let card_id = available_cards[card_index];
         deck.push(card_from_id(card_id));
@@ -27,11 +43,16 @@ pub fn generate_random_deck() -> Vec<Card> {
 
 pub fn deck_is_valid(deck: &[Card]) -> bool {
     // a deck is valid if no card appears more than 3 times.
-    let mut card_counts = [0; 722];
-    for card in deck {
-        card_counts[(card.id - 1) as usize] += 1;
+    // unknown card IDs also make the deck invalid, instead of panicking.
+    DeckRules {
+        deck_size: None,
+        ..DeckRules::campaign()
     }
-    card_counts.iter().all(|&count| count <= 3)
+    .is_valid(deck)
+}
+
+pub fn deck_size_is_valid(deck: &[Card]) -> bool {
+    deck.len() == DECK_SIZE
 }
 
 // test generate_random_deck
@@ -45,5 +66,6 @@ mod tests {
         assert_eq!(deck.len(), 40);
         dbg!(&deck);
         assert!(deck_is_valid(&deck));
+        assert!(deck_size_is_valid(&deck));
     }
 }
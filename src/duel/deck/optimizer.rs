@@ -0,0 +1,215 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::{
+    card_from_id,
+    duel::ruleset::Ruleset,
+    simulate::{matchup_configs, simulate, StrategyFactory},
+    stats::{mean_interval, ConfidenceInterval},
+    Card, CARDS,
+};
+
+use super::{deck_is_valid, sample_opening_attacks, DECK_SIZE};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OptimizerError {
+    #[error("Unknown card ID {0} in the card pool.")]
+    UnknownCard(usize),
+    #[error("The card pool only allows {available} cards, but a deck needs {DECK_SIZE}.")]
+    NotEnoughCards { available: usize },
+}
+
+pub trait DeckObjective {
+    // Scores a deck, where higher is better. The interval is used to report how reliable the score is.
+    fn evaluate(&mut self, deck: &[Card], rng: &mut StdRng) -> ConfidenceInterval;
+}
+
+// Scores a deck by the average attack of the strongest play from a random opening hand.
+pub struct OpeningAttackObjective {
+    pub samples: usize,
+}
+
+impl DeckObjective for OpeningAttackObjective {
+    fn evaluate(&mut self, deck: &[Card], rng: &mut StdRng) -> ConfidenceInterval {
+        let attacks = sample_opening_attacks(deck, self.samples, rng)
+            .into_iter()
+            .map(|attack| attack as f64)
+            .collect::<Vec<_>>();
+        mean_interval(&attacks)
+    }
+}
+
+// Scores a deck by its win rate against an opponent deck, playing half of the duels as the first player.
+pub struct WinRateObjective {
+    pub opponent_deck: Vec<Card>,
+    pub strategy: StrategyFactory,
+    pub opponent_strategy: StrategyFactory,
+    pub ruleset: Ruleset,
+    pub duels: usize,
+    pub workers: usize,
+}
+
+impl DeckObjective for WinRateObjective {
+    fn evaluate(&mut self, deck: &[Card], rng: &mut StdRng) -> ConfidenceInterval {
+        let configs = matchup_configs(
+            deck,
+            &self.opponent_deck,
+            self.strategy.clone(),
+            self.opponent_strategy.clone(),
+            &self.ruleset,
+            self.duels,
+            rng.gen(),
+        );
+        simulate(&configs, self.workers).a.win_rate
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizedDeck {
+    pub deck: Vec<Card>,
+    // The score of the deck from a fresh evaluation, since the score that won the search is biased upwards.
+    pub score: ConfidenceInterval,
+    pub evaluations: usize,
+}
+
+pub struct DeckOptimizer {
+    // How many copies of each card (by ID) are available to build the deck, e.g. the player's chest.
+    pub pool: HashMap<usize, u32>,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl DeckOptimizer {
+    fn ids_to_deck(ids: &[usize]) -> Vec<Card> {
+        ids.iter().map(|&id| card_from_id(id)).collect()
+    }
+
+    pub fn optimize<O: DeckObjective>(
+        &self,
+        objective: &mut O,
+    ) -> Result<OptimizedDeck, OptimizerError> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        // The amount of copies of a card we can use is limited by the pool, and by the 3 copy limit of deck_is_valid.
+        let mut limits = HashMap::new();
+        for (&id, &count) in &self.pool {
+            if id == 0 || id > CARDS.len() {
+                return Err(OptimizerError::UnknownCard(id));
+            }
+            if count > 0 {
+                limits.insert(id, (count as usize).min(3));
+            }
+        }
+        // HashMap iteration order is random, so sort the IDs to keep the search reproducible for a given seed.
+        let mut candidate_ids = limits.keys().copied().collect::<Vec<_>>();
+        candidate_ids.sort();
+
+        let available = limits.values().sum::<usize>();
+        if available < DECK_SIZE {
+            return Err(OptimizerError::NotEnoughCards { available });
+        }
+
+        // Start from a random deck built from the pool.
+        let mut copies = candidate_ids
+            .iter()
+            .flat_map(|&id| vec![id; limits[&id]])
+            .collect::<Vec<_>>();
+        copies.shuffle(&mut rng);
+        let mut current_ids = copies[..DECK_SIZE].to_vec();
+        let mut current_score = objective.evaluate(&Self::ids_to_deck(&current_ids), &mut rng);
+        let mut evaluations = 1;
+
+        // Local search: swap a random card in the deck for a random card from the pool, keeping the swap if the score does not get worse.
+        for _ in 0..self.iterations {
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for &id in &current_ids {
+                *counts.entry(id).or_insert(0) += 1;
+            }
+            let replacements = candidate_ids
+                .iter()
+                .filter(|id| counts.get(id).copied().unwrap_or(0) < limits[id])
+                .copied()
+                .collect::<Vec<_>>();
+            if replacements.is_empty() {
+                break;
+            }
+
+            let mut neighbour_ids = current_ids.clone();
+            let index = rng.gen_range(0..neighbour_ids.len());
+            neighbour_ids[index] = *replacements.choose(&mut rng).unwrap();
+
+            let neighbour = Self::ids_to_deck(&neighbour_ids);
+            if !deck_is_valid(&neighbour) {
+                continue;
+            }
+            let neighbour_score = objective.evaluate(&neighbour, &mut rng);
+            evaluations += 1;
+            if neighbour_score.estimate >= current_score.estimate {
+                current_ids = neighbour_ids;
+                current_score = neighbour_score;
+            }
+        }
+
+        let deck = Self::ids_to_deck(&current_ids);
+        let score = objective.evaluate(&deck, &mut rng);
+        Ok(OptimizedDeck {
+            deck,
+            score,
+            evaluations: evaluations + 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        duel::deck::{deck_size_is_valid, generate_random_deck},
+        simulate::random_strategy,
+    };
+
+    #[test]
+    fn test_optimize_deck() {
+        // 3 copies of the first 20 cards
+        let pool = (1..=20).map(|id| (id, 3)).collect::<HashMap<_, _>>();
+        let optimizer = DeckOptimizer {
+            pool,
+            iterations: 5,
+            seed: 0,
+        };
+
+        let result = optimizer
+            .optimize(&mut OpeningAttackObjective { samples: 5 })
+            .unwrap();
+        assert!(deck_is_valid(&result.deck));
+        assert!(deck_size_is_valid(&result.deck));
+        assert!(result.score.lower <= result.score.estimate);
+        assert!(result.score.estimate <= result.score.upper);
+
+        let result = optimizer
+            .optimize(&mut WinRateObjective {
+                opponent_deck: generate_random_deck(),
+                strategy: random_strategy(),
+                opponent_strategy: random_strategy(),
+                ruleset: Ruleset::default(),
+                duels: 4,
+                workers: 2,
+            })
+            .unwrap();
+        assert!(deck_is_valid(&result.deck));
+        assert!(result.score.lower >= 0.0 && result.score.upper <= 1.0);
+
+        let small_pool = DeckOptimizer {
+            pool: (1..=10).map(|id| (id, 3)).collect(),
+            iterations: 5,
+            seed: 0,
+        };
+        assert_eq!(
+            small_pool
+                .optimize(&mut OpeningAttackObjective { samples: 5 })
+                .unwrap_err(),
+            OptimizerError::NotEnoughCards { available: 30 }
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+use crate::{Card, CARDS};
+
+use super::DECK_SIZE;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DeckViolation {
+    #[error("Deck must contain exactly {expected} cards, found {actual}.")]
+    InvalidDeckSize { expected: usize, actual: usize },
+    #[error("Unknown card ID {card_id}.")]
+    UnknownCard { card_id: usize },
+    #[error("Card {card_id} appears {count} times, but at most {limit} copies are allowed.")]
+    TooManyCopies {
+        card_id: usize,
+        count: usize,
+        limit: usize,
+    },
+    #[error("Card {card_id} is banned.")]
+    BannedCard { card_id: usize },
+    #[error("Deck costs {cost} starchips, but the deck capacity is {capacity}.")]
+    DeckCapacityExceeded { cost: u64, capacity: u64 },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DeckRules {
+    // The exact number of cards a deck must hold. None allows any size.
+    pub deck_size: Option<usize>,
+    // How many copies of a card are allowed, unless overridden in copy_limits.
+    pub max_copies: usize,
+    // Per-card overrides of max_copies, keyed by card ID.
+    pub copy_limits: HashMap<usize, usize>,
+    // The maximum total cost of the deck, where each card costs its starchip price. None means no limit.
+    pub deck_capacity: Option<u64>,
+    pub banned_cards: HashSet<usize>,
+}
+
+impl Default for DeckRules {
+    fn default() -> Self {
+        Self::campaign()
+    }
+}
+
+impl DeckRules {
+    // The rules of the original game: 40 cards with at most 3 copies of each.
+    pub fn campaign() -> Self {
+        Self {
+            deck_size: Some(DECK_SIZE),
+            max_copies: 3,
+            copy_limits: HashMap::new(),
+            deck_capacity: None,
+            banned_cards: HashSet::new(),
+        }
+    }
+
+    // Campaign rules, but the strongest magic cards are limited to a single copy and Exodia is banned.
+    pub fn tournament() -> Self {
+        Self {
+            // Dark Hole, Raigeki, Swords of Revealing Light, Megamorph, Crush Card, Harpie's Feather Duster
+            copy_limits: [336, 337, 348, 657, 661, 672]
+                .into_iter()
+                .map(|card_id| (card_id, 1))
+                .collect(),
+            // The 5 pieces of Exodia
+            banned_cards: (17..=21).collect(),
+            ..Self::campaign()
+        }
+    }
+
+    pub fn copy_limit(&self, card_id: usize) -> usize {
+        if self.banned_cards.contains(&card_id) {
+            0
+        } else {
+            *self.copy_limits.get(&card_id).unwrap_or(&self.max_copies)
+        }
+    }
+
+    pub fn deck_cost(deck: &[Card]) -> u64 {
+        deck.iter().map(|card| card.stars as u64).sum()
+    }
+
+    pub fn check(&self, deck: &[Card]) -> Vec<DeckViolation> {
+        let mut violations = Vec::new();
+
+        if let Some(expected) = self.deck_size {
+            if deck.len() != expected {
+                violations.push(DeckViolation::InvalidDeckSize {
+                    expected,
+                    actual: deck.len(),
+                });
+            }
+        }
+
+        // Count the copies of each card, keeping the order in which cards first appear so violations are reported in deck order.
+        let mut card_counts: HashMap<usize, usize> = HashMap::new();
+        let mut card_order = Vec::new();
+        for card in deck {
+            let count = card_counts.entry(card.id).or_insert(0);
+            if *count == 0 {
+                card_order.push(card.id);
+            }
+            *count += 1;
+        }
+
+        for card_id in card_order {
+            if card_id == 0 || card_id > CARDS.len() {
+                violations.push(DeckViolation::UnknownCard { card_id });
+            } else if self.banned_cards.contains(&card_id) {
+                violations.push(DeckViolation::BannedCard { card_id });
+            } else {
+                let count = card_counts[&card_id];
+                let limit = self.copy_limit(card_id);
+                if count > limit {
+                    violations.push(DeckViolation::TooManyCopies {
+                        card_id,
+                        count,
+                        limit,
+                    });
+                }
+            }
+        }
+
+        if let Some(capacity) = self.deck_capacity {
+            let cost = Self::deck_cost(deck);
+            if cost > capacity {
+                violations.push(DeckViolation::DeckCapacityExceeded { cost, capacity });
+            }
+        }
+
+        violations
+    }
+
+    pub fn is_valid(&self, deck: &[Card]) -> bool {
+        self.check(deck).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card_from_name, duel::deck::generate_random_deck};
+
+    #[test]
+    fn test_deck_rules_violations() {
+        let deck = generate_random_deck();
+        assert!(DeckRules::campaign().is_valid(&deck));
+
+        let mut bad_card = card_from_name("Thunder Dragon");
+        bad_card.id = 9999;
+        let mut deck = vec![card_from_name("Raigeki"); 4];
+        deck.push(card_from_name("Exodia the Forbidden"));
+        deck.push(bad_card);
+
+        let violations = DeckRules::tournament().check(&deck);
+        assert_eq!(
+            violations,
+            vec![
+                DeckViolation::InvalidDeckSize {
+                    expected: 40,
+                    actual: 6
+                },
+                DeckViolation::TooManyCopies {
+                    card_id: 337,
+                    count: 4,
+                    limit: 1
+                },
+                DeckViolation::BannedCard { card_id: 21 },
+                DeckViolation::UnknownCard { card_id: 9999 },
+            ]
+        );
+
+        let rules = DeckRules {
+            deck_size: None,
+            deck_capacity: Some(1000),
+            ..DeckRules::campaign()
+        };
+        assert!(matches!(
+            rules.check(&deck[0..3]).as_slice(),
+            [DeckViolation::DeckCapacityExceeded { .. }]
+        ));
+    }
+}
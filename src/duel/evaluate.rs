@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{guardian_star_relation, AdvantageRelation, CardVariant, Duel, Duelist};
+
+use super::{player::Player, PlayerEnum};
+
+// Scores a duel from the point of view of one player, where higher is better for that player.
+// Search strategies only evaluate duels that are not over yet, since finished duels are scored by their winner.
+pub trait Evaluator {
+    fn evaluate(&self, duel: &Duel, player: PlayerEnum) -> f64;
+}
+
+// The things a LinearEvaluator looks at, each as the difference between the player and the enemy.
+// The same struct holds the weights, so a weight has the same name as its feature.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct BoardFeatures {
+    pub life_points: f64,
+    // The attack and defense of the monsters on the field, including the terrain.
+    pub attack: f64,
+    pub defense: f64,
+    // How many pairs of a player's monster and an enemy monster have a guardian star advantage, minus those with a disadvantage.
+    pub guardian_star: f64,
+    pub set_traps: f64,
+    // Cards in the hand or on the field.
+    pub cards: f64,
+    // How many cards the deck is short of the critical deck size.
+    pub deck_shortfall: f64,
+    // Different pieces of Exodia in the hand.
+    pub exodia_pieces: f64,
+}
+
+// The cards with IDs 17-21 are the 5 pieces of Exodia, see exodia_check.
+fn exodia_pieces(player: &Player) -> usize {
+    player
+        .hand
+        .iter()
+        .filter(|card| (17..=21).contains(&card.id))
+        .map(|card| card.id)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+impl BoardFeatures {
+    pub fn new(duel: &Duel, player: PlayerEnum, critical_deck_size: usize) -> Self {
+        let own = Self::side(duel, duel.get_player_by_enum(player), critical_deck_size);
+        let enemy = Self::side(duel, duel.get_enemy_by_enum(player), critical_deck_size);
+        let mut features = Self::from_array(std::array::from_fn(|index| {
+            own.to_array()[index] - enemy.to_array()[index]
+        }));
+
+        let own_monsters = duel.get_player_by_enum(player).monster_row.iter().flatten();
+        for own_monster in own_monsters {
+            for enemy_monster in duel.get_enemy_by_enum(player).monster_row.iter().flatten() {
+                features.guardian_star += match guardian_star_relation(
+                    own_monster.get_selected_gs(),
+                    enemy_monster.get_selected_gs(),
+                ) {
+                    AdvantageRelation::Advantaged => 1.0,
+                    AdvantageRelation::Disadvantaged => -1.0,
+                    AdvantageRelation::Neutral => 0.0,
+                };
+            }
+        }
+        features
+    }
+
+    // The features of one player on their own, without the guardian star matchups.
+    fn side(duel: &Duel, player: &Player, critical_deck_size: usize) -> Self {
+        let mut side = Self {
+            life_points: player.life_points as f64,
+            cards: player.hand.len() as f64,
+            deck_shortfall: critical_deck_size.saturating_sub(player.deck.len()) as f64,
+            exodia_pieces: exodia_pieces(player) as f64,
+            ..Default::default()
+        };
+        for monster in player.monster_row.iter().flatten() {
+            let (attack, defense) = monster
+                .card
+                .get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus)
+                .unwrap();
+            side.attack += attack.max(0) as f64;
+            side.defense += defense.max(0) as f64;
+            side.cards += 1.0;
+        }
+        for spell in player.spell_row.iter().flatten() {
+            if matches!(spell.card.variant, CardVariant::Trap(_)) {
+                side.set_traps += 1.0;
+            }
+            side.cards += 1.0;
+        }
+        side
+    }
+
+    fn to_array(&self) -> [f64; 8] {
+        [
+            self.life_points,
+            self.attack,
+            self.defense,
+            self.guardian_star,
+            self.set_traps,
+            self.cards,
+            self.deck_shortfall,
+            self.exodia_pieces,
+        ]
+    }
+
+    fn from_array(
+        [life_points, attack, defense, guardian_star, set_traps, cards, deck_shortfall, exodia_pieces]: [f64; 8],
+    ) -> Self {
+        Self {
+            life_points,
+            attack,
+            defense,
+            guardian_star,
+            set_traps,
+            cards,
+            deck_shortfall,
+            exodia_pieces,
+        }
+    }
+
+    pub fn dot(&self, weights: &Self) -> f64 {
+        self.to_array()
+            .iter()
+            .zip(weights.to_array())
+            .map(|(feature, weight)| feature * weight)
+            .sum()
+    }
+
+    // Weights for the material on both sides only: life points, monster stats and cards.
+    pub fn material_weights() -> Self {
+        Self {
+            life_points: 1.0,
+            attack: 0.5,
+            defense: 0.25,
+            cards: 300.0,
+            ..Default::default()
+        }
+    }
+
+    // Weights that were picked by hand rather than tuned, and the starting point for tuning.
+    pub fn hand_tuned_weights() -> Self {
+        Self {
+            life_points: 1.0,
+            attack: 0.5,
+            defense: 0.25,
+            guardian_star: 250.0,
+            set_traps: 300.0,
+            cards: 200.0,
+            deck_shortfall: -300.0,
+            exodia_pieces: 500.0,
+        }
+    }
+}
+
+// Scores a duel as a weighted sum of its BoardFeatures.
+// The weights can be loaded from JSON, e.g. after tuning them from self-play data. A weight missing from the weights in
+// the JSON is 0, and JSON without any weights uses the hand-tuned weights.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct LinearEvaluator {
+    pub weights: BoardFeatures,
+    // The deck size below which a player is considered to be close to decking out.
+    pub critical_deck_size: usize,
+}
+
+impl Default for LinearEvaluator {
+    fn default() -> Self {
+        Self {
+            weights: BoardFeatures::hand_tuned_weights(),
+            critical_deck_size: 10,
+        }
+    }
+}
+
+impl LinearEvaluator {
+    pub fn material() -> Self {
+        Self {
+            weights: BoardFeatures::material_weights(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+impl Evaluator for LinearEvaluator {
+    fn evaluate(&self, duel: &Duel, player: PlayerEnum) -> f64 {
+        BoardFeatures::new(duel, player, self.critical_deck_size).dot(&self.weights)
+    }
+}
+
+// The default evaluator for AIs. It uses the hand-tuned weights, except that pieces of Exodia are worth more
+// the closer a player is to holding all 5, since a single piece on its own is almost worthless.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct HandTunedEvaluator {
+    pub critical_deck_size: usize,
+}
+
+impl Default for HandTunedEvaluator {
+    fn default() -> Self {
+        Self {
+            critical_deck_size: 10,
+        }
+    }
+}
+
+impl HandTunedEvaluator {
+    // Uses the critical deck size of an enemy duelist, e.g. Teana worries about decking out sooner than Jono.
+    pub fn for_duelist(duelist: &Duelist) -> Self {
+        Self {
+            critical_deck_size: duelist.critical_deck_size as usize,
+        }
+    }
+}
+
+impl Evaluator for HandTunedEvaluator {
+    fn evaluate(&self, duel: &Duel, player: PlayerEnum) -> f64 {
+        let weights = BoardFeatures {
+            exodia_pieces: 0.0,
+            ..BoardFeatures::hand_tuned_weights()
+        };
+        let exodia_value = |player: &Player| 100.0 * exodia_pieces(player).pow(2) as f64;
+        BoardFeatures::new(duel, player, self.critical_deck_size).dot(&weights)
+            + exodia_value(duel.get_player_by_enum(player))
+            - exodia_value(duel.get_enemy_by_enum(player))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card_from_name, duelist_from_name};
+
+    #[test]
+    fn test_material_evaluator() {
+        let mut duel = Duel::random();
+        let evaluator = LinearEvaluator::material();
+        let before = evaluator.evaluate(&duel, PlayerEnum::Player1);
+        dbg!(before);
+        // the game is zero-sum
+        assert_eq!(evaluator.evaluate(&duel, PlayerEnum::Player2), -before);
+
+        duel.player2.life_points -= 1000;
+        assert_eq!(
+            evaluator.evaluate(&duel, PlayerEnum::Player1),
+            before + 1000.0
+        );
+    }
+
+    #[test]
+    fn test_linear_evaluator() {
+        let mut duel = Duel::random();
+        let thunder_dragon = card_from_name("Thunder Dragon");
+        duel.player1.hand = vec![
+            card_from_name("Exodia the Forbidden"),
+            card_from_name("Right Leg of the Forbidden One"),
+            thunder_dragon,
+            thunder_dragon,
+            thunder_dragon,
+        ];
+        duel.player2.hand = vec![thunder_dragon; 5];
+
+        let features = BoardFeatures::new(&duel, PlayerEnum::Player1, 40);
+        dbg!(&features);
+        assert_eq!(features.exodia_pieces, 2.0);
+        assert_eq!(features.life_points, 0.0);
+        // only player 1 has drawn a hand so far
+        assert_eq!(features.deck_shortfall, 5.0);
+
+        // weights missing from the weights in the JSON are 0
+        let evaluator =
+            LinearEvaluator::from_json(r#"{ "weights": { "exodia_pieces": 1.0 } }"#).unwrap();
+        assert_eq!(evaluator.evaluate(&duel, PlayerEnum::Player1), 2.0);
+        // and without any weights, the hand-tuned weights are used
+        let evaluator = LinearEvaluator::from_json(r#"{ "critical_deck_size": 40 }"#).unwrap();
+        assert_eq!(evaluator.weights, BoardFeatures::hand_tuned_weights());
+        assert_eq!(evaluator.critical_deck_size, 40);
+        assert!(LinearEvaluator::from_json(r#"{ "weights": { "attack": "high" } }"#).is_err());
+
+        // a third piece of Exodia is worth more than the second
+        let hand_tuned = HandTunedEvaluator::for_duelist(duelist_from_name("Teana"));
+        let two_pieces = hand_tuned.evaluate(&duel, PlayerEnum::Player1);
+        duel.player1.hand[2] = card_from_name("Left Leg of the Forbidden One");
+        let three_pieces = hand_tuned.evaluate(&duel, PlayerEnum::Player1);
+        assert!(three_pieces - two_pieces > 400.0);
+    }
+}
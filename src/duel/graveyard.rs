@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{equip, fuse_with_rules, Card, CardVariant};
+
+use super::{field::MonsterRowPosition, player::Player};
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
+pub enum DiscardReason {
+    Battle,         // Destroyed in battle.
+    FusionMaterial, // Used as a material in a fusion.
+    MagicEffect,    // Destroyed by a magic card, e.g. Raigeki.
+    Trap,           // Destroyed by a trap, e.g. Acid Trap Hole.
+    RitualTribute,  // Tributed to a ritual.
+    Activated,      // A magic, equip, ritual or trap card that was used up.
+    Overwritten,    // Replaced by another card that it could not fuse or equip with.
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct GraveyardEntry {
+    pub card: Card,
+    pub reason: DiscardReason,
+}
+
+impl Player {
+    pub fn discard(&mut self, card: Card, reason: DiscardReason) {
+        self.graveyard.push(GraveyardEntry { card, reason });
+    }
+
+    // Sends the monster at the index to the graveyard, if there is one.
+    pub fn discard_monster(&mut self, index: usize, reason: DiscardReason) {
+        if let Some(monster) = self.monster_row[index].take() {
+            self.discard(monster.card, reason);
+        }
+    }
+
+    // Sends the spell at the index to the graveyard, if there is one.
+    pub fn discard_spell(&mut self, index: usize, reason: DiscardReason) {
+        if let Some(spell) = self.spell_row[index].take() {
+            self.discard(spell.card, reason);
+        }
+    }
+
+    pub fn discard_monsters_where<F: Fn(&MonsterRowPosition) -> bool>(
+        &mut self,
+        predicate: F,
+        reason: DiscardReason,
+    ) {
+        for index in 0..self.monster_row.len() {
+            if self.monster_row[index].as_ref().is_some_and(&predicate) {
+                self.discard_monster(index, reason);
+            }
+        }
+    }
+
+    pub fn discard_all_monsters(&mut self, reason: DiscardReason) {
+        self.discard_monsters_where(|_| true, reason);
+    }
+
+    pub fn discard_all_spells(&mut self, reason: DiscardReason) {
+        for index in 0..self.spell_row.len() {
+            self.discard_spell(index, reason);
+        }
+    }
+
+    // Sends the cards used up by one step of combine_with_rules() to the graveyard.
+    pub fn discard_combination_step(
+        &mut self,
+        card1: &Card,
+        card2: &Card,
+        result: &Card,
+        glitch_fusions: bool,
+    ) {
+        let (entries, created) = combination_step_discards(card1, card2, result, glitch_fusions);
+        self.graveyard.extend(entries);
+        if created {
+            self.cards_created += 1;
+        }
+    }
+}
+
+// The cards used up by one step of combine_with_rules(), and whether the step created a new card.
+// A fusion uses up both cards and creates a new one. An equip only uses up the equip card.
+// Otherwise the combination keeps one of the two cards, and the other one is overwritten.
+pub fn combination_step_discards(
+    card1: &Card,
+    card2: &Card,
+    result: &Card,
+    glitch_fusions: bool,
+) -> (Vec<GraveyardEntry>, bool) {
+    let entry = |card: &Card, reason| GraveyardEntry {
+        card: *card,
+        reason,
+    };
+    if fuse_with_rules(card1, card2, glitch_fusions).is_some() {
+        (
+            vec![
+                entry(card1, DiscardReason::FusionMaterial),
+                entry(card2, DiscardReason::FusionMaterial),
+            ],
+            true,
+        )
+    } else if equip(card1, card2).is_some() {
+        let equip_card = match card1.variant {
+            CardVariant::Equip { .. } => card1,
+            _ => card2,
+        };
+        (vec![entry(equip_card, DiscardReason::Activated)], false)
+    } else if result == card2 {
+        (vec![entry(card1, DiscardReason::Overwritten)], false)
+    } else {
+        (vec![entry(card2, DiscardReason::Overwritten)], false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name, combine,
+        duel::{
+            command::{DuelCommand, HandPlayMultipleCmd},
+            field::{CardMode, FaceDirection, GuardianStarChoice},
+            state::DuelStateEnum,
+        },
+        Duel,
+    };
+
+    #[test]
+    fn test_discard_combination_step() {
+        let mut duel = Duel::random();
+        let player = &mut duel.player1;
+        let graveyard_size = player.graveyard.len();
+
+        // Thunder Dragon + Thunder Dragon = Twin-headed Thunder Dragon
+        let thunder_dragon = card_from_name("Thunder Dragon");
+        let result = combine(&thunder_dragon, &thunder_dragon);
+        player.discard_combination_step(&thunder_dragon, &thunder_dragon, &result, true);
+        assert_eq!(player.graveyard.len(), graveyard_size + 2);
+        assert_eq!(player.cards_created, 1);
+        assert!(player
+            .graveyard
+            .iter()
+            .all(|entry| entry.reason == DiscardReason::FusionMaterial));
+
+        // Megamorph is used up, but the monster survives
+        let megamorph = card_from_name("Megamorph");
+        let equipped = combine(&result, &megamorph);
+        player.discard_combination_step(&result, &megamorph, &equipped, true);
+        let entry = player.graveyard.last().unwrap();
+        assert_eq!(entry.card.name, "Megamorph");
+        assert_eq!(entry.reason, DiscardReason::Activated);
+        assert_eq!(player.cards_created, 1);
+
+        player.monster_row[0] = Some(MonsterRowPosition {
+            card: equipped,
+            face_direction: FaceDirection::Up,
+            card_mode: CardMode::Attack,
+            guardian_star_choice: GuardianStarChoice::A,
+            disabled: false,
+        });
+        player.discard_all_monsters(DiscardReason::MagicEffect);
+        assert!(player.monster_row.iter().all(|monster| monster.is_none()));
+        assert_eq!(player.graveyard.len(), graveyard_size + 4);
+    }
+
+    // A fusion onto an occupied slot that ends in a magic card uses up the monster in the slot
+    #[test]
+    fn test_fusion_onto_occupied_slot_into_magic() {
+        let mut duel = Duel::random();
+        let harpie_lady = card_from_name("Harpie Lady");
+        let player = duel.get_player_mut();
+        player.life_points = 1000;
+        player.hand[0] = harpie_lady;
+        player.hand[1] = card_from_name("Goblin's Secret Remedy");
+        player.monster_row[0] = Some(MonsterRowPosition {
+            card: harpie_lady,
+            face_direction: FaceDirection::Up,
+            card_mode: CardMode::Attack,
+            guardian_star_choice: GuardianStarChoice::A,
+            disabled: false,
+        });
+        let before = duel.clone();
+
+        // Harpie Lady + Harpie Lady = Harpie's Feather Duster, a glitch fusion, which Goblin's Secret Remedy overwrites
+        HandPlayMultipleCmd {
+            hand_indices: vec![0, 1],
+            field_index: 0,
+        }
+        .execute(&mut duel)
+        .unwrap();
+        let player = duel.get_player();
+        dbg!(&player.graveyard);
+        assert!(player.monster_row[0].is_none());
+        assert_eq!(player.life_points, 2000);
+        let reasons = player.graveyard[before.get_player().graveyard.len()..]
+            .iter()
+            .map(|entry| (entry.card.name.as_str(), entry.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                ("Harpie Lady", DiscardReason::FusionMaterial),
+                ("Harpie Lady", DiscardReason::FusionMaterial),
+                ("Harpie's Feather Duster", DiscardReason::Overwritten),
+                ("Goblin's Secret Remedy", DiscardReason::Activated),
+            ]
+        );
+        assert!(matches!(duel.state, DuelStateEnum::FieldState(_)));
+        // no card was lost or duplicated
+        assert!(Duel::check_transition(&before, &duel).is_ok());
+    }
+}
@@ -0,0 +1,425 @@
+use crate::{Card, Duel, TerrainType};
+
+use super::{
+    field::{MonsterRowPosition, SpellRowPosition},
+    state::DuelStateEnum,
+    PlayerEnum,
+};
+
+// A part of the duel that contributes to its state hash.
+// The hash of a duel is the XOR of the keys of all its features, as in Zobrist hashing. state_hash() computes it from scratch,
+// and updated_state_hash() updates the hash of the duel before a command by swapping the keys of the features that changed.
+// The deck only contributes its size, since the order is hidden, and the graveyard not at all, since it does not affect the play.
+// Searches that know the order of the decks add their DeckCard features with deck_order_hash().
+#[derive(Debug, Clone, Copy)]
+pub enum Feature<'a> {
+    HandCard {
+        player: PlayerEnum,
+        index: usize,
+        card: Card,
+    },
+    MonsterRow {
+        player: PlayerEnum,
+        index: usize,
+        position: &'a MonsterRowPosition,
+    },
+    SpellRow {
+        player: PlayerEnum,
+        index: usize,
+        position: &'a SpellRowPosition,
+    },
+    LifePoints {
+        player: PlayerEnum,
+        life_points: u32,
+    },
+    DeckSize {
+        player: PlayerEnum,
+        size: usize,
+    },
+    SorlCountdown {
+        player: PlayerEnum,
+        countdown: Option<u32>,
+    },
+    Terrain(TerrainType),
+    Turn(u32),
+    State(&'a DuelStateEnum),
+    DeckCard {
+        player: PlayerEnum,
+        index: usize,
+        card: Card,
+    },
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Zobrist hashing usually draws a random key for every feature from a table.
+// Mixing the feature instead gives the same random-looking keys without a table, so any hand size or row size is covered.
+fn mix(parts: &[u64]) -> u64 {
+    parts.iter().fold(0, |hash, &part| splitmix64(hash ^ part))
+}
+
+fn card_parts(card: &Card) -> [u64; 2] {
+    [card.id as u64, card.stat_delta as u64]
+}
+
+fn monster_parts(position: &MonsterRowPosition) -> [u64; 6] {
+    let [id, stat_delta] = card_parts(&position.card);
+    [
+        id,
+        stat_delta,
+        position.face_direction as u64,
+        position.card_mode as u64,
+        position.guardian_star_choice as u64,
+        position.disabled as u64,
+    ]
+}
+
+impl Feature<'_> {
+    pub fn key(&self) -> u64 {
+        match *self {
+            Feature::HandCard {
+                player,
+                index,
+                card,
+            } => {
+                let [id, stat_delta] = card_parts(&card);
+                mix(&[0, player as u64, index as u64, id, stat_delta])
+            }
+            Feature::MonsterRow {
+                player,
+                index,
+                position,
+            } => {
+                let [id, stat_delta, face, mode, guardian_star, disabled] = monster_parts(position);
+                mix(&[
+                    1,
+                    player as u64,
+                    index as u64,
+                    id,
+                    stat_delta,
+                    face,
+                    mode,
+                    guardian_star,
+                    disabled,
+                ])
+            }
+            Feature::SpellRow {
+                player,
+                index,
+                position,
+            } => {
+                let [id, stat_delta] = card_parts(&position.card);
+                mix(&[
+                    2,
+                    player as u64,
+                    index as u64,
+                    id,
+                    stat_delta,
+                    position.face_direction as u64,
+                ])
+            }
+            Feature::LifePoints {
+                player,
+                life_points,
+            } => mix(&[3, player as u64, life_points as u64]),
+            Feature::DeckSize { player, size } => mix(&[4, player as u64, size as u64]),
+            Feature::SorlCountdown { player, countdown } => mix(&[
+                5,
+                player as u64,
+                countdown.map_or(u64::MAX, |countdown| countdown as u64),
+            ]),
+            Feature::Terrain(terrain_type) => mix(&[6, terrain_type as u64]),
+            Feature::Turn(turn) => mix(&[7, turn as u64]),
+            Feature::State(state) => match state {
+                DuelStateEnum::HandState(_) => mix(&[8, 0]),
+                DuelStateEnum::FieldState(_) => mix(&[8, 1]),
+                DuelStateEnum::SetGuardianStarState(state) => {
+                    let [id, stat_delta, face, mode, guardian_star, disabled] =
+                        monster_parts(&state.monster_row_position);
+                    mix(&[
+                        8,
+                        2,
+                        id,
+                        stat_delta,
+                        face,
+                        mode,
+                        guardian_star,
+                        disabled,
+                        state.monster_row_index as u64,
+                        state
+                            .applied_equips_amount
+                            .map_or(u64::MAX, |amount| amount as u64),
+                    ])
+                }
+                DuelStateEnum::EndState(state) => {
+                    mix(&[8, 3, state.winner as u64, state.win_condition as u64])
+                }
+            },
+            Feature::DeckCard {
+                player,
+                index,
+                card,
+            } => {
+                let [id, stat_delta] = card_parts(&card);
+                mix(&[9, player as u64, index as u64, id, stat_delta])
+            }
+        }
+    }
+}
+
+impl Duel {
+    // Calls f with every feature of the duel.
+    pub fn for_each_feature<'a>(&'a self, mut f: impl FnMut(Feature<'a>)) {
+        for player in [PlayerEnum::Player1, PlayerEnum::Player2] {
+            let player_data = self.get_player_by_enum(player);
+            for (index, &card) in player_data.hand.iter().enumerate() {
+                f(Feature::HandCard {
+                    player,
+                    index,
+                    card,
+                });
+            }
+            for (index, position) in player_data.monster_row.iter().enumerate() {
+                if let Some(position) = position {
+                    f(Feature::MonsterRow {
+                        player,
+                        index,
+                        position,
+                    });
+                }
+            }
+            for (index, position) in player_data.spell_row.iter().enumerate() {
+                if let Some(position) = position {
+                    f(Feature::SpellRow {
+                        player,
+                        index,
+                        position,
+                    });
+                }
+            }
+            f(Feature::LifePoints {
+                player,
+                life_points: player_data.life_points,
+            });
+            f(Feature::DeckSize {
+                player,
+                size: player_data.deck.len(),
+            });
+            f(Feature::SorlCountdown {
+                player,
+                countdown: player_data.sorl_effect_countdown,
+            });
+        }
+        f(Feature::Terrain(self.terrain_type));
+        f(Feature::Turn(self.turn));
+        f(Feature::State(&self.state));
+    }
+
+    // A 64-bit hash of everything that affects the rest of the duel, for use in transposition tables.
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = 0;
+        self.for_each_feature(|feature| hash ^= feature.key());
+        hash
+    }
+
+    // The state hash of the duel after a command, from the duel before it and its state hash.
+    // Only the features that changed are hashed, so it is cheaper than state_hash() when the duel before is at hand anyway,
+    // e.g. in a search, which executes every command on a copy of the duel.
+    pub fn updated_state_hash(&self, before: &Duel, before_hash: u64) -> u64 {
+        let mut hash = before_hash;
+        let mut swap = |old: Option<Feature>, new: Option<Feature>| {
+            hash ^= old.map_or(0, |feature| feature.key()) ^ new.map_or(0, |feature| feature.key());
+        };
+
+        for player in [PlayerEnum::Player1, PlayerEnum::Player2] {
+            let (old, new) = (
+                before.get_player_by_enum(player),
+                self.get_player_by_enum(player),
+            );
+            for index in 0..old.hand.len().max(new.hand.len()) {
+                let (old_card, new_card) = (old.hand.get(index), new.hand.get(index));
+                if old_card != new_card {
+                    let feature = |card: Option<&Card>| {
+                        card.map(|&card| Feature::HandCard {
+                            player,
+                            index,
+                            card,
+                        })
+                    };
+                    swap(feature(old_card), feature(new_card));
+                }
+            }
+            for index in 0..old.monster_row.len().max(new.monster_row.len()) {
+                let (old_position, new_position) = (
+                    old.monster_row.get(index).and_then(Option::as_ref),
+                    new.monster_row.get(index).and_then(Option::as_ref),
+                );
+                if old_position != new_position {
+                    let feature = |position| {
+                        Some(Feature::MonsterRow {
+                            player,
+                            index,
+                            position,
+                        })
+                    };
+                    swap(
+                        old_position.and_then(feature),
+                        new_position.and_then(feature),
+                    );
+                }
+            }
+            for index in 0..old.spell_row.len().max(new.spell_row.len()) {
+                let (old_position, new_position) = (
+                    old.spell_row.get(index).and_then(Option::as_ref),
+                    new.spell_row.get(index).and_then(Option::as_ref),
+                );
+                if old_position != new_position {
+                    let feature = |position| {
+                        Some(Feature::SpellRow {
+                            player,
+                            index,
+                            position,
+                        })
+                    };
+                    swap(
+                        old_position.and_then(feature),
+                        new_position.and_then(feature),
+                    );
+                }
+            }
+            if old.life_points != new.life_points {
+                let feature = |life_points| Feature::LifePoints {
+                    player,
+                    life_points,
+                };
+                swap(
+                    Some(feature(old.life_points)),
+                    Some(feature(new.life_points)),
+                );
+            }
+            if old.deck.len() != new.deck.len() {
+                let feature = |size| Feature::DeckSize { player, size };
+                swap(Some(feature(old.deck.len())), Some(feature(new.deck.len())));
+            }
+            if old.sorl_effect_countdown != new.sorl_effect_countdown {
+                let feature = |countdown| Feature::SorlCountdown { player, countdown };
+                swap(
+                    Some(feature(old.sorl_effect_countdown)),
+                    Some(feature(new.sorl_effect_countdown)),
+                );
+            }
+        }
+        if before.terrain_type != self.terrain_type {
+            swap(
+                Some(Feature::Terrain(before.terrain_type)),
+                Some(Feature::Terrain(self.terrain_type)),
+            );
+        }
+        if before.turn != self.turn {
+            swap(
+                Some(Feature::Turn(before.turn)),
+                Some(Feature::Turn(self.turn)),
+            );
+        }
+        if before.state != self.state {
+            swap(
+                Some(Feature::State(&before.state)),
+                Some(Feature::State(&self.state)),
+            );
+        }
+        hash
+    }
+
+    // The order of both decks, to XOR into the state hash when the cards that will be drawn are known,
+    // e.g. for a search with perfect information.
+    pub fn deck_order_hash(&self) -> u64 {
+        let mut hash = 0;
+        for player in [PlayerEnum::Player1, PlayerEnum::Player2] {
+            for (index, &card) in self.get_player_by_enum(player).deck.iter().enumerate() {
+                hash ^= Feature::DeckCard {
+                    player,
+                    index,
+                    card,
+                }
+                .key();
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::command_strategy::{CommandStrategy, SeededRandomCommandStrategy};
+    use test::Bencher;
+
+    #[test]
+    fn test_state_hash() {
+        let duel = Duel::random();
+        let hash = duel.state_hash();
+        assert_eq!(duel.clone().state_hash(), hash);
+
+        // changing a feature swaps its key
+        let mut damaged = duel.clone();
+        damaged.player2.life_points = 7000;
+        let updated = hash
+            ^ Feature::LifePoints {
+                player: PlayerEnum::Player2,
+                life_points: 8000,
+            }
+            .key()
+            ^ Feature::LifePoints {
+                player: PlayerEnum::Player2,
+                life_points: 7000,
+            }
+            .key();
+        assert_eq!(damaged.state_hash(), updated);
+        assert_ne!(updated, hash);
+
+        // the order of the deck is only in deck_order_hash
+        let mut shuffled = duel.clone();
+        shuffled.player1.deck.reverse();
+        assert_eq!(shuffled.state_hash(), hash);
+        assert_ne!(shuffled.deck_order_hash(), duel.deck_order_hash());
+
+        // commands with the same outcome reach the same hash, regardless of the order of the graveyard
+        for group in duel.generate_canonical_commands() {
+            let mut expected = None;
+            for command in group.equivalent(&duel) {
+                let mut after = duel.clone();
+                after.execute(&command).unwrap();
+                let after_hash = after.state_hash();
+                assert_eq!(*expected.get_or_insert(after_hash), after_hash);
+            }
+        }
+    }
+
+    // updating the hash after every command of a duel gives the same hash as computing it again
+    #[test]
+    fn test_updated_state_hash() {
+        let mut duel = Duel::random();
+        let mut hash = duel.state_hash();
+        let strategy = SeededRandomCommandStrategy::new(0);
+        for _ in 0..500 {
+            if matches!(duel.state, DuelStateEnum::EndState(_)) {
+                break;
+            }
+            let before = duel.clone();
+            duel.execute(&strategy.get_command(&duel)).unwrap();
+            hash = duel.updated_state_hash(&before, hash);
+            assert_eq!(hash, duel.state_hash());
+        }
+    }
+
+    #[bench]
+    fn bench_state_hash(b: &mut Bencher) {
+        let duel = Duel::random();
+        b.iter(|| duel.state_hash());
+    }
+}
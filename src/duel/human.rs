@@ -0,0 +1,630 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{Card, CardVariant, Duel};
+
+use super::{
+    command::{CommandError, DuelCommand, DuelCommandEnum, ValidCommands},
+    command_strategy::{Budget, BudgetedStrategy, Decision},
+    field::{FaceDirection, GuardianStarChoice},
+    state::DuelStateEnum,
+};
+
+// The menus a human goes through to build a command, following the states of the CommandBuilder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Menu {
+    Hand,
+    HandSingle {
+        hand_index: usize,
+    },
+    HandSingleFacing {
+        hand_index: usize,
+        face_direction: FaceDirection,
+    },
+    // Cards are picked one at a time, in the order they are combined.
+    HandMultiple {
+        hand_indices: Vec<usize>,
+    },
+    HandMultiplePlace {
+        hand_indices: Vec<usize>,
+    },
+    Field,
+    FieldMonster {
+        monster_index: usize,
+    },
+    FieldEquip {
+        spell_index: usize,
+    },
+    GuardianStar,
+}
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Menu(Menu),
+    // The command built by the CommandBuilder, or the reason it was rejected.
+    Command(Result<DuelCommandEnum, CommandError>),
+}
+
+#[derive(Debug, Clone)]
+pub struct MenuOption {
+    pub label: String,
+    pub step: Step,
+}
+
+// A menu with only the options that lead to at least one valid command.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub menu: Menu,
+    pub title: String,
+    pub options: Vec<MenuOption>,
+}
+
+fn card_label(card: &Card, duel: &Duel) -> String {
+    match card.get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus) {
+        Some((attack, defense)) => format!("{} ({}/{})", card.name, attack, defense),
+        None => card.name.clone(),
+    }
+}
+
+fn position_label(index: usize) -> String {
+    format!("Position {}", index + 1)
+}
+
+impl Menu {
+    // The menu a human starts in for the state of the duel.
+    pub fn root(duel: &Duel) -> Self {
+        match duel.state {
+            DuelStateEnum::FieldState(_) => Menu::Field,
+            DuelStateEnum::SetGuardianStarState(_) => Menu::GuardianStar,
+            _ => Menu::Hand,
+        }
+    }
+
+    pub fn prompt(&self, duel: &Duel, valid: &ValidCommands) -> Prompt {
+        let player = duel.get_player();
+        let menu = |label: String, menu: Menu| MenuOption {
+            label,
+            step: Step::Menu(menu),
+        };
+        let command = |label: &str, command: Result<DuelCommandEnum, CommandError>| MenuOption {
+            label: label.to_string(),
+            step: Step::Command(command),
+        };
+        // HandPlayMultipleCmds are checked with any_hand_play_multiple, since a large hand has millions of them
+        let any = |predicate: &dyn Fn(&DuelCommandEnum) -> bool| valid.others().any(predicate);
+        let single = |hand_index: usize,
+                      face_direction: Option<FaceDirection>,
+                      field_index: Option<Option<usize>>| {
+            any(&|command| {
+                matches!(command, DuelCommandEnum::HandPlaySingleCmd(cmd)
+                    if cmd.hand_index == hand_index
+                        && face_direction.is_none_or(|face| cmd.face_direction == face)
+                        && field_index.is_none_or(|field_index| cmd.field_index == field_index))
+            })
+        };
+        let multiple = |prefix: &[usize]| valid.any_hand_play_multiple(prefix, None);
+
+        let (title, options) = match self {
+            Menu::Hand => {
+                let mut options = (0..player.hand.len())
+                    .filter(|&hand_index| single(hand_index, None, None))
+                    .map(|hand_index| {
+                        menu(
+                            card_label(&player.hand[hand_index], duel),
+                            Menu::HandSingle { hand_index },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if multiple(&[]) {
+                    options.push(menu(
+                        "Fuse cards".to_string(),
+                        Menu::HandMultiple {
+                            hand_indices: Vec::new(),
+                        },
+                    ));
+                }
+                ("Select a card to play.", options)
+            }
+            Menu::HandSingle { hand_index } => (
+                "Play the card face up or face down?",
+                [
+                    ("Face up", FaceDirection::Up),
+                    ("Face down", FaceDirection::Down),
+                ]
+                .into_iter()
+                .filter(|&(_, face_direction)| single(*hand_index, Some(face_direction), None))
+                .map(|(label, face_direction)| {
+                    menu(
+                        label.to_string(),
+                        Menu::HandSingleFacing {
+                            hand_index: *hand_index,
+                            face_direction,
+                        },
+                    )
+                })
+                .collect(),
+            ),
+            Menu::HandSingleFacing {
+                hand_index,
+                face_direction,
+            } => {
+                let builder = || {
+                    duel.command_builder()
+                        .hand()
+                        .and_then(|builder| builder.select(*hand_index))
+                        .map(|builder| builder.facing(*face_direction))
+                };
+                let mut options = Vec::new();
+                if single(*hand_index, Some(*face_direction), Some(None)) {
+                    options.push(command(
+                        "Activate",
+                        builder().and_then(|builder| builder.play()),
+                    ));
+                }
+                for field_index in 0..player.monster_row.len().max(player.spell_row.len()) {
+                    if single(*hand_index, Some(*face_direction), Some(Some(field_index))) {
+                        options.push(command(
+                            &position_label(field_index),
+                            builder().and_then(|builder| builder.place(field_index)),
+                        ));
+                    }
+                }
+                ("Select where to play the card.", options)
+            }
+            Menu::HandMultiple { hand_indices } => {
+                let mut options = (0..player.hand.len())
+                    .filter(|hand_index| !hand_indices.contains(hand_index))
+                    .map(|hand_index| {
+                        (
+                            hand_index,
+                            [hand_indices.as_slice(), &[hand_index]].concat(),
+                        )
+                    })
+                    .filter(|(_, selected)| multiple(selected))
+                    .map(|(hand_index, selected)| {
+                        menu(
+                            card_label(&player.hand[hand_index], duel),
+                            Menu::HandMultiple {
+                                hand_indices: selected,
+                            },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if hand_indices.len() >= 2 {
+                    options.push(menu(
+                        "Done".to_string(),
+                        Menu::HandMultiplePlace {
+                            hand_indices: hand_indices.clone(),
+                        },
+                    ));
+                }
+                ("Select the next card to fuse, in order.", options)
+            }
+            Menu::HandMultiplePlace { hand_indices } => (
+                "Select where to play the cards.",
+                (0..player.monster_row.len())
+                    .filter(|&field_index| {
+                        hand_indices.len() >= 2
+                            && valid.any_hand_play_multiple(hand_indices, Some(field_index))
+                    })
+                    .map(|field_index| {
+                        command(
+                            &position_label(field_index),
+                            duel.command_builder()
+                                .hand()
+                                .and_then(|builder| builder.select_multiple(hand_indices.clone()))
+                                .and_then(|builder| builder.place(field_index)),
+                        )
+                    })
+                    .collect(),
+            ),
+            Menu::Field => {
+                let mut options = Vec::new();
+                for (monster_index, monster) in player.monster_row.iter().enumerate() {
+                    let selectable = any(&|command| match command {
+                        DuelCommandEnum::FieldAttackCmd(cmd) => {
+                            cmd.monster_row_index == monster_index
+                        }
+                        DuelCommandEnum::FieldChangeModeCmd(cmd) => {
+                            cmd.monster_index == monster_index
+                        }
+                        _ => false,
+                    });
+                    if let (true, Some(monster)) = (selectable, monster) {
+                        options.push(menu(
+                            card_label(&monster.card, duel),
+                            Menu::FieldMonster { monster_index },
+                        ));
+                    }
+                }
+                for (spell_index, spell) in player.spell_row.iter().enumerate() {
+                    let Some(spell) = spell else { continue };
+                    if any(&|command| {
+                        matches!(command, DuelCommandEnum::FieldPlaySpellCmd(cmd)
+                            if cmd.spell_row_index == spell_index)
+                    }) {
+                        options.push(command(
+                            &format!("Activate {}", spell.card.name),
+                            duel.command_builder()
+                                .field()
+                                .and_then(|builder| builder.play_spell(spell_index)),
+                        ));
+                    }
+                    if any(&|command| {
+                        matches!(command, DuelCommandEnum::FieldPlayEquipCmd(cmd)
+                            if cmd.spell_row_index == spell_index)
+                    }) {
+                        options.push(menu(
+                            format!("Equip {}", spell.card.name),
+                            Menu::FieldEquip { spell_index },
+                        ));
+                    }
+                }
+                options.push(command(
+                    "End turn",
+                    duel.command_builder()
+                        .field()
+                        .map(|builder| builder.end_turn()),
+                ));
+                ("Select a card on the field, or end the turn.", options)
+            }
+            Menu::FieldMonster { monster_index } => {
+                let builder = || {
+                    duel.command_builder()
+                        .field()
+                        .and_then(|builder| builder.select_monster(*monster_index))
+                };
+                let enemy = duel.get_enemy();
+                let mut options = Vec::new();
+                for enemy_index in 0..enemy.monster_row.len() {
+                    if any(&|command| {
+                        matches!(command, DuelCommandEnum::FieldAttackCmd(cmd)
+                            if cmd.monster_row_index == *monster_index
+                                && cmd.enemy_monster_row_index == enemy_index)
+                    }) {
+                        let label = match &enemy.monster_row[enemy_index] {
+                            Some(monster) if monster.face_direction == FaceDirection::Up => {
+                                format!("Attack {}", card_label(&monster.card, duel))
+                            }
+                            Some(_) => format!(
+                                "Attack the face-down monster at {}",
+                                position_label(enemy_index)
+                            ),
+                            None => "Attack directly".to_string(),
+                        };
+                        options.push(command(
+                            &label,
+                            builder().and_then(|builder| builder.attack(enemy_index)),
+                        ));
+                    }
+                }
+                if any(&|command| {
+                    matches!(command, DuelCommandEnum::FieldChangeModeCmd(cmd)
+                        if cmd.monster_index == *monster_index)
+                }) {
+                    options.push(command(
+                        "Change mode",
+                        builder().map(|builder| builder.change_mode()),
+                    ));
+                }
+                ("Select what the monster does.", options)
+            }
+            Menu::FieldEquip { spell_index } => (
+                "Select the monster to equip.",
+                player
+                    .monster_row
+                    .iter()
+                    .enumerate()
+                    .filter(|(monster_index, _)| {
+                        any(&|command| {
+                            matches!(command, DuelCommandEnum::FieldPlayEquipCmd(cmd)
+                                if cmd.spell_row_index == *spell_index
+                                    && cmd.monster_row_index == *monster_index)
+                        })
+                    })
+                    .filter_map(|(monster_index, monster)| {
+                        let monster = monster.as_ref()?;
+                        Some(command(
+                            &card_label(&monster.card, duel),
+                            duel.command_builder().field().and_then(|builder| {
+                                builder.play_equip(*spell_index, monster_index)
+                            }),
+                        ))
+                    })
+                    .collect(),
+            ),
+            Menu::GuardianStar => {
+                let guardian_stars = match &duel.state {
+                    DuelStateEnum::SetGuardianStarState(state) => {
+                        match state.monster_row_position.card.variant {
+                            CardVariant::Monster {
+                                guardian_star_a,
+                                guardian_star_b,
+                                ..
+                            } => Some((guardian_star_a, guardian_star_b)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                let label = |choice: GuardianStarChoice| match (guardian_stars, choice) {
+                    (Some((a, _)), GuardianStarChoice::A) => format!("{:?}", a),
+                    (Some((_, b)), GuardianStarChoice::B) => format!("{:?}", b),
+                    (None, choice) => format!("{:?}", choice),
+                };
+                (
+                    "Select a guardian star.",
+                    [GuardianStarChoice::A, GuardianStarChoice::B]
+                        .into_iter()
+                        .map(|choice| {
+                            command(
+                                &label(choice),
+                                duel.command_builder().set_guardian_star(choice),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+        };
+
+        Prompt {
+            menu: self.clone(),
+            title: title.to_string(),
+            options,
+        }
+    }
+}
+
+// The menus a human went through to get to the current one, for front ends that react to input events
+// rather than asking an InputSource, e.g. the TUI.
+#[derive(Debug, Clone)]
+pub struct MenuStack {
+    valid: ValidCommands,
+    menus: Vec<Menu>,
+}
+
+impl MenuStack {
+    pub fn new(duel: &Duel) -> Self {
+        Self {
+            valid: duel.valid_commands(),
+            menus: vec![Menu::root(duel)],
+        }
+    }
+
+    pub fn menu(&self) -> &Menu {
+        self.menus.last().unwrap()
+    }
+
+    pub fn prompt(&self, duel: &Duel) -> Prompt {
+        self.menu().prompt(duel, &self.valid)
+    }
+
+    // Goes back to the previous menu, if this is not the root menu.
+    pub fn back(&mut self) {
+        if self.menus.len() > 1 {
+            self.menus.pop();
+        }
+    }
+
+    // Follows an option of the current menu. Returns the command once one is built and valid.
+    // When the command is rejected, the error is returned and the human starts over from the root menu.
+    // An index that is out of range does nothing.
+    pub fn choose(
+        &mut self,
+        duel: &Duel,
+        index: usize,
+    ) -> Option<Result<DuelCommandEnum, CommandError>> {
+        let option = self.prompt(duel).options.into_iter().nth(index)?;
+        match option.step {
+            Step::Menu(menu) => {
+                self.menus.push(menu);
+                None
+            }
+            Step::Command(command) => {
+                let checked =
+                    command.and_then(|command| command.check_valid(duel).map(|_| command));
+                if checked.is_err() {
+                    self.menus.truncate(1);
+                }
+                Some(checked)
+            }
+        }
+    }
+}
+
+// Where a human's choices come from, e.g. a terminal or a GUI.
+pub trait InputSource {
+    // The index of the chosen option, or None to go back to the previous menu.
+    // An index that is out of range asks again, and an error means no choice can be made anymore, e.g. the input was closed.
+    fn choose(&mut self, duel: &Duel, prompt: &Prompt) -> io::Result<Option<usize>>;
+
+    // Called when the CommandBuilder or the duel rejects the command that was built.
+    fn show_error(&mut self, error: &CommandError);
+
+    // Called with the duel after the enemy's command was executed.
+    fn show_enemy_command(&mut self, _duel: &Duel, _command: &DuelCommandEnum) {}
+}
+
+// A human player, who builds every command through the menus of an input source.
+pub struct HumanStrategy<I: InputSource> {
+    pub input: I,
+}
+
+impl<I: InputSource> HumanStrategy<I> {
+    pub fn new(input: I) -> Self {
+        Self { input }
+    }
+
+    pub fn choose_command(&mut self, duel: &Duel) -> io::Result<DuelCommandEnum> {
+        let mut menus = MenuStack::new(duel);
+        loop {
+            let prompt = menus.prompt(duel);
+            let Some(index) = self.input.choose(duel, &prompt)? else {
+                menus.back();
+                continue;
+            };
+            match menus.choose(duel, index) {
+                Some(Ok(command)) => return Ok(command),
+                Some(Err(error)) => self.input.show_error(&error),
+                None => {}
+            }
+        }
+    }
+}
+
+impl<I: InputSource> BudgetedStrategy for HumanStrategy<I> {
+    // Humans take as long as they like. A human whose input failed has left the duel, so the first valid command
+    // is played for them until it ends.
+    fn decide(&mut self, duel: &Duel, _budget: Budget) -> Decision {
+        let command = self
+            .choose_command(duel)
+            .unwrap_or_else(|_| duel.valid_commands().get(0).unwrap());
+        Decision::new(command)
+    }
+
+    fn on_enemy_command(&mut self, duel: &Duel, command: &DuelCommandEnum) {
+        self.input.show_enemy_command(duel, command);
+    }
+}
+
+// Numbered menus on a terminal, where 0 goes back.
+pub struct TerminalInput<R: BufRead, W: Write> {
+    pub reader: R,
+    pub writer: W,
+}
+
+impl TerminalInput<std::io::StdinLock<'static>, std::io::Stdout> {
+    pub fn stdio() -> Self {
+        Self {
+            reader: std::io::stdin().lock(),
+            writer: std::io::stdout(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> InputSource for TerminalInput<R, W> {
+    fn choose(&mut self, _duel: &Duel, prompt: &Prompt) -> io::Result<Option<usize>> {
+        loop {
+            writeln!(self.writer, "{}", prompt.title)?;
+            for (index, option) in prompt.options.iter().enumerate() {
+                writeln!(self.writer, "  {}. {}", index + 1, option.label)?;
+            }
+            writeln!(self.writer, "  0. Back")?;
+            self.writer.flush()?;
+
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The input was closed before a command was chosen.",
+                ));
+            }
+            match line.trim().parse::<usize>() {
+                Ok(0) => return Ok(None),
+                Ok(choice) if choice <= prompt.options.len() => return Ok(Some(choice - 1)),
+                _ => writeln!(
+                    self.writer,
+                    "Enter a number from 0 to {}.",
+                    prompt.options.len()
+                )?,
+            }
+        }
+    }
+
+    fn show_error(&mut self, error: &CommandError) {
+        writeln!(self.writer, "{}", error).unwrap();
+    }
+
+    fn show_enemy_command(&mut self, _duel: &Duel, command: &DuelCommandEnum) {
+        writeln!(self.writer, "The enemy played {:?}.", command).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name,
+        duel::command::{HandPlayMultipleCmd, HandPlaySingleCmd, SetGuardianStarCmd},
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_human_strategy() {
+        let mut duel = Duel::random();
+        duel.player1.hand = vec![card_from_name("Thunder Dragon"); 5];
+
+        // an invalid number asks again, and 0 goes back to the card selection
+        let input = TerminalInput {
+            reader: Cursor::new("9\n1\n0\n2\n1\n3\n2\n"),
+            writer: Vec::new(),
+        };
+        let mut human = HumanStrategy::new(input);
+        let command = human.choose_command(&duel).unwrap();
+        assert!(matches!(
+            command,
+            DuelCommandEnum::HandPlaySingleCmd(HandPlaySingleCmd {
+                hand_index: 1,
+                face_direction: FaceDirection::Up,
+                field_index: Some(2),
+            })
+        ));
+
+        duel.execute(&command).unwrap();
+        let command = human.choose_command(&duel).unwrap();
+        assert!(matches!(
+            command,
+            DuelCommandEnum::SetGuardianStarCmd(SetGuardianStarCmd {
+                guardian_star_choice: GuardianStarChoice::B,
+            })
+        ));
+
+        let output = String::from_utf8(human.input.writer).unwrap();
+        println!("{}", output);
+        assert!(output.contains("Enter a number from 0 to 6."));
+        assert!(output.contains("  6. Fuse cards"));
+        assert!(output.contains("Thunder Dragon (1600/1500)"));
+    }
+
+    // a closed input is an error rather than a panic, and the strategy plays on for the human who left
+    #[test]
+    fn test_human_strategy_closed_input() {
+        let duel = Duel::random();
+        let input = TerminalInput {
+            reader: Cursor::new("1\n"),
+            writer: Vec::new(),
+        };
+        let mut human = HumanStrategy::new(input);
+        let error = human.choose_command(&duel).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let decision = human.decide(&duel, Budget::nodes(0));
+        assert!(decision.command.check_valid(&duel).is_ok());
+    }
+
+    // the menus never list every command, so a 20-card hand like Heishin's can still fuse
+    #[test]
+    fn test_menus_large_hand() {
+        let mut duel = Duel::random();
+        duel.player1.hand = vec![card_from_name("Thunder Dragon"); 20];
+        let mut menus = MenuStack::new(&duel);
+        assert_eq!(menus.prompt(&duel).options.len(), 21);
+
+        // fuse the first two cards, then place them at the first position
+        for index in [20, 0, 0] {
+            assert!(menus.choose(&duel, index).is_none());
+        }
+        let prompt = menus.prompt(&duel);
+        assert_eq!(prompt.options.len(), 19);
+        assert_eq!(prompt.options[18].label, "Done");
+        assert!(menus.choose(&duel, 18).is_none());
+        assert_eq!(menus.prompt(&duel).options.len(), 5);
+        let command = menus.choose(&duel, 0).unwrap().unwrap();
+        assert!(matches!(
+            command,
+            DuelCommandEnum::HandPlayMultipleCmd(HandPlayMultipleCmd {
+                ref hand_indices,
+                field_index: 0,
+            }) if *hand_indices == [0, 1]
+        ));
+    }
+}
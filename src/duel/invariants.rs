@@ -0,0 +1,342 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+use crate::{Card, CardVariant, Duel};
+
+use super::{
+    command::{CommandError, DuelCommand, DuelCommandEnum},
+    player::Player,
+    ruleset::Ruleset,
+    state::DuelStateEnum,
+    PlayerEnum,
+};
+
+// Invariants are always checked in debug builds. In release builds they are only checked once enabled, e.g. by the fuzzer.
+static INVARIANT_CHECKS: AtomicBool = AtomicBool::new(false);
+
+// Returns whether they were enabled before, so the caller can restore it.
+pub fn set_invariant_checks(enabled: bool) -> bool {
+    INVARIANT_CHECKS.swap(enabled, Ordering::Relaxed)
+}
+
+pub fn invariant_checks_enabled() -> bool {
+    cfg!(debug_assertions) || INVARIANT_CHECKS.load(Ordering::Relaxed)
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum InvariantViolation {
+    #[error("{player:?} has {length} monster row positions instead of {expected}.")]
+    MonsterRowLength {
+        player: PlayerEnum,
+        length: usize,
+        expected: usize,
+    },
+    #[error("{player:?} has {length} spell row positions instead of {expected}.")]
+    SpellRowLength {
+        player: PlayerEnum,
+        length: usize,
+        expected: usize,
+    },
+    #[error("{player:?} has {life_points} life points, which is above {max_life_points}.")]
+    LifePointsOutOfRange {
+        player: PlayerEnum,
+        life_points: u32,
+        max_life_points: u32,
+    },
+    #[error(
+        "{player:?} has card {card_id} in monster row position {index}, but it is not a monster."
+    )]
+    NonMonsterInMonsterRow {
+        player: PlayerEnum,
+        index: usize,
+        card_id: usize,
+    },
+    #[error("Card {card_id} has a stat delta of {stat_delta} from its base stats, which equips and magic cannot cause.")]
+    InvalidStatDelta { card_id: usize, stat_delta: i32 },
+    #[error("Card {card_id} is waiting for a guardian star, but it is not a monster.")]
+    NonMonsterInSetGuardianStar { card_id: usize },
+    #[error("A monster is waiting for a guardian star at monster row position {index}, which is out of bounds.")]
+    SetGuardianStarIndexOutOfBounds { index: usize },
+    #[error(
+        "{player:?} had {before} cards from their deck before the command, but {after} after."
+    )]
+    CardsNotConserved {
+        player: PlayerEnum,
+        before: usize,
+        after: usize,
+    },
+    #[error("The duel had ended, but the command changed it.")]
+    EndStateNotTerminal,
+    #[error("The state hash updated for the command is {updated:#x}, but the duel hashes to {expected:#x}.")]
+    StateHashNotUpdated { updated: u64, expected: u64 },
+}
+
+impl InvariantViolation {
+    // Identifies the invariant that was broken, regardless of the cards or positions involved.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MonsterRowLength { .. } => "MonsterRowLength",
+            Self::SpellRowLength { .. } => "SpellRowLength",
+            Self::LifePointsOutOfRange { .. } => "LifePointsOutOfRange",
+            Self::NonMonsterInMonsterRow { .. } => "NonMonsterInMonsterRow",
+            Self::InvalidStatDelta { .. } => "InvalidStatDelta",
+            Self::NonMonsterInSetGuardianStar { .. } => "NonMonsterInSetGuardianStar",
+            Self::SetGuardianStarIndexOutOfBounds { .. } => "SetGuardianStarIndexOutOfBounds",
+            Self::CardsNotConserved { .. } => "CardsNotConserved",
+            Self::EndStateNotTerminal => "EndStateNotTerminal",
+            Self::StateHashNotUpdated { .. } => "StateHashNotUpdated",
+        }
+    }
+}
+
+// The panic payload used when a command breaks an invariant, so the fuzzer can tell which invariant it was.
+#[derive(Debug, Clone)]
+pub struct InvariantFailure {
+    pub command: DuelCommandEnum,
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl fmt::Display for InvariantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} broke the duel invariants:", self.command)?;
+        for violation in &self.violations {
+            write!(f, " {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_player(
+    player: &Player,
+    player_enum: PlayerEnum,
+    ruleset: &Ruleset,
+    violations: &mut Vec<InvariantViolation>,
+) {
+    if player.monster_row.len() != ruleset.row_size {
+        violations.push(InvariantViolation::MonsterRowLength {
+            player: player_enum,
+            length: player.monster_row.len(),
+            expected: ruleset.row_size,
+        });
+    }
+    if player.spell_row.len() != ruleset.row_size {
+        violations.push(InvariantViolation::SpellRowLength {
+            player: player_enum,
+            length: player.spell_row.len(),
+            expected: ruleset.row_size,
+        });
+    }
+    // life_points is unsigned, so only the upper bound can be broken.
+    if player.life_points > ruleset.max_life_points {
+        violations.push(InvariantViolation::LifePointsOutOfRange {
+            player: player_enum,
+            life_points: player.life_points,
+            max_life_points: ruleset.max_life_points,
+        });
+    }
+
+    for (index, monster) in player.monster_row.iter().enumerate() {
+        if let Some(monster) = monster {
+            match monster.card.variant {
+                CardVariant::Monster { .. } => check_stat_delta(&monster.card, violations),
+                _ => violations.push(InvariantViolation::NonMonsterInMonsterRow {
+                    player: player_enum,
+                    index,
+                    card_id: monster.card.id,
+                }),
+            }
+        }
+    }
+    for spell in player.spell_row.iter().flatten() {
+        check_stat_delta(&spell.card, violations);
+    }
+}
+
+// Equips and magic always change the stats of a monster by a multiple of 500, which modify_stats relies on.
+// Other cards have no stats to change.
+fn check_stat_delta(card: &Card, violations: &mut Vec<InvariantViolation>) {
+    let valid = match card.variant {
+        CardVariant::Monster { .. } => card.stat_delta % 500 == 0,
+        _ => card.stat_delta == 0,
+    };
+    if !valid {
+        violations.push(InvariantViolation::InvalidStatDelta {
+            card_id: card.id,
+            stat_delta: card.stat_delta,
+        });
+    }
+}
+
+impl Duel {
+    // Checks the invariants that must hold in every state of the duel.
+    pub fn check_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = Vec::new();
+        check_player(
+            &self.player1,
+            PlayerEnum::Player1,
+            &self.ruleset,
+            &mut violations,
+        );
+        check_player(
+            &self.player2,
+            PlayerEnum::Player2,
+            &self.ruleset,
+            &mut violations,
+        );
+
+        if let DuelStateEnum::SetGuardianStarState(state) = &self.state {
+            let card = &state.monster_row_position.card;
+            match card.variant {
+                CardVariant::Monster { .. } => check_stat_delta(card, &mut violations),
+                _ => violations
+                    .push(InvariantViolation::NonMonsterInSetGuardianStar { card_id: card.id }),
+            }
+            if state.monster_row_index >= self.ruleset.row_size {
+                violations.push(InvariantViolation::SetGuardianStarIndexOutOfBounds {
+                    index: state.monster_row_index,
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // Every card the player owns: in the deck, hand, on the field, or in the graveyard.
+    // A monster waiting for its guardian star belongs to the current player.
+    pub fn card_count(&self, player_enum: PlayerEnum) -> usize {
+        let player = self.get_player_by_enum(player_enum);
+        let waiting_monster = match &self.state {
+            DuelStateEnum::SetGuardianStarState(_) if self.get_player_enum() == player_enum => 1,
+            _ => 0,
+        };
+        player.deck.len()
+            + player.hand.len()
+            + player.monster_row.iter().flatten().count()
+            + player.spell_row.iter().flatten().count()
+            + player.graveyard.len()
+            + waiting_monster
+    }
+
+    // Checks the invariants that relate the state before a command to the state after it.
+    pub fn check_transition(before: &Duel, after: &Duel) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = after.check_invariants().err().unwrap_or_default();
+
+        // No card may be lost or duplicated. The only cards that do not come from the deck are the ones created by fusions and rituals.
+        for player_enum in [PlayerEnum::Player1, PlayerEnum::Player2] {
+            let deck_cards = |duel: &Duel| {
+                duel.card_count(player_enum) - duel.get_player_by_enum(player_enum).cards_created
+            };
+            let (before_count, after_count) = (deck_cards(before), deck_cards(after));
+            if after_count != before_count {
+                violations.push(InvariantViolation::CardsNotConserved {
+                    player: player_enum,
+                    before: before_count,
+                    after: after_count,
+                });
+            }
+        }
+
+        if matches!(before.state, DuelStateEnum::EndState(_)) && before != after {
+            violations.push(InvariantViolation::EndStateNotTerminal);
+        }
+
+        // Searches update the state hash instead of computing it again, so both must agree.
+        let (updated, expected) = (
+            after.updated_state_hash(before, before.state_hash()),
+            after.state_hash(),
+        );
+        if updated != expected {
+            violations.push(InvariantViolation::StateHashNotUpdated { updated, expected });
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // Executes the command, and panics with the broken invariants if the command left the duel in an invalid state.
+    // The invariants are only checked when invariant_checks_enabled() is true.
+    pub fn execute(&mut self, command: &DuelCommandEnum) -> Result<(), CommandError> {
+        if !invariant_checks_enabled() {
+            return command.execute(self);
+        }
+
+        let before = self.clone();
+        command.execute(self)?;
+        if let Err(violations) = Self::check_transition(&before, self) {
+            std::panic::panic_any(InvariantFailure {
+                command: command.clone(),
+                violations,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name,
+        duel::{
+            command::EndTurnCmd,
+            field::{CardMode, FaceDirection, GuardianStarChoice, MonsterRowPosition},
+        },
+    };
+
+    #[test]
+    fn test_check_invariants() {
+        let mut duel = Duel::random();
+        assert_eq!(duel.check_invariants(), Ok(()));
+
+        duel.player1.monster_row.pop();
+        duel.player2.life_points = 9000;
+        let mut monster = card_from_name("Thunder Dragon");
+        monster.stat_delta = 250;
+        duel.player2.monster_row[0] = Some(MonsterRowPosition {
+            card: monster,
+            face_direction: FaceDirection::Up,
+            card_mode: CardMode::Attack,
+            guardian_star_choice: GuardianStarChoice::A,
+            disabled: false,
+        });
+
+        let violations = duel.check_invariants().unwrap_err();
+        dbg!(&violations);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| violation.name())
+                .collect::<Vec<_>>(),
+            vec![
+                "MonsterRowLength",
+                "LifePointsOutOfRange",
+                "InvalidStatDelta"
+            ]
+        );
+
+        // a card appearing out of nowhere is caught by the transition check
+        let before = Duel::random();
+        let mut after = before.clone();
+        after.player2.deck.push(card_from_name("Thunder Dragon"));
+        assert_eq!(
+            Duel::check_transition(&before, &after).unwrap_err(),
+            vec![InvariantViolation::CardsNotConserved {
+                player: PlayerEnum::Player2,
+                before: 40,
+                after: 41
+            }]
+        );
+
+        // a command that is rejected leaves the duel untouched, so no invariant is broken
+        let mut duel = Duel::random();
+        assert!(duel.execute(&EndTurnCmd.into()).is_err());
+    }
+}
@@ -2,19 +2,29 @@ use serde::{Deserialize, Serialize};
 
 use crate::TerrainType;
 
-use self::command::DuelCommandEnum;
+use self::command::{DuelCommandEnum, ValidCommands};
 use self::player::Player;
+use self::ruleset::Ruleset;
 use self::state::{DuelStateEnum, HandState};
 
+pub mod canonical;
 pub mod command;
 pub mod command_builder;
 pub mod command_strategy;
 pub mod deck;
+pub mod evaluate;
 pub mod field;
+pub mod graveyard;
+pub mod hash;
+pub mod human;
+pub mod invariants;
 pub mod player;
+pub mod ruleset;
+pub mod search;
 pub mod state;
+pub mod transposition;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Copy, Clone)]
 pub enum PlayerEnum {
     Player1,
     Player2,
@@ -27,21 +37,34 @@ pub struct Duel {
     pub terrain_type: TerrainType,
     pub turn: u32,
     pub state: DuelStateEnum,
+    // Duels saved before rulesets existed were played with the default rules.
+    #[serde(default)]
+    pub ruleset: Ruleset,
 }
 
 impl Duel {
-    pub fn random() -> Self {
+    pub fn new(player1: Player, player2: Player) -> Self {
+        Self::with_ruleset(player1, player2, Ruleset::default())
+    }
+
+    // The players should be created with the same ruleset, e.g. with Player::with_ruleset.
+    pub fn with_ruleset(player1: Player, player2: Player, ruleset: Ruleset) -> Self {
         let mut duel = Self {
-            player1: Player::random(),
-            player2: Player::random(),
+            player1,
+            player2,
             terrain_type: TerrainType::Default,
             turn: 0,
             state: HandState.into(),
+            ruleset,
         };
         duel.get_player_mut().draw();
         duel
     }
 
+    pub fn random() -> Self {
+        Self::new(Player::random(), Player::random())
+    }
+
     pub fn command_builder(&self) -> command_builder::CommandBuilder<command_builder::Start> {
         command_builder::CommandBuilder::new(self)
     }
@@ -50,6 +73,10 @@ impl Duel {
         DuelCommandEnum::generate_all_valid(self)
     }
 
+    pub fn valid_commands(&self) -> ValidCommands {
+        DuelCommandEnum::valid(self)
+    }
+
     pub fn get_player(&self) -> &Player {
         if self.turn % 2 == 0 {
             &self.player1
@@ -116,6 +143,7 @@ impl Duel {
 #[cfg(test)]
 mod tests {
     use crate::duel::Duel;
+    use test::Bencher;
 
     #[test]
     fn test_duel_turns() {
@@ -127,4 +155,11 @@ mod tests {
         duel.turn += 1;
         assert_eq!(duel.get_player().life_points, 8000);
     }
+
+    // every rollout of a search starts by cloning the duel
+    #[bench]
+    fn bench_clone(b: &mut Bencher) {
+        let duel = Duel::random();
+        b.iter(|| duel.clone());
+    }
 }
@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::Card;
+use crate::{Card, Duelist};
 
 use super::{
     deck::generate_random_deck,
     field::{MonsterRowPosition, SpellRowPosition},
+    graveyard::GraveyardEntry,
+    ruleset::Ruleset,
 };
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -16,35 +18,66 @@ pub struct Player {
     pub monster_row: Vec<Option<MonsterRowPosition>>,
     pub spell_row: Vec<Option<SpellRowPosition>>,
     pub sorl_effect_countdown: Option<u32>,
+    // Mage duelists prioritise changing the terrain to their own field, see MageCommandStrategy.
+    #[serde(default)]
+    pub is_mage: bool,
+    // Every card that left play, in the order it happened.
+    #[serde(default)]
+    pub graveyard: Vec<GraveyardEntry>,
+    // How many cards were created during the duel by fusions and rituals, which do not come from the deck.
+    #[serde(default)]
+    pub cards_created: usize,
 }
 
 impl Player {
-    pub fn random() -> Self {
+    pub fn new(deck: Vec<Card>) -> Self {
+        Self::with_ruleset(deck, &Ruleset::default())
+    }
+
+    pub fn with_ruleset(deck: Vec<Card>, ruleset: &Ruleset) -> Self {
+        // Cards are drawn from the end of the deck, so the caller is responsible for shuffling it.
         Self {
-            life_points: 8000,
-            deck: generate_random_deck(),
+            life_points: ruleset.starting_life_points,
+            deck,
             hand: Vec::new(),
-            hand_size: 5,
-            monster_row: vec![None; 5],
-            spell_row: vec![None; 5],
+            hand_size: ruleset.hand_size,
+            monster_row: vec![None; ruleset.row_size],
+            spell_row: vec![None; ruleset.row_size],
             sorl_effect_countdown: None,
+            is_mage: false,
+            graveyard: Vec::new(),
+            cards_created: 0,
+        }
+    }
+
+    // A player with the hand size and mage behaviour of an enemy duelist, e.g. Heishin draws up to 20 cards.
+    pub fn from_duelist(duelist: &Duelist, deck: Vec<Card>, ruleset: &Ruleset) -> Self {
+        Self {
+            hand_size: duelist.hand_size as usize,
+            is_mage: duelist.is_mage != 0,
+            ..Self::with_ruleset(deck, ruleset)
         }
     }
+
+    pub fn random() -> Self {
+        Self::new(generate_random_deck())
+    }
 }
 
 impl Player {
     pub fn draw(&mut self) {
         // Draw cards until the hand has hand_size cards, or until the deck is empty.
-        // Note that after this is done, if the hand does not have at least 5 cards, the player loses by deck out (the caller will check for this)
+        // Note that after this is done, if the hand does not have at least deck_out_hand_size cards, the player loses by deck out (the caller will check for this)
         while self.hand.len() < self.hand_size && !self.deck.is_empty() {
             let card = self.deck.pop().unwrap();
             self.hand.push(card);
         }
     }
 
-    // life points must be kept within the range 0 to 8000.
-    pub fn modify_life_points(&mut self, amount: i32) {
-        self.life_points = (self.life_points as i32 + amount).max(0).min(8000) as u32;
+    // life points must be kept within the range 0 to max_life_points.
+    pub fn modify_life_points(&mut self, amount: i32, max_life_points: u32) {
+        self.life_points =
+            (self.life_points as i64 + amount as i64).clamp(0, max_life_points as i64) as u32;
     }
 
     // pub fn play_hand(&mut self, hand_indices: &Vec<usize>, field_index: usize) -> Card {
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+// The rules of a duel. Missing fields are filled in from the default ruleset, so house rules only need to list what they change.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct Ruleset {
+    // How many cards a player draws up to at the start of their turn. Enemy duelists may override this with their own hand size.
+    pub hand_size: usize,
+    pub starting_life_points: u32,
+    // Life points are capped to this when healed.
+    pub max_life_points: u32,
+    // How many positions there are in the monster row and in the spell row.
+    pub row_size: usize,
+    // The attack and defense bonus (or penalty) for monsters that are advantaged (or disadvantaged) by the terrain.
+    pub terrain_bonus: i32,
+    // The attack and defense bonus for a monster whose guardian star beats the other monster's.
+    pub guardian_star_bonus: i32,
+    // How many turns the enemy cannot attack for after Swords of Revealing Light is played.
+    pub sorl_turns: u32,
+    // Whether the first player can attack on the very first turn.
+    pub first_turn_attack: bool,
+    // A player loses by deck out if they hold fewer cards than this after drawing.
+    pub deck_out_hand_size: usize,
+    // Whether fusions that result in a non-monster card are allowed, e.g. Sky Dragon + Machine Conversion Factory = Harpie's Feather Duster.
+    // These come from the fusion table of the original game, but can't happen in normal play.
+    pub glitch_fusions: bool,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self::original_with_glitches()
+    }
+}
+
+impl Ruleset {
+    // The rules of the original game.
+    pub fn original() -> Self {
+        Self {
+            hand_size: 5,
+            starting_life_points: 8000,
+            max_life_points: 8000,
+            row_size: 5,
+            terrain_bonus: 500,
+            guardian_star_bonus: 500,
+            sorl_turns: 3,
+            first_turn_attack: false,
+            deck_out_hand_size: 5,
+            glitch_fusions: false,
+        }
+    }
+
+    // The rules of the original game, including the glitch fusions.
+    pub fn original_with_glitches() -> Self {
+        Self {
+            glitch_fusions: true,
+            ..Self::original()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name,
+        data::spell::{MagicEffect, SwordsOfRevealingLightEffect},
+        duel::{
+            command::{CommandError, DuelCommand, EndTurnCmd, FieldAttackCmd},
+            deck::generate_random_deck,
+            field::{CardMode, FaceDirection, GuardianStarChoice, MonsterRowPosition},
+            player::Player,
+            state::{DuelStateEnum, FieldState, HandState, WinCondition},
+        },
+        Duel,
+    };
+
+    fn duel_with_ruleset(ruleset: &Ruleset) -> Duel {
+        Duel::with_ruleset(
+            Player::with_ruleset(generate_random_deck(), ruleset),
+            Player::with_ruleset(generate_random_deck(), ruleset),
+            ruleset.clone(),
+        )
+    }
+
+    fn monster(name: &str) -> Option<MonsterRowPosition> {
+        Some(MonsterRowPosition {
+            card: card_from_name(name),
+            face_direction: FaceDirection::Up,
+            card_mode: CardMode::Attack,
+            guardian_star_choice: GuardianStarChoice::A,
+            disabled: false,
+        })
+    }
+
+    #[test]
+    fn test_house_rules() {
+        // house rules only need to list the rules they change
+        let ruleset: Ruleset = serde_json::from_str(
+            r#"{ "starting_life_points": 4000, "max_life_points": 4000, "first_turn_attack": true }"#,
+        )
+        .unwrap();
+        dbg!(&ruleset);
+        assert_eq!(ruleset.starting_life_points, 4000);
+        assert!(ruleset.first_turn_attack);
+        assert_eq!(ruleset.hand_size, 5);
+        assert!(ruleset.glitch_fusions);
+        assert_ne!(Ruleset::original(), Ruleset::original_with_glitches());
+    }
+
+    #[test]
+    fn test_first_turn_attack() {
+        let attack_on_first_turn = |ruleset: &Ruleset| {
+            let mut duel = duel_with_ruleset(ruleset);
+            duel.get_player_mut().monster_row[0] = monster("Thunder Dragon");
+            duel.state = FieldState.into();
+            FieldAttackCmd {
+                monster_row_index: 0,
+                enemy_monster_row_index: 0,
+            }
+            .check_valid(&duel)
+        };
+
+        assert!(matches!(
+            attack_on_first_turn(&Ruleset::original()),
+            Err(CommandError::CannotAttackOnFirstTurn)
+        ));
+        let ruleset = Ruleset {
+            first_turn_attack: true,
+            ..Ruleset::original()
+        };
+        assert!(attack_on_first_turn(&ruleset).is_ok());
+    }
+
+    #[test]
+    fn test_guardian_star_bonus() {
+        // Thunder Dragon (Pluto) attacks Great White (Neptune), and both have 1600 attack
+        let battle = |ruleset: &Ruleset| {
+            let mut duel = duel_with_ruleset(ruleset);
+            duel.turn = 1;
+            duel.get_player_mut().monster_row[0] = monster("Thunder Dragon");
+            duel.get_enemy_mut().monster_row[0] = monster("Great White");
+            duel.state = FieldState.into();
+            FieldAttackCmd {
+                monster_row_index: 0,
+                enemy_monster_row_index: 0,
+            }
+            .execute(&mut duel)
+            .unwrap();
+            duel
+        };
+
+        // Pluto is strong against Neptune, so Thunder Dragon wins by the bonus
+        let duel = battle(&Ruleset::original());
+        assert!(duel.get_player().monster_row[0].is_some());
+        assert!(duel.get_enemy().monster_row[0].is_none());
+        assert_eq!(duel.get_enemy().life_points, 7500);
+
+        // without a bonus, the attacks are equal and both monsters are destroyed
+        let ruleset = Ruleset {
+            guardian_star_bonus: 0,
+            ..Ruleset::original()
+        };
+        let duel = battle(&ruleset);
+        assert!(duel.get_player().monster_row[0].is_none());
+        assert!(duel.get_enemy().monster_row[0].is_none());
+        assert_eq!(duel.get_enemy().life_points, 8000);
+    }
+
+    #[test]
+    fn test_deck_out_hand_size() {
+        // the next player can only draw 3 cards
+        let end_turn = |ruleset: &Ruleset| {
+            let mut duel = duel_with_ruleset(ruleset);
+            duel.get_enemy_mut().deck.truncate(3);
+            duel.state = FieldState.into();
+            EndTurnCmd.execute(&mut duel).unwrap();
+            duel
+        };
+
+        let duel = end_turn(&Ruleset::original());
+        match &duel.state {
+            DuelStateEnum::EndState(end) => {
+                assert_eq!(end.win_condition, WinCondition::DeckOut);
+                assert_eq!(end.winner, duel.get_enemy_enum());
+            }
+            _ => panic!("expected a deck out, got {:?}", duel.state),
+        }
+
+        let ruleset = Ruleset {
+            deck_out_hand_size: 3,
+            ..Ruleset::original()
+        };
+        let duel = end_turn(&ruleset);
+        assert_eq!(duel.state, HandState.into());
+        assert_eq!(duel.get_player().hand.len(), 3);
+    }
+
+    #[test]
+    fn test_sorl_turns() {
+        // counts the enemy turns in which Swords of Revealing Light stops them from attacking
+        let blocked_turns = |ruleset: &Ruleset| {
+            let mut duel = duel_with_ruleset(ruleset);
+            let enemy = duel.get_enemy_enum();
+            SwordsOfRevealingLightEffect.execute_effect(&mut duel);
+            let mut blocked = 0;
+            for _ in 0..10 {
+                duel.state = FieldState.into();
+                EndTurnCmd.execute(&mut duel).unwrap();
+                duel.state = FieldState.into();
+                let attack = FieldAttackCmd {
+                    monster_row_index: 0,
+                    enemy_monster_row_index: 0,
+                };
+                if duel.get_player_enum() == enemy
+                    && matches!(
+                        attack.check_valid(&duel),
+                        Err(CommandError::CannotAttackWhileSORLEffectActive)
+                    )
+                {
+                    blocked += 1;
+                }
+            }
+            blocked
+        };
+
+        assert_eq!(blocked_turns(&Ruleset::original()), 3);
+        let ruleset = Ruleset {
+            sorl_turns: 1,
+            ..Ruleset::original()
+        };
+        assert_eq!(blocked_turns(&ruleset), 1);
+    }
+}
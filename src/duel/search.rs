@@ -0,0 +1,616 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use crate::Duel;
+
+use super::{
+    command::{DuelCommand, DuelCommandEnum},
+    command_strategy::{Budget, BudgetedStrategy, CommandStrategy, Decision},
+    evaluate::Evaluator,
+    state::DuelStateEnum,
+    transposition::TranspositionTable,
+    PlayerEnum,
+};
+
+// The value of a won duel. Wins further in the future are worth slightly less, so the quickest win is preferred.
+pub const WIN_VALUE: f64 = 1e9;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct SearchConfig {
+    // How many commands to look ahead at most. Iterative deepening searches 1, 2, ... commands ahead, up to this depth.
+    pub max_depth: u32,
+    // Iterative deepening stops when this runs out, and the deepest search that finished decides the command.
+    // The first command is always searched at the first depth, so there is always a command to play.
+    // Only used through CommandStrategy, since a BudgetedStrategy is given its budget for every command.
+    pub time_budget: Option<Duration>,
+    // Whether the search can see the enemy's hand and the order of both decks, e.g. for puzzle positions.
+    pub perfect_information: bool,
+    // Otherwise, the hidden cards are dealt at random this many times at the start of the search, and the values averaged.
+    pub determinizations: usize,
+    // Ending the turn draws cards, so its value is averaged over this many random draws.
+    pub chance_samples: usize,
+    pub transposition_table_size: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            time_budget: Some(Duration::from_secs(1)),
+            perfect_information: false,
+            determinizations: 4,
+            chance_samples: 2,
+            transposition_table_size: 1 << 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub command: DuelCommandEnum,
+    // The value of the command for the player to move, averaged over the determinizations.
+    pub value: f64,
+    // The commands the search expects to be played from here, starting with the command itself.
+    // It stops at the end of the turn, since the draws that follow are not known.
+    pub principal_variation: Vec<DuelCommandEnum>,
+    // The deepest search that finished.
+    pub depth: u32,
+    pub nodes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NodeValue {
+    // The player the value is for.
+    player: PlayerEnum,
+    value: f64,
+    bound: Bound,
+    // The index of the best canonical command, which is searched first next time.
+    best: Option<usize>,
+}
+
+// Win values count the plies from the root, but a stored value can be probed at another ply, or by a later search
+// with another root. So the table counts the plies from the node instead.
+fn value_to_table(value: f64, ply: u32) -> f64 {
+    if value > WIN_VALUE / 2.0 {
+        value + ply as f64
+    } else if value < -WIN_VALUE / 2.0 {
+        value - ply as f64
+    } else {
+        value
+    }
+}
+
+fn value_from_table(value: f64, ply: u32) -> f64 {
+    if value > WIN_VALUE / 2.0 {
+        value - ply as f64
+    } else if value < -WIN_VALUE / 2.0 {
+        value + ply as f64
+    } else {
+        value
+    }
+}
+
+// Returned when the budget runs out in the middle of a search.
+struct OutOfBudget;
+
+// A duel in the search, with its state hash, which is updated for every command instead of computed again.
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    duel: &'a Duel,
+    state_hash: u64,
+}
+
+impl<'a> Node<'a> {
+    fn new(duel: &'a Duel) -> Self {
+        Self {
+            duel,
+            state_hash: duel.state_hash(),
+        }
+    }
+
+    fn child(&self, child: &'a Duel) -> Self {
+        Self {
+            duel: child,
+            state_hash: child.updated_state_hash(self.duel, self.state_hash),
+        }
+    }
+}
+
+struct Search<'a, E: Evaluator> {
+    evaluator: &'a E,
+    config: &'a SearchConfig,
+    // The player the values are for.
+    player: PlayerEnum,
+    deadline: Option<Instant>,
+    max_nodes: Option<usize>,
+    rng: &'a mut StdRng,
+    table: &'a mut TranspositionTable<NodeValue>,
+    nodes: usize,
+}
+
+impl<E: Evaluator> Search<'_, E> {
+    // With perfect information, ending the turn draws from the actual decks, so their order is part of the state.
+    fn hash(&self, node: Node) -> u64 {
+        if self.config.perfect_information {
+            node.state_hash ^ node.duel.deck_order_hash()
+        } else {
+            node.state_hash
+        }
+    }
+
+    // Alpha-beta search, where the player maximises the value and the enemy minimises it.
+    fn value(
+        &mut self,
+        node: Node,
+        depth: u32,
+        ply: u32,
+        mut alpha: f64,
+        mut beta: f64,
+    ) -> Result<f64, OutOfBudget> {
+        self.nodes += 1;
+        if self
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes > max_nodes)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(OutOfBudget);
+        }
+        let duel = node.duel;
+        if let DuelStateEnum::EndState(end_state) = &duel.state {
+            let value = WIN_VALUE - ply as f64;
+            return Ok(if end_state.winner == self.player {
+                value
+            } else {
+                -value
+            });
+        }
+        if depth == 0 {
+            return Ok(self.evaluator.evaluate(duel, self.player));
+        }
+
+        let hash = self.hash(node);
+        let mut best_index = None;
+        if let Some(entry) = self
+            .table
+            .get(hash)
+            .filter(|entry| entry.value.player == self.player)
+        {
+            best_index = entry.value.best;
+            if entry.depth >= depth {
+                let NodeValue { value, bound, .. } = entry.value;
+                let value = value_from_table(value, ply);
+                match bound {
+                    Bound::Exact => return Ok(value),
+                    Bound::Lower => alpha = alpha.max(value),
+                    Bound::Upper => beta = beta.min(value),
+                }
+                if alpha >= beta {
+                    return Ok(value);
+                }
+            }
+        }
+        let (original_alpha, original_beta) = (alpha, beta);
+
+        // the best command of an earlier search is searched first, so more of the others are pruned.
+        // the commands are generated as they are searched, so a cutoff skips generating the rest.
+        let first_index = best_index;
+        let first = first_index.and_then(|index| {
+            DuelCommandEnum::generate_canonical(duel)
+                .nth(index)
+                .map(|group| (index, group.command))
+        });
+        let rest = DuelCommandEnum::generate_canonical(duel)
+            .enumerate()
+            .filter(|&(index, _)| Some(index) != first_index)
+            .map(|(index, group)| (index, group.command));
+
+        let maximizing = duel.get_player_enum() == self.player;
+        let mut best = if maximizing {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+        for (index, command) in first.into_iter().chain(rest) {
+            let value = self.child_value(node, &command, depth - 1, ply + 1, alpha, beta)?;
+            if maximizing {
+                if value > best {
+                    best = value;
+                    best_index = Some(index);
+                }
+                alpha = alpha.max(value);
+            } else {
+                if value < best {
+                    best = value;
+                    best_index = Some(index);
+                }
+                beta = beta.min(value);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= original_beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            hash,
+            depth,
+            NodeValue {
+                player: self.player,
+                value: value_to_table(best, ply),
+                bound,
+                best: best_index,
+            },
+        );
+        Ok(best)
+    }
+
+    // The value of a command at the root, averaged over the worlds.
+    fn root_value(
+        &mut self,
+        worlds: &[Duel],
+        command: &DuelCommandEnum,
+        depth: u32,
+    ) -> Result<f64, OutOfBudget> {
+        let mut total = 0.0;
+        for world in worlds {
+            total += self.child_value(
+                Node::new(world),
+                command,
+                depth - 1,
+                1,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+            )?;
+        }
+        Ok(total / worlds.len() as f64)
+    }
+
+    // Follows the best commands stored in the transposition table, starting with the given command.
+    fn principal_variation(
+        &mut self,
+        duel: &Duel,
+        command: &DuelCommandEnum,
+        depth: u32,
+    ) -> Vec<DuelCommandEnum> {
+        let mut duel = duel.clone();
+        let mut variation = vec![command.clone()];
+        command.execute(&mut duel).unwrap();
+        while variation.len() < depth as usize
+            && (self.config.perfect_information
+                || !matches!(variation.last(), Some(DuelCommandEnum::EndTurnCmd(_))))
+        {
+            let Some(best) = self
+                .table
+                .get(self.hash(Node::new(&duel)))
+                .filter(|entry| entry.value.player == self.player)
+                .and_then(|entry| entry.value.best)
+            else {
+                break;
+            };
+            let Some(group) = DuelCommandEnum::generate_canonical(&duel).nth(best) else {
+                break;
+            };
+            group.command.execute(&mut duel).unwrap();
+            variation.push(group.command);
+        }
+        variation
+    }
+
+    // Ending the turn draws cards for the next player, so it is a chance node whose value is averaged over random draws.
+    fn child_value(
+        &mut self,
+        node: Node,
+        command: &DuelCommandEnum,
+        depth: u32,
+        ply: u32,
+        alpha: f64,
+        beta: f64,
+    ) -> Result<f64, OutOfBudget> {
+        if self.config.perfect_information || !matches!(command, DuelCommandEnum::EndTurnCmd(_)) {
+            let mut child = node.duel.clone();
+            command.execute(&mut child).unwrap();
+            return self.value(node.child(&child), depth, ply, alpha, beta);
+        }
+
+        let samples = self.config.chance_samples.max(1);
+        let mut total = 0.0;
+        for _ in 0..samples {
+            let mut child = node.duel.clone();
+            child.get_enemy_mut().deck.shuffle(self.rng);
+            command.execute(&mut child).unwrap();
+            // the bounds of the parent do not apply to a single sample
+            total += self.value(
+                node.child(&child),
+                depth,
+                ply,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+            )?;
+        }
+        Ok(total / samples as f64)
+    }
+}
+
+// Deals the cards the player cannot see at random: the enemy's hand is shuffled into their deck and drawn again,
+// and the player's own deck is shuffled.
+fn determinize(duel: &Duel, player: PlayerEnum, rng: &mut StdRng) -> Duel {
+    let mut duel = duel.clone();
+    let (own, enemy) = match player {
+        PlayerEnum::Player1 => (&mut duel.player1, &mut duel.player2),
+        PlayerEnum::Player2 => (&mut duel.player2, &mut duel.player1),
+    };
+    own.deck.shuffle(rng);
+
+    let hand_size = enemy.hand.len();
+    let mut hidden = std::mem::take(&mut enemy.deck);
+    hidden.append(&mut enemy.hand);
+    hidden.shuffle(rng);
+    enemy.hand = hidden.split_off(hidden.len() - hand_size);
+    enemy.deck = hidden;
+    duel
+}
+
+// A depth-limited expectiminimax search over the canonical commands, with alpha-beta pruning between chance nodes.
+// The search is reproducible for a given seed and configuration, unless it is cut short by the time budget.
+// The transposition table is kept between commands, so states searched on earlier commands are not searched again.
+pub struct SearchCommandStrategy<E: Evaluator> {
+    pub evaluator: E,
+    pub config: SearchConfig,
+    rng: RefCell<StdRng>,
+    table: RefCell<TranspositionTable<NodeValue>>,
+}
+
+impl<E: Evaluator> SearchCommandStrategy<E> {
+    pub fn new(evaluator: E, config: SearchConfig, seed: u64) -> Self {
+        Self {
+            evaluator,
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+            table: RefCell::new(TranspositionTable::new(config.transposition_table_size)),
+            config,
+        }
+    }
+
+    pub fn search(&self, duel: &Duel) -> SearchResult {
+        self.search_with_budget(
+            duel,
+            Budget {
+                time: self.config.time_budget,
+                nodes: None,
+            },
+        )
+    }
+
+    pub fn search_with_budget(&self, duel: &Duel, budget: Budget) -> SearchResult {
+        let deadline = budget.deadline(Instant::now());
+        let player = duel.get_player_enum();
+        // the commands are generated while the first depth is searched, since a large hand has too many to list first
+        let mut canonical = DuelCommandEnum::generate_canonical(duel).map(|group| group.command);
+        let mut commands = canonical.by_ref().take(2).collect::<Vec<_>>();
+        let mut result = SearchResult {
+            command: commands[0].clone(),
+            value: self.evaluator.evaluate(duel, player),
+            principal_variation: vec![commands[0].clone()],
+            depth: 0,
+            nodes: 0,
+        };
+        if commands.len() == 1 {
+            return result;
+        }
+
+        let mut rng = self.rng.borrow_mut();
+        let mut table = self.table.borrow_mut();
+        let worlds = if self.config.perfect_information {
+            vec![duel.clone()]
+        } else {
+            (0..self.config.determinizations.max(1))
+                .map(|_| determinize(duel, player, &mut rng))
+                .collect()
+        };
+        let mut search = Search {
+            evaluator: &self.evaluator,
+            config: &self.config,
+            player,
+            deadline: None,
+            max_nodes: None,
+            rng: &mut rng,
+            table: &mut table,
+            nodes: 0,
+        };
+
+        // the best commands of the previous depth are searched first
+        let mut order = Vec::new();
+        for depth in 1..=self.config.max_depth {
+            let mut values = Vec::with_capacity(commands.len());
+            let finished = if depth == 1 {
+                let mut index = 0;
+                loop {
+                    if index == commands.len() {
+                        match canonical.next() {
+                            Some(command) => commands.push(command),
+                            None => break Ok(()),
+                        }
+                    }
+                    let value = search.root_value(&worlds, &commands[index], depth);
+                    // the first command is always searched to the end, so there is always a command to play
+                    search.deadline = deadline;
+                    search.max_nodes = budget.nodes;
+                    match value {
+                        Ok(value) => values.push((index, value)),
+                        Err(out_of_budget) => break Err(out_of_budget),
+                    }
+                    index += 1;
+                }
+            } else {
+                order.iter().try_for_each(|&index| {
+                    values.push((index, search.root_value(&worlds, &commands[index], depth)?));
+                    Ok(())
+                })
+            };
+            // a search that was cut short only decides the command at the first depth
+            if finished.is_err() && depth > 1 {
+                break;
+            }
+
+            // a stable sort, so ties keep the order of the previous depth
+            values.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            order = values.iter().map(|&(index, _)| index).collect();
+            let (best_index, best_value) = values[0];
+            result.command = commands[best_index].clone();
+            result.value = best_value;
+            result.depth = depth;
+
+            // a forced win or loss will not change by searching deeper, and a cut short first depth ends the search
+            if finished.is_err() || best_value.abs() >= WIN_VALUE - self.config.max_depth as f64 {
+                break;
+            }
+        }
+
+        result.principal_variation =
+            search.principal_variation(&worlds[0], &result.command, result.depth);
+        result.nodes = search.nodes;
+        result
+    }
+}
+
+impl<E: Evaluator> CommandStrategy for SearchCommandStrategy<E> {
+    fn get_command(&self, duel: &Duel) -> DuelCommandEnum {
+        self.search(duel).command
+    }
+}
+
+impl<E: Evaluator> BudgetedStrategy for SearchCommandStrategy<E> {
+    fn decide(&mut self, duel: &Duel, budget: Budget) -> Decision {
+        let result = self.search_with_budget(duel, budget);
+        Decision {
+            command: result.command,
+            principal_variation: result.principal_variation,
+            evaluation: Some(result.value),
+            nodes: result.nodes,
+        }
+    }
+
+    fn on_duel_end(&mut self, _duel: &Duel) {
+        self.table.get_mut().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card_from_name,
+        duel::{
+            evaluate::LinearEvaluator,
+            field::{CardMode, FaceDirection, GuardianStarChoice, MonsterRowPosition},
+            state::FieldState,
+        },
+    };
+
+    #[test]
+    fn test_win_values_in_table() {
+        // a win 2 plies below a node at ply 3 is stored as 2 plies away, and found at ply 1 as a win at ply 3
+        let stored = value_to_table(WIN_VALUE - 5.0, 3);
+        assert_eq!(stored, WIN_VALUE - 2.0);
+        assert_eq!(value_from_table(stored, 1), WIN_VALUE - 3.0);
+        assert_eq!(
+            value_from_table(value_to_table(-WIN_VALUE + 4.0, 2), 2),
+            -WIN_VALUE + 4.0
+        );
+        assert_eq!(value_to_table(1500.0, 3), 1500.0);
+    }
+
+    #[test]
+    fn test_search_finds_lethal_attack() {
+        // a puzzle where attacking directly wins, but ending the turn first does not
+        let mut duel = Duel::random();
+        duel.turn = 2;
+        duel.state = FieldState.into();
+        duel.player2.life_points = 1000;
+        duel.player2
+            .monster_row
+            .iter_mut()
+            .for_each(|monster| *monster = None);
+        duel.player2
+            .spell_row
+            .iter_mut()
+            .for_each(|spell| *spell = None);
+        duel.player1.monster_row[2] = Some(MonsterRowPosition {
+            card: card_from_name("Thunder Dragon"),
+            face_direction: FaceDirection::Up,
+            card_mode: CardMode::Attack,
+            guardian_star_choice: GuardianStarChoice::A,
+            disabled: false,
+        });
+
+        let config = SearchConfig {
+            max_depth: 3,
+            perfect_information: true,
+            ..Default::default()
+        };
+        let strategy = SearchCommandStrategy::new(LinearEvaluator::material(), config, 0);
+        let result = strategy.search(&duel);
+        dbg!(&result);
+        assert!(matches!(
+            result.command,
+            DuelCommandEnum::FieldAttackCmd(ref cmd) if cmd.monster_row_index == 2
+        ));
+        assert_eq!(result.value, WIN_VALUE - 1.0);
+        // the attack ends the duel, so nothing follows it
+        assert_eq!(result.principal_variation.len(), 1);
+        // searching again finds the same win through the table, which was filled at other plies
+        assert_eq!(strategy.search(&duel).value, WIN_VALUE - 1.0);
+
+        // with hidden cards and a time budget, the search still returns a valid command in time
+        let duel = Duel::random();
+        let config = SearchConfig {
+            time_budget: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let strategy = SearchCommandStrategy::new(LinearEvaluator::material(), config, 0);
+        let start = Instant::now();
+        let result = strategy.search(&duel);
+        dbg!(result.depth, result.nodes, start.elapsed());
+        assert!(result.command.check_valid(&duel).is_ok());
+        assert!(result.depth >= 1);
+
+        // through BudgetedStrategy, a node budget cuts the search short instead
+        let mut strategy = strategy;
+        let decision = strategy.decide(&duel, Budget::nodes(1000));
+        dbg!(
+            decision.evaluation,
+            decision.nodes,
+            &decision.principal_variation
+        );
+        assert!(decision.command.check_valid(&duel).is_ok());
+        assert!(decision.evaluation.is_some());
+
+        // a 20-card hand has too many commands to search them all, but the budget still ends the search
+        let mut duel = Duel::random();
+        duel.player1.hand_size = 20;
+        duel.player1.draw();
+        let start = Instant::now();
+        let decision = strategy.decide(&duel, Budget::nodes(1000));
+        dbg!(decision.nodes, start.elapsed());
+        assert!(decision.command.check_valid(&duel).is_ok());
+    }
+}
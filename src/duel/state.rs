@@ -22,9 +22,20 @@ pub struct SetGuardianStarState {
 }
 impl DuelState for SetGuardianStarState {}
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Copy, Clone, Default)]
+pub enum WinCondition {
+    LifePoints, // The loser's life points reached 0.
+    DeckOut,    // The loser could not draw a full hand.
+    Exodia,     // The winner held all 5 pieces of Exodia.
+    #[default]
+    Unknown, // Loaded from a duel serialized before win conditions were recorded.
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct EndState {
     pub winner: PlayerEnum,
+    #[serde(default)]
+    pub win_condition: WinCondition,
 }
 impl DuelState for EndState {}
 
@@ -37,3 +48,22 @@ pub enum DuelStateEnum {
     SetGuardianStarState, // Happens when a monster is played from the hand, or when an equip is played on an existing monster but the equip fails.
     EndState,             // The game is over.
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_old_end_state() {
+        let state: DuelStateEnum =
+            serde_json::from_str(r#"{"EndState":{"winner":"Player1"}}"#).unwrap();
+        dbg!(&state);
+        assert_eq!(
+            state,
+            DuelStateEnum::EndState(EndState {
+                winner: PlayerEnum::Player1,
+                win_condition: WinCondition::Unknown,
+            })
+        );
+    }
+}
@@ -0,0 +1,119 @@
+// A fixed-size table from state hashes (see Duel::state_hash()) to search results, so that a state reached by
+// several different orderings of commands is only searched once.
+// The table has one entry per slot. When two states share a slot, the one that was searched deeper is kept.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<V> {
+    entries: Vec<Option<TranspositionEntry<V>>>,
+    len: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranspositionEntry<V> {
+    pub hash: u64,
+    // How many plies deep the state was searched to find the value.
+    pub depth: u32,
+    pub value: V,
+}
+
+impl<V> TranspositionTable<V> {
+    // The capacity is rounded up to a power of two.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        Self {
+            entries: std::iter::repeat_with(|| None).take(capacity).collect(),
+            len: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn slot(&self, hash: u64) -> usize {
+        hash as usize & (self.entries.len() - 1)
+    }
+
+    // The entry for the state, if it was stored and not replaced since.
+    pub fn get(&mut self, hash: u64) -> Option<&TranspositionEntry<V>> {
+        let slot = self.slot(hash);
+        match &self.entries[slot] {
+            Some(entry) if entry.hash == hash => {
+                self.hits += 1;
+                self.entries[slot].as_ref()
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // The value of the state, if it was searched at least min_depth plies deep.
+    pub fn get_value(&mut self, hash: u64, min_depth: u32) -> Option<&V> {
+        self.get(hash)
+            .filter(|entry| entry.depth >= min_depth)
+            .map(|entry| &entry.value)
+    }
+
+    // Stores the value, unless the slot holds a different state that was searched deeper.
+    pub fn insert(&mut self, hash: u64, depth: u32, value: V) {
+        let slot = self.slot(hash);
+        match &self.entries[slot] {
+            Some(entry) if entry.hash != hash && entry.depth > depth => return,
+            Some(_) => {}
+            None => self.len += 1,
+        }
+        self.entries[slot] = Some(TranspositionEntry { hash, depth, value });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.len = 0;
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Duel;
+
+    #[test]
+    fn test_transposition_table() {
+        let mut table = TranspositionTable::new(1000);
+        assert_eq!(table.capacity(), 1024);
+
+        let duel = Duel::random();
+        let hash = duel.state_hash();
+        assert!(table.get(hash).is_none());
+        table.insert(hash, 2, 1.5);
+        assert_eq!(table.get_value(hash, 2), Some(&1.5));
+        assert_eq!(table.get_value(hash, 3), None);
+
+        // a shallower search of another state in the same slot does not replace the deeper one
+        let other = hash ^ (1 << 40);
+        table.insert(other, 1, -1.0);
+        assert!(table.get(other).is_none());
+        table.insert(other, 3, -1.0);
+        assert_eq!(table.get_value(other, 3), Some(&-1.0));
+        assert!(table.get(hash).is_none());
+        assert_eq!(table.len(), 1);
+        dbg!(table.hits, table.misses);
+
+        table.clear();
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,754 @@
+// A reinforcement learning environment in the style of Gym. The agent plays one side of a duel against an opponent strategy,
+// and picks commands by their index in a fixed ActionTable. Observations are fixed-size vectors of numbers, along with a mask
+// of the actions that are legal, so they can be fed to a policy directly.
+// VecEnv steps a batch of environments at once, and resets the ones whose duel ended.
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    api::{ApiError, NewDuel},
+    duel::{
+        command::{
+            hand_permutation, hand_permutation_count, hand_permutation_rank, DuelCommand,
+            DuelCommandEnum, EndTurnCmd, FieldAttackCmd, FieldChangeModeCmd, FieldPlayEquipCmd,
+            FieldPlaySpellCmd, HandPlayMultipleCmd, HandPlaySingleCmd, SetGuardianStarCmd,
+        },
+        command_strategy::{Budget, BudgetedStrategy},
+        field::{CardMode, FaceDirection, GuardianStarChoice},
+        state::{DuelStateEnum, EndState},
+        PlayerEnum,
+    },
+    protocol::{self, CardView, MonsterView, Phase, PlayerView},
+    simulate::{random_strategy, StrategyFactory},
+    Duel, GuardianStarType,
+};
+
+#[derive(Error, Debug)]
+pub enum EnvError {
+    #[error("Failed to create a duel: {0}")]
+    Duel(#[from] ApiError),
+    #[error("Action {0} is not legal.")]
+    IllegalAction(usize),
+    #[error("The environment needs to be reset.")]
+    NotReset,
+}
+
+// Every command the agent could ever play with a hand and rows of the given sizes, each at a fixed index.
+// Every order of 2-5 hand cards is a separate action, so with all 20 cards of a duelist like Heishin the table has
+// about 10 million actions. The commands are worked out from their index rather than stored.
+pub struct ActionTable {
+    hand_size: usize,
+    row_size: usize,
+}
+
+impl ActionTable {
+    pub fn new(hand_size: usize, row_size: usize) -> Self {
+        Self {
+            hand_size,
+            row_size,
+        }
+    }
+
+    // How many actions there are of each kind of command, in the order of the table.
+    fn section_lens(&self) -> [usize; 8] {
+        let (hand, row) = (self.hand_size, self.row_size);
+        [
+            hand * 2 * (row + 1),
+            hand_permutation_count(hand) * row,
+            2,
+            row * row,
+            row,
+            row,
+            row * row,
+            1,
+        ]
+    }
+
+    pub fn len(&self) -> usize {
+        self.section_lens().iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn command(&self, index: usize) -> Option<DuelCommandEnum> {
+        let row = self.row_size;
+        let mut index = index;
+        for (section, len) in self.section_lens().into_iter().enumerate() {
+            if index >= len {
+                index -= len;
+                continue;
+            }
+            return Some(match section {
+                0 => HandPlaySingleCmd {
+                    hand_index: index / (2 * (row + 1)),
+                    face_direction: match index / (row + 1) % 2 {
+                        0 => FaceDirection::Up,
+                        _ => FaceDirection::Down,
+                    },
+                    field_index: Some(index % (row + 1)).filter(|&field_index| field_index < row),
+                }
+                .into(),
+                1 => HandPlayMultipleCmd {
+                    hand_indices: hand_permutation(self.hand_size, index / row)?,
+                    field_index: index % row,
+                }
+                .into(),
+                2 => SetGuardianStarCmd {
+                    guardian_star_choice: if index == 0 {
+                        GuardianStarChoice::A
+                    } else {
+                        GuardianStarChoice::B
+                    },
+                }
+                .into(),
+                3 => FieldAttackCmd {
+                    monster_row_index: index / row,
+                    enemy_monster_row_index: index % row,
+                }
+                .into(),
+                4 => FieldChangeModeCmd {
+                    monster_index: index,
+                }
+                .into(),
+                5 => FieldPlaySpellCmd {
+                    spell_row_index: index,
+                }
+                .into(),
+                6 => FieldPlayEquipCmd {
+                    spell_row_index: index / row,
+                    monster_row_index: index % row,
+                }
+                .into(),
+                _ => EndTurnCmd.into(),
+            });
+        }
+        None
+    }
+
+    // The index of a command, or None if it does not fit the hand and row sizes of the table.
+    pub fn index(&self, command: &DuelCommandEnum) -> Option<usize> {
+        let (hand, row) = (self.hand_size, self.row_size);
+        let [single, multiple, guardian_star, attack, change_mode, spell, equip, _] =
+            self.section_lens();
+        let multiple = single + multiple;
+        let guardian_star = multiple + guardian_star;
+        let attack = guardian_star + attack;
+        let change_mode = attack + change_mode;
+        let spell = change_mode + spell;
+        let equip = spell + equip;
+        let in_row = |index: usize| (index < row).then_some(index);
+
+        match command {
+            DuelCommandEnum::HandPlaySingleCmd(cmd) => {
+                let field_slot = match cmd.field_index {
+                    Some(field_index) => in_row(field_index)?,
+                    None => row,
+                };
+                (cmd.hand_index < hand).then_some(
+                    (cmd.hand_index * 2 + cmd.face_direction as usize) * (row + 1) + field_slot,
+                )
+            }
+            DuelCommandEnum::HandPlayMultipleCmd(cmd) => {
+                let permutation = hand_permutation_rank(hand, &cmd.hand_indices)?;
+                Some(single + permutation * row + in_row(cmd.field_index)?)
+            }
+            DuelCommandEnum::SetGuardianStarCmd(cmd) => {
+                Some(multiple + cmd.guardian_star_choice as usize)
+            }
+            DuelCommandEnum::FieldAttackCmd(cmd) => Some(
+                guardian_star
+                    + in_row(cmd.monster_row_index)? * row
+                    + in_row(cmd.enemy_monster_row_index)?,
+            ),
+            DuelCommandEnum::FieldChangeModeCmd(cmd) => Some(attack + in_row(cmd.monster_index)?),
+            DuelCommandEnum::FieldPlaySpellCmd(cmd) => {
+                Some(change_mode + in_row(cmd.spell_row_index)?)
+            }
+            DuelCommandEnum::FieldPlayEquipCmd(cmd) => {
+                Some(spell + in_row(cmd.spell_row_index)? * row + in_row(cmd.monster_row_index)?)
+            }
+            DuelCommandEnum::EndTurnCmd(_) => Some(equip),
+        }
+    }
+
+    // Whether the action is a valid command for the current player of the duel.
+    pub fn is_legal(&self, duel: &Duel, action: usize) -> bool {
+        self.command(action)
+            .is_some_and(|command| command.check_valid(duel).is_ok())
+    }
+
+    // Which actions are valid commands for the current player of the duel, marked from the valid commands of the duel.
+    // Commands that do not fit the table, e.g. from a hand larger than the table's, are left out.
+    pub fn mask(&self, duel: &Duel) -> Vec<bool> {
+        let mut mask = vec![false; self.len()];
+        for command in duel.valid_commands().iter() {
+            if let Some(action) = self.index(&command) {
+                mask[action] = true;
+            }
+        }
+        mask
+    }
+}
+
+// The kinds of CardView, in the order of their one-hot encoding.
+const CARD_KINDS: [&str; 5] = ["Monster", "Ritual", "Equip", "Magic", "Trap"];
+const GUARDIAN_STARS: usize = 10;
+const TERRAINS: usize = 7;
+const PHASES: usize = 4;
+// Stats and life points are divided by these, to keep the numbers around 0-1.
+const STAT_SCALE: f32 = 1000.0;
+const LIFE_POINTS_SCALE: f32 = 8000.0;
+const DECK_SCALE: f32 = 40.0;
+const TURN_SCALE: f32 = 100.0;
+
+// Present, ID, kind, attack, defense and the possible guardian stars.
+const CARD_FEATURES: usize = 2 + CARD_KINDS.len() + 2 + GUARDIAN_STARS;
+// The card, if it can be seen, then present, face-down, defense mode, disabled and the selected guardian star.
+const MONSTER_FEATURES: usize = CARD_FEATURES + 4 + GUARDIAN_STARS;
+// Present, then the card, if it can be seen.
+const SPELL_FEATURES: usize = 1 + CARD_FEATURES;
+// Life points, deck size, hand size, and whether and for how long Swords of Revealing Light is active.
+const PLAYER_SCALARS: usize = 5;
+
+// Turns a protocol::Observation into a vector of a fixed size. Cards that can't be seen, and empty positions, are all zeros.
+// The card ID is kept as a number, e.g. for an embedding. Hands longer than the hand slots are cut off.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+pub struct ObservationEncoder {
+    pub hand_slots: usize,
+    pub row_slots: usize,
+}
+
+impl ObservationEncoder {
+    pub fn size(&self) -> usize {
+        let player = PLAYER_SCALARS
+            + self.hand_slots * CARD_FEATURES
+            + self.row_slots * (MONSTER_FEATURES + SPELL_FEATURES);
+        1 + PHASES + TERRAINS + MONSTER_FEATURES + 2 * player
+    }
+
+    pub fn encode(&self, observation: &protocol::Observation) -> Vec<f32> {
+        let mut features = Vec::with_capacity(self.size());
+        features.push(observation.turn as f32 / TURN_SCALE);
+        let phase = match observation.phase {
+            Phase::Hand => 0,
+            Phase::Field => 1,
+            Phase::SetGuardianStar => 2,
+            Phase::End => 3,
+        };
+        one_hot(&mut features, Some(phase), PHASES);
+        one_hot(
+            &mut features,
+            Some(observation.terrain_type as usize),
+            TERRAINS,
+        );
+        encode_monster(&mut features, observation.pending_monster.as_ref());
+        self.encode_player(&mut features, &observation.own);
+        self.encode_player(&mut features, &observation.enemy);
+        debug_assert_eq!(features.len(), self.size());
+        features
+    }
+
+    fn encode_player(&self, features: &mut Vec<f32>, player: &PlayerView) {
+        features.extend([
+            player.life_points as f32 / LIFE_POINTS_SCALE,
+            player.deck_size as f32 / DECK_SCALE,
+            player.hand_size as f32,
+            player.sorl_effect_countdown.is_some() as u8 as f32,
+            player.sorl_effect_countdown.unwrap_or(0) as f32,
+        ]);
+        let hand = player.hand.as_deref().unwrap_or_default();
+        for slot in 0..self.hand_slots {
+            encode_card(features, hand.get(slot));
+        }
+        for slot in 0..self.row_slots {
+            let monster = player.monster_row.get(slot).and_then(Option::as_ref);
+            encode_monster(features, monster);
+        }
+        for slot in 0..self.row_slots {
+            let spell = player.spell_row.get(slot).and_then(Option::as_ref);
+            features.push(spell.is_some() as u8 as f32);
+            encode_card(features, spell.and_then(|spell| spell.card.as_ref()));
+        }
+    }
+}
+
+fn one_hot(features: &mut Vec<f32>, index: Option<usize>, len: usize) {
+    features.extend((0..len).map(|i| (Some(i) == index) as u8 as f32));
+}
+
+fn star_index(star: GuardianStarType) -> usize {
+    star as usize
+}
+
+fn encode_card(features: &mut Vec<f32>, card: Option<&CardView>) {
+    let Some(card) = card else {
+        features.extend([0.0; CARD_FEATURES]);
+        return;
+    };
+    features.extend([1.0, card.id as f32]);
+    one_hot(
+        features,
+        CARD_KINDS.iter().position(|kind| *kind == card.kind),
+        CARD_KINDS.len(),
+    );
+    features.extend([
+        card.attack.unwrap_or(0) as f32 / STAT_SCALE,
+        card.defense.unwrap_or(0) as f32 / STAT_SCALE,
+    ]);
+    let stars = card.guardian_stars.map(|stars| stars.map(star_index));
+    features.extend(
+        (0..GUARDIAN_STARS).map(|i| stars.is_some_and(|stars| stars.contains(&i)) as u8 as f32),
+    );
+}
+
+fn encode_monster(features: &mut Vec<f32>, monster: Option<&MonsterView>) {
+    encode_card(features, monster.and_then(|monster| monster.card.as_ref()));
+    let Some(monster) = monster else {
+        features.extend([0.0; 4 + GUARDIAN_STARS]);
+        return;
+    };
+    features.extend([
+        1.0,
+        (monster.face_direction == FaceDirection::Down) as u8 as f32,
+        (monster.card_mode == CardMode::Defense) as u8 as f32,
+        monster.disabled as u8 as f32,
+    ]);
+    one_hot(
+        features,
+        monster.guardian_star.map(star_index),
+        GUARDIAN_STARS,
+    );
+}
+
+// What the agent sees after a reset or a step. The mask is all false once the duel is over.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Observation {
+    pub features: Vec<f32>,
+    pub action_mask: Vec<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepInfo {
+    pub command: DuelCommandEnum,
+    // What the opponent played after the agent's command, until it was the agent's turn again.
+    pub opponent_commands: Vec<DuelCommandEnum>,
+    pub end_state: Option<EndState>,
+    // Whether the duel was cut off by EnvConfig::max_steps rather than won or lost.
+    pub truncated: bool,
+    pub turn: u32,
+}
+
+// The observation, reward, whether the episode is done, and what happened.
+pub type Step = (Observation, f32, bool, StepInfo);
+
+#[derive(Clone)]
+pub struct EnvConfig {
+    // The decks, duelists and ruleset of every duel. The seed is replaced by the one passed to reset.
+    pub new_duel: NewDuel,
+    // The side the agent plays. Player1 takes the first turn.
+    pub player: PlayerEnum,
+    pub opponent: StrategyFactory,
+    // The budget of the opponent for every command.
+    pub budget: Budget,
+    // Whether the agent can see the enemy's hand and face-down cards.
+    pub perfect_information: bool,
+    // Winning is worth 1 and losing -1. On top of that, every step is rewarded this much times the change in
+    // the agent's life point lead, as a fraction of the starting life points.
+    pub life_points_reward: f32,
+    // The most steps an episode may take, since some pairs of strategies never finish a duel.
+    pub max_steps: Option<usize>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            new_duel: NewDuel::default(),
+            player: PlayerEnum::Player1,
+            opponent: random_strategy(),
+            budget: Budget::nodes(1000),
+            perfect_information: false,
+            life_points_reward: 0.0,
+            max_steps: Some(1000),
+        }
+    }
+}
+
+struct Episode {
+    duel: Duel,
+    opponent: Box<dyn BudgetedStrategy>,
+    steps: usize,
+}
+
+pub struct Env {
+    config: EnvConfig,
+    actions: ActionTable,
+    encoder: ObservationEncoder,
+    episode: Option<Episode>,
+}
+
+impl Env {
+    // The action table and observation size are fixed by the agent's hand size and the row size of the first duel.
+    pub fn new(config: EnvConfig) -> Result<Self, EnvError> {
+        let (duel, _) = config.new_duel.build()?;
+        let hand_slots = duel.get_player_by_enum(config.player).hand_size;
+        let row_slots = duel.ruleset.row_size;
+        Ok(Self {
+            actions: ActionTable::new(hand_slots, row_slots),
+            encoder: ObservationEncoder {
+                hand_slots,
+                row_slots,
+            },
+            config,
+            episode: None,
+        })
+    }
+
+    pub fn actions(&self) -> &ActionTable {
+        &self.actions
+    }
+
+    pub fn observation_size(&self) -> usize {
+        self.encoder.size()
+    }
+
+    // The current duel, if the environment was reset.
+    pub fn duel(&self) -> Option<&Duel> {
+        self.episode.as_ref().map(|episode| &episode.duel)
+    }
+
+    // Starts a new duel, which is the same for the same seed. If the opponent goes first, its turn is played already.
+    pub fn reset(&mut self, seed: u64) -> Result<Observation, EnvError> {
+        let (duel, _) = NewDuel {
+            seed: Some(seed),
+            ..self.config.new_duel.clone()
+        }
+        .build()?;
+        // the opponent's seed is drawn rather than reused, so it does not follow the same stream as the decks
+        let opponent = (self.config.opponent)(StdRng::seed_from_u64(seed).gen());
+        let mut episode = Episode {
+            duel,
+            opponent,
+            steps: 0,
+        };
+        self.play_opponent(&mut episode);
+        let observation = self.observe(&episode.duel);
+        self.episode = Some(episode);
+        Ok(observation)
+    }
+
+    // Plays the command at the index of the action table, then the opponent's commands until it's the agent's turn again.
+    pub fn step(&mut self, action: usize) -> Result<Step, EnvError> {
+        let mut episode = self.episode.take().ok_or(EnvError::NotReset)?;
+        // only actions in the mask are played, since a command can fail after it already changed the duel
+        let command = match self.actions.command(action) {
+            Some(command) if self.actions.is_legal(&episode.duel, action) => command,
+            _ => {
+                self.episode = Some(episode);
+                return Err(EnvError::IllegalAction(action));
+            }
+        };
+        let lead_before = self.life_points_lead(&episode.duel);
+        episode
+            .duel
+            .execute(&command)
+            .expect("A legal action failed to execute");
+        episode.steps += 1;
+        if !is_over(&episode.duel) {
+            episode.opponent.on_enemy_command(&episode.duel, &command);
+        }
+        let opponent_commands = self.play_opponent(&mut episode);
+
+        let duel = &episode.duel;
+        let end_state = match &duel.state {
+            DuelStateEnum::EndState(end) => Some(end.clone()),
+            _ => None,
+        };
+        let truncated = end_state.is_none()
+            && self
+                .config
+                .max_steps
+                .is_some_and(|max_steps| episode.steps >= max_steps);
+        let mut reward = self.config.life_points_reward
+            * (self.life_points_lead(duel) - lead_before)
+            / duel.ruleset.starting_life_points as f32;
+        if let Some(end) = &end_state {
+            reward += if end.winner == self.config.player {
+                1.0
+            } else {
+                -1.0
+            };
+        }
+        let done = end_state.is_some() || truncated;
+        let info = StepInfo {
+            command: command.clone(),
+            opponent_commands,
+            end_state,
+            truncated,
+            turn: duel.turn,
+        };
+        let observation = self.observe(duel);
+        if !done {
+            self.episode = Some(episode);
+        }
+        Ok((observation, reward, done, info))
+    }
+
+    fn observe(&self, duel: &Duel) -> Observation {
+        let observation =
+            protocol::Observation::new(duel, self.config.player, self.config.perfect_information);
+        Observation {
+            features: self.encoder.encode(&observation),
+            action_mask: if is_over(duel) {
+                vec![false; self.actions.len()]
+            } else {
+                self.actions.mask(duel)
+            },
+        }
+    }
+
+    fn life_points_lead(&self, duel: &Duel) -> f32 {
+        duel.get_player_by_enum(self.config.player).life_points as f32
+            - duel.get_enemy_by_enum(self.config.player).life_points as f32
+    }
+
+    fn play_opponent(&self, episode: &mut Episode) -> Vec<DuelCommandEnum> {
+        let mut commands = Vec::new();
+        while !is_over(&episode.duel) && episode.duel.get_player_enum() != self.config.player {
+            let command = episode
+                .opponent
+                .decide(&episode.duel, self.config.budget)
+                .command;
+            episode.duel.execute(&command).unwrap();
+            commands.push(command);
+        }
+        if is_over(&episode.duel) {
+            episode.opponent.on_duel_end(&episode.duel);
+        }
+        commands
+    }
+}
+
+fn is_over(duel: &Duel) -> bool {
+    matches!(duel.state, DuelStateEnum::EndState(_))
+}
+
+// The observations of a batch of environments, flattened row by row: features has observation_size numbers per
+// environment, and action_masks has one entry per action for every environment.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct BatchObservation {
+    pub features: Vec<f32>,
+    pub action_masks: Vec<bool>,
+}
+
+impl BatchObservation {
+    fn push(&mut self, observation: Observation) {
+        self.features.extend(observation.features);
+        self.action_masks.extend(observation.action_mask);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchStep {
+    // For environments that are done, this is the first observation of their next duel.
+    pub observation: BatchObservation,
+    pub rewards: Vec<f32>,
+    pub dones: Vec<bool>,
+    pub infos: Vec<StepInfo>,
+    // The last observation of the duels that ended.
+    pub final_observations: Vec<Option<Observation>>,
+}
+
+// Environments that are stepped together. Strategies can't be moved between threads, so they are stepped one after the other;
+// run one VecEnv per process to use more cores.
+pub struct VecEnv {
+    envs: Vec<Env>,
+    // Picks the seeds of new duels.
+    rng: StdRng,
+}
+
+impl VecEnv {
+    pub fn new(config: EnvConfig, count: usize) -> Result<Self, EnvError> {
+        let envs = (0..count)
+            .map(|_| Env::new(config.clone()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            envs,
+            rng: StdRng::seed_from_u64(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    pub fn envs(&self) -> &[Env] {
+        &self.envs
+    }
+
+    // Resets every environment. The seed decides the duels of this and every later episode.
+    pub fn reset(&mut self, seed: u64) -> Result<BatchObservation, EnvError> {
+        self.rng = StdRng::seed_from_u64(seed);
+        let mut batch = BatchObservation::default();
+        for env in &mut self.envs {
+            batch.push(env.reset(self.rng.gen())?);
+        }
+        Ok(batch)
+    }
+
+    // Steps every environment with its action, and resets the ones that are done.
+    pub fn step(&mut self, actions: &[usize]) -> Result<BatchStep, EnvError> {
+        assert_eq!(
+            actions.len(),
+            self.envs.len(),
+            "Expected one action per environment"
+        );
+        let mut batch = BatchStep {
+            observation: BatchObservation::default(),
+            rewards: Vec::with_capacity(actions.len()),
+            dones: Vec::with_capacity(actions.len()),
+            infos: Vec::with_capacity(actions.len()),
+            final_observations: Vec::with_capacity(actions.len()),
+        };
+        for (env, &action) in self.envs.iter_mut().zip(actions) {
+            let (observation, reward, done, info) = env.step(action)?;
+            if done {
+                batch.observation.push(env.reset(self.rng.gen())?);
+                batch.final_observations.push(Some(observation));
+            } else {
+                batch.observation.push(observation);
+                batch.final_observations.push(None);
+            }
+            batch.rewards.push(reward);
+            batch.dones.push(done);
+            batch.infos.push(info);
+        }
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_table() {
+        let table = ActionTable::new(5, 5);
+        for index in 0..table.len() {
+            let command = table.command(index).unwrap();
+            assert_eq!(table.index(&command), Some(index));
+        }
+        assert!(table.command(table.len()).is_none());
+
+        let duel = Duel::random();
+        let mask = table.mask(&duel);
+        let valid = duel.generate_all_valid_commands();
+        dbg!(table.len(), valid.len());
+        assert_eq!(mask.iter().filter(|&&legal| legal).count(), valid.len());
+        for (action, &legal) in mask.iter().enumerate() {
+            assert_eq!(table.is_legal(&duel, action), legal);
+        }
+    }
+
+    #[test]
+    fn test_action_table_large_hand() {
+        let table = ActionTable::new(20, 5);
+        dbg!(table.len());
+        // 240 single plays, 1983980 orders of cards for every field index, then 63 other commands
+        assert_eq!(table.len(), 240 + 1_983_980 * 5 + 63);
+        let fusion = HandPlayMultipleCmd {
+            hand_indices: vec![19, 0, 8, 12, 3],
+            field_index: 4,
+        };
+        let index = table.index(&fusion.into()).unwrap();
+        assert!(matches!(
+            table.command(index),
+            Some(DuelCommandEnum::HandPlayMultipleCmd(HandPlayMultipleCmd {
+                ref hand_indices,
+                field_index: 4,
+            })) if *hand_indices == [19, 0, 8, 12, 3]
+        ));
+
+        // fusions of any hand slot are in the mask, like they are valid for the engine
+        let table = ActionTable::new(10, 5);
+        let mut duel = Duel::random();
+        duel.player1.hand_size = 10;
+        duel.player1.draw();
+        let mask = table.mask(&duel);
+        let fusion = HandPlayMultipleCmd {
+            hand_indices: vec![9, 0],
+            field_index: 0,
+        };
+        assert!(fusion.check_valid(&duel).is_ok());
+        assert!(mask[table.index(&fusion.into()).unwrap()]);
+        assert_eq!(
+            mask.iter().filter(|&&legal| legal).count(),
+            duel.valid_commands().len()
+        );
+    }
+
+    #[test]
+    fn test_vec_env() {
+        let mut envs = VecEnv::new(
+            EnvConfig {
+                max_steps: Some(50),
+                ..Default::default()
+            },
+            2,
+        )
+        .unwrap();
+        let actions = envs.envs()[0].actions().len();
+        let size = envs.envs()[0].observation_size();
+        let mut observation = envs.reset(1).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let mut dones = 0;
+        while dones == 0 {
+            assert_eq!(observation.features.len(), 2 * size);
+            assert_eq!(observation.action_masks.len(), 2 * actions);
+            // a random legal action for every environment
+            let chosen = observation
+                .action_masks
+                .chunks(actions)
+                .map(|mask| {
+                    let legal = (0..actions).filter(|&i| mask[i]).collect::<Vec<_>>();
+                    legal[rng.gen_range(0..legal.len())]
+                })
+                .collect::<Vec<_>>();
+            let step = envs.step(&chosen).unwrap();
+            dones += step.dones.iter().filter(|&&done| done).count();
+            observation = step.observation;
+        }
+
+        // the same seed plays the same duel
+        let mut env = Env::new(EnvConfig::default()).unwrap();
+        let first = env.reset(7).unwrap();
+        assert_eq!(env.reset(7).unwrap(), first);
+        assert!(matches!(
+            env.step(actions + 1),
+            Err(EnvError::IllegalAction(_))
+        ));
+
+        // with a hand smaller than the hand slots, e.g. when it is not refilled, actions past the hand are illegal
+        let mut env = Env::new(EnvConfig::default()).unwrap();
+        let observation = env.reset(7).unwrap();
+        let duel = &mut env.episode.as_mut().unwrap().duel;
+        duel.get_player_mut().hand.truncate(3);
+        let duel = duel.clone();
+        let fusion = HandPlayMultipleCmd {
+            hand_indices: vec![0, 4],
+            field_index: 0,
+        };
+        let action = env.actions().index(&fusion.into()).unwrap();
+        assert!(observation.action_mask[action]);
+        assert!(!env.actions().mask(&duel)[action]);
+        assert!(matches!(env.step(action), Err(EnvError::IllegalAction(_))));
+        assert_eq!(env.duel(), Some(&duel));
+    }
+}
@@ -0,0 +1,508 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use thiserror::Error;
+
+use crate::{
+    duel::{
+        command::DuelCommandEnum,
+        command_strategy::{CommandStrategy, SeededRandomCommandStrategy},
+        deck::generate_random_deck_with_rng,
+        invariants::{set_invariant_checks, InvariantFailure},
+        player::Player,
+        state::DuelStateEnum,
+    },
+    Duel,
+};
+
+// Every case here is a fixed crash, replayed by the regression test below.
+pub const CRASH_FIXTURES_DIR: &str = "fixtures/crashes";
+// Minimized crashes are written here, and moved to CRASH_FIXTURES_DIR once their bug is fixed.
+pub const OPEN_CRASHES_DIR: &str = "fixtures/crashes/open";
+
+#[derive(Error, Debug)]
+pub enum FuzzError {
+    #[error("Failed to access the crash corpus: {0}.")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to read or write a crash: {0}.")]
+    Json(#[from] serde_json::Error),
+}
+
+// Where in the source a panic happened.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+pub struct PanicLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PanicReport {
+    pub location: PanicLocation,
+    pub message: String,
+    // The invariants that were broken, if the panic came from the invariant checker.
+    #[serde(default)]
+    pub invariants: Option<String>,
+}
+
+// Crashes with the same signature are considered the same bug.
+// Every invariant failure panics at the same location, so they are told apart by which invariants were broken.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+pub struct CrashSignature {
+    pub location: PanicLocation,
+    pub invariants: Option<String>,
+}
+
+impl fmt::Display for CrashSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.invariants {
+            Some(invariants) => write!(f, "{} ({})", self.location, invariants),
+            None => write!(f, "{}", self.location),
+        }
+    }
+}
+
+impl PanicReport {
+    pub fn signature(&self) -> CrashSignature {
+        CrashSignature {
+            location: self.location.clone(),
+            invariants: self.invariants.clone(),
+        }
+    }
+}
+
+// A duel and the commands that were executed on it. This is also the format of the crash files written by older versions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Crash {
+    pub starting_duel_state: Duel,
+    pub commands_list: Vec<DuelCommandEnum>,
+}
+
+// A minimized crash, as stored in the corpus.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashCase {
+    pub panic: PanicReport,
+    #[serde(flatten)]
+    pub crash: Crash,
+}
+
+thread_local! {
+    // While set, panics on this thread are recorded in LAST_PANIC instead of being printed.
+    static CAPTURE_PANICS: Cell<bool> = const { Cell::new(false) };
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CAPTURE_PANICS.with(|capture| capture.get()) {
+                return default_hook(info);
+            }
+            let location = info
+                .location()
+                .map(|location| PanicLocation {
+                    file: location.file().to_string(),
+                    line: location.line(),
+                    column: location.column(),
+                })
+                .unwrap_or(PanicLocation {
+                    file: "unknown".to_string(),
+                    line: 0,
+                    column: 0,
+                });
+            let payload = info.payload();
+            let failure = payload.downcast_ref::<InvariantFailure>();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .or_else(|| failure.map(|failure| failure.to_string()))
+                .unwrap_or_default();
+            let invariants = failure.map(|failure| {
+                failure
+                    .violations
+                    .iter()
+                    .map(|violation| violation.name())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            });
+            LAST_PANIC.with(|last| {
+                *last.borrow_mut() = Some(PanicReport {
+                    location,
+                    message,
+                    invariants,
+                })
+            });
+        }));
+    });
+}
+
+// Runs the closure, returning the location of the panic if it panicked.
+fn catch_panic<F: FnOnce()>(f: F) -> Option<PanicReport> {
+    install_panic_hook();
+    CAPTURE_PANICS.with(|capture| capture.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CAPTURE_PANICS.with(|capture| capture.set(false));
+    let report = LAST_PANIC.with(|last| last.borrow_mut().take());
+    result
+        .err()
+        .map(|_| report.expect("panic hook did not record the panic"))
+}
+
+impl Crash {
+    // Replays the commands, skipping any the duel rejects. Returns the commands that were accepted, and the panic if there was one.
+    // A command that panics counts as accepted, so the accepted commands always reproduce the panic.
+    fn run(&self) -> (Vec<DuelCommandEnum>, Option<PanicReport>) {
+        let mut duel = self.starting_duel_state.clone();
+        let mut accepted = Vec::new();
+        let panic = catch_panic(|| {
+            for command in &self.commands_list {
+                accepted.push(command.clone());
+                if duel.execute(command).is_err() {
+                    accepted.pop();
+                }
+            }
+        });
+        (accepted, panic)
+    }
+
+    pub fn replay(&self) -> Option<PanicReport> {
+        self.run().1
+    }
+
+    pub fn reproduces(&self, signature: &CrashSignature) -> bool {
+        self.replay()
+            .is_some_and(|report| &report.signature() == signature)
+    }
+
+    // Shrinks the crash to as few commands and deck cards as possible, while still panicking with the same signature.
+    // Returns None if the crash does not panic at all.
+    pub fn minimize(&self) -> Option<CrashCase> {
+        let signature = self.replay()?.signature();
+        let mut crash = self.clone();
+
+        crash.commands_list = ddmin(&crash.commands_list, |commands| {
+            Crash {
+                commands_list: commands.to_vec(),
+                ..crash.clone()
+            }
+            .reproduces(&signature)
+        });
+
+        // Cards are drawn from the end of the deck, so the cards near the start are often never needed.
+        for player in [0, 1] {
+            let deck = match player {
+                0 => &crash.starting_duel_state.player1.deck,
+                _ => &crash.starting_duel_state.player2.deck,
+            };
+            let deck = ddmin(deck, |deck| {
+                let mut candidate = crash.clone();
+                match player {
+                    0 => candidate.starting_duel_state.player1.deck = deck.to_vec(),
+                    _ => candidate.starting_duel_state.player2.deck = deck.to_vec(),
+                }
+                candidate.reproduces(&signature)
+            });
+            match player {
+                0 => crash.starting_duel_state.player1.deck = deck,
+                _ => crash.starting_duel_state.player2.deck = deck,
+            }
+        }
+
+        // Drop the commands that became invalid after shrinking, so the case replays without skipping anything.
+        let (accepted, panic) = crash.run();
+        crash.commands_list = accepted;
+        Some(CrashCase {
+            panic: panic?,
+            crash,
+        })
+    }
+}
+
+// Delta debugging: repeatedly removes chunks of the items for as long as `test` still passes, returning a minimal list.
+pub fn ddmin<T: Clone, F: FnMut(&[T]) -> bool>(items: &[T], mut test: F) -> Vec<T> {
+    let mut items = items.to_vec();
+    let mut granularity = 2;
+
+    while items.len() >= 2 {
+        let chunk_size = items.len().div_ceil(granularity);
+        let mut reduced = false;
+        for start in (0..items.len()).step_by(chunk_size) {
+            let end = (start + chunk_size).min(items.len());
+            let complement = [&items[..start], &items[end..]].concat();
+            if test(&complement) {
+                items = complement;
+                granularity = (granularity - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if granularity >= items.len() {
+                break;
+            }
+            granularity = (granularity * 2).min(items.len());
+        }
+    }
+
+    if items.len() == 1 && test(&[]) {
+        items.clear();
+    }
+    items
+}
+
+// A directory of crash cases, holding at most one case per crash signature.
+pub struct CrashCorpus {
+    pub dir: PathBuf,
+    signatures: HashSet<CrashSignature>,
+}
+
+impl CrashCorpus {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, FuzzError> {
+        let dir = dir.as_ref().to_path_buf();
+        let signatures = load_cases(&dir)?
+            .into_iter()
+            .map(|case| case.panic.signature())
+            .collect();
+        Ok(Self { dir, signatures })
+    }
+
+    pub fn contains(&self, signature: &CrashSignature) -> bool {
+        self.signatures.contains(signature)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    // Writes the case to the corpus, unless a case with the same signature is already there.
+    pub fn add(&mut self, case: &CrashCase) -> Result<Option<PathBuf>, FuzzError> {
+        let signature = case.panic.signature();
+        if self.contains(&signature) {
+            return Ok(None);
+        }
+
+        // e.g. src/duel/command.rs:120:5 is stored as crash-src_duel_command_rs-120-5.json
+        let location = &signature.location;
+        let mut file_name = format!(
+            "crash-{}-{}-{}",
+            location
+                .file
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            location.line,
+            location.column
+        );
+        if let Some(invariants) = &signature.invariants {
+            file_name += &format!("-{}", invariants.replace(',', "-"));
+        }
+        file_name += ".json";
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(file_name);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), case)?;
+
+        self.signatures.insert(signature);
+        Ok(Some(path))
+    }
+}
+
+// Loads every case in the directory. A missing directory is treated as empty.
+pub fn load_cases<P: AsRef<Path>>(dir: P) -> Result<Vec<CrashCase>, FuzzError> {
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "json")
+    });
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?))
+        .collect()
+}
+
+pub struct FuzzConfig {
+    pub duels: usize,
+    pub workers: usize,
+    // Duel i uses seed + i for its decks and strategy, so a campaign can be repeated.
+    pub seed: u64,
+}
+
+// A command that the strategy generated as valid, but that the duel then rejected.
+// It does not panic, so it can't be minimized like a crash, but the seed plays the same duel again.
+#[derive(Debug, Clone)]
+pub struct RejectedCommand {
+    pub seed: u64,
+    pub command: DuelCommandEnum,
+    pub error: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FuzzReport {
+    pub duels: usize,
+    pub crashes: usize,
+    // The files written for crashes with signatures that were not in the corpus yet.
+    pub new_cases: Vec<PathBuf>,
+    pub rejected_commands: Vec<RejectedCommand>,
+}
+
+enum FuzzOutcome {
+    Finished,
+    Crashed(PanicReport, Box<Crash>),
+    Rejected(RejectedCommand),
+}
+
+// Plays a random duel to the end, which is the same for the same seed.
+fn fuzz_duel(seed: u64) -> FuzzOutcome {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let player1 = Player::new(generate_random_deck_with_rng(&mut rng));
+    let player2 = Player::new(generate_random_deck_with_rng(&mut rng));
+    let mut duel = Duel::new(player1, player2);
+    let starting_duel_state = duel.clone();
+    let strategy = SeededRandomCommandStrategy::new(rng.gen());
+    let mut commands_list = Vec::new();
+    let mut rejected = None;
+
+    let panic = catch_panic(|| {
+        while !matches!(duel.state, DuelStateEnum::EndState(_)) {
+            let command = strategy.get_command(&duel);
+            commands_list.push(command.clone());
+            if let Err(error) = duel.execute(&command) {
+                rejected = Some(RejectedCommand {
+                    seed,
+                    command,
+                    error: error.to_string(),
+                });
+                break;
+            }
+        }
+    });
+
+    match (panic, rejected) {
+        (Some(panic), _) => FuzzOutcome::Crashed(
+            panic,
+            Box::new(Crash {
+                starting_duel_state,
+                commands_list,
+            }),
+        ),
+        (None, Some(rejected)) => FuzzOutcome::Rejected(rejected),
+        (None, None) => FuzzOutcome::Finished,
+    }
+}
+
+// Plays random duels on a pool of worker threads, checking the invariants after every command.
+// Every crash with a new signature is minimized and added to the corpus.
+// The invariant checks are enabled while fuzzing, and set back to how they were afterwards.
+pub fn fuzz(config: &FuzzConfig, corpus: &mut CrashCorpus) -> Result<FuzzReport, FuzzError> {
+    let previous = set_invariant_checks(true);
+    let report = fuzz_checked(config, corpus);
+    set_invariant_checks(previous);
+    report
+}
+
+fn fuzz_checked(config: &FuzzConfig, corpus: &mut CrashCorpus) -> Result<FuzzReport, FuzzError> {
+    let next_duel = AtomicUsize::new(0);
+    let crashes = Mutex::new(Vec::new());
+    let rejected_commands = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..config.workers.max(1) {
+            scope.spawn(|| loop {
+                let i = next_duel.fetch_add(1, Ordering::Relaxed);
+                if i >= config.duels {
+                    break;
+                }
+                match fuzz_duel(config.seed.wrapping_add(i as u64)) {
+                    FuzzOutcome::Crashed(panic, crash) => {
+                        crashes.lock().unwrap().push((panic, *crash))
+                    }
+                    FuzzOutcome::Rejected(rejected) => {
+                        rejected_commands.lock().unwrap().push(rejected)
+                    }
+                    FuzzOutcome::Finished => {}
+                }
+            });
+        }
+    });
+
+    let crashes = crashes.into_inner().unwrap();
+    let mut new_cases = Vec::new();
+    for (panic, crash) in &crashes {
+        // Minimizing is slow, so only do it once per signature.
+        if corpus.contains(&panic.signature()) {
+            continue;
+        }
+        if let Some(case) = crash.minimize() {
+            if let Some(path) = corpus.add(&case)? {
+                new_cases.push(path);
+            }
+        }
+    }
+
+    Ok(FuzzReport {
+        duels: config.duels,
+        crashes: crashes.len(),
+        new_cases,
+        rejected_commands: rejected_commands.into_inner().unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ddmin() {
+        // the smallest list that still contains both 3 and 7
+        let items = (0..20).collect::<Vec<_>>();
+        let minimized = ddmin(&items, |items| items.contains(&3) && items.contains(&7));
+        assert_eq!(minimized, vec![3, 7]);
+
+        let report = catch_panic(|| panic!("boom")).unwrap();
+        assert_eq!(report.message, "boom");
+        assert!(report.location.file.ends_with("fuzz.rs"));
+        assert!(catch_panic(|| {}).is_none());
+    }
+
+    // Every fixed crash is a regression test: its case must replay without panicking. Open crashes are not run.
+    #[test]
+    fn test_crash_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(CRASH_FIXTURES_DIR);
+        for case in load_cases(dir).unwrap() {
+            let panic = case.crash.replay();
+            assert!(
+                panic.is_none(),
+                "crash at {} still reproduces: {:?}",
+                case.panic.signature(),
+                panic
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Serves the JSON API of the api module over HTTP, for tools running on the same machine.
+// GET /duels/{id}/events upgrades to a WebSocket, which receives every DuelEvent of the duel as a JSON text message
+// and is closed when the duel is removed.
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use thiserror::Error;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use crate::api::{duel_id_from_path, Api, ApiResponse, DuelEvent};
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("Failed to start the server: {0}.")]
+    Bind(String),
+}
+
+pub struct HttpServer {
+    server: Server,
+    api: Arc<Api>,
+}
+
+impl HttpServer {
+    pub fn bind(address: &str, api: Arc<Api>) -> Result<Self, HttpError> {
+        let server = Server::http(address).map_err(|error| HttpError::Bind(error.to_string()))?;
+        Ok(Self { server, api })
+    }
+
+    // The address the server listens on, e.g. to find the port when binding to port 0.
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    // Answers requests one at a time, forever. Every WebSocket is served on its own thread.
+    pub fn run(&self) {
+        for request in self.server.incoming_requests() {
+            self.respond(request);
+        }
+    }
+
+    fn respond(&self, mut request: Request) {
+        let websocket_key = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+            .map(|header| header.value.to_string());
+        if let (Some(key), Some(id)) = (websocket_key, duel_id_from_path(request.url(), "events")) {
+            // an unknown duel gets the usual 404 below
+            if let Ok(events) = self.api.subscribe(id) {
+                stream_events(request, &key, events);
+                return;
+            }
+        }
+
+        let mut body = String::new();
+        let response = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => self
+                .api
+                .handle(request.method().as_str(), request.url(), &body),
+            Err(error) => ApiResponse {
+                status: 400,
+                body: json!({ "error": format!("Failed to read the request: {}.", error) })
+                    .to_string(),
+            },
+        };
+        // a client that hung up early only loses its own response
+        let _ = request.respond(
+            Response::from_string(response.body)
+                .with_status_code(StatusCode(response.status))
+                .with_header(header("Content-Type: application/json")),
+        );
+    }
+}
+
+fn header(text: &str) -> Header {
+    text.parse().expect("Invalid header")
+}
+
+fn stream_events(request: Request, key: &str, events: Receiver<DuelEvent>) {
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Upgrade: websocket"))
+        .with_header(header("Connection: Upgrade"))
+        .with_header(header(&format!(
+            "Sec-WebSocket-Accept: {}",
+            derive_accept_key(key.as_bytes())
+        )));
+    let stream = request.upgrade("websocket", response);
+    std::thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for event in events {
+            let text = serde_json::to_string(&event).expect("Failed to serialize an event");
+            if socket.send(Message::Text(text)).is_err() {
+                return;
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_http_server() {
+        let server = HttpServer::bind("127.0.0.1:0", Arc::new(Api::new())).unwrap();
+        let address = server.address().unwrap();
+        std::thread::spawn(move || server.run());
+
+        let response = request(address, "GET", "/cards/1", "");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        let response = request(address, "POST", "/duels", r#"{"seed": 1}"#);
+        assert!(response.starts_with("HTTP/1.1 201"));
+
+        let (mut socket, _) =
+            tungstenite::connect(format!("ws://{}/duels/1/events", address)).unwrap();
+        let response = request(
+            address,
+            "POST",
+            "/duels/1/commands",
+            r#"{"HandPlaySingleCmd": {"hand_index": 0, "face_direction": "Down", "field_index": 0}}"#,
+        );
+        dbg!(&response);
+        assert!(response.starts_with("HTTP/1.1 200"));
+        let Message::Text(text) = socket.read().unwrap() else {
+            panic!("Expected a text message");
+        };
+        let event: DuelEvent = serde_json::from_str(&text).unwrap();
+        assert_eq!(event.turn, 0);
+    }
+}
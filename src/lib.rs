@@ -4,8 +4,18 @@
 
 extern crate test;
 
+pub mod api;
 pub mod data;
 pub mod duel;
+pub mod env;
+pub mod fuzz;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod protocol;
+pub mod replay;
+pub mod simulate;
+pub mod stats;
+pub mod tournament;
 
 pub use data::*;
 pub use duel::Duel;
@@ -2,29 +2,55 @@
 
 use std::fs::File;
 use std::io::BufReader;
-use std::thread;
 
-use dioxus::prelude::*;
-use dioxus_desktop::WindowBuilder;
-use fmsim::duel::command::{DuelCommand, DuelCommandEnum};
-use fmsim::duel::command_strategy::{CommandStrategy, RandomCommandStrategy};
-use fmsim::duel::field::{MonsterRowPosition, SpellRowPosition};
-use fmsim::duel::state::DuelStateEnum;
-use fmsim::{card_from_name, Card, Duel};
-use serde::{Deserialize, Serialize};
+use fmsim::fuzz::{fuzz, Crash, CrashCorpus, FuzzConfig, OPEN_CRASHES_DIR};
+use fmsim::Duel;
 
-// derive serde
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct Crash {
-    starting_duel_state: Duel,
-    commands_list: Vec<DuelCommandEnum>,
+fn main() {
+    // `fmsim fuzz [duels]` hunts for crashes, and adds every new one to the open crashes after minimizing it.
+    // `fmsim [crash file]` replays a crash, printing the duel before every command.
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.get(1).map(String::as_str) {
+        Some("fuzz") => run_fuzz(
+            args.get(2)
+                .and_then(|duels| duels.parse().ok())
+                .unwrap_or(50000),
+        ),
+        Some(path) => replay(path),
+        None => replay("crashes/crash.json"),
+    }
 }
 
-fn main() {
-    // load crashes/crash.json
+fn run_fuzz(duels: usize) {
+    let mut corpus = CrashCorpus::open(OPEN_CRASHES_DIR).expect("Unable to open crash corpus");
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let report = fuzz(
+        &FuzzConfig {
+            duels,
+            workers,
+            seed: rand::random(),
+        },
+        &mut corpus,
+    )
+    .expect("Unable to write crash");
+
+    println!("Played {} duels, {} crashed.", report.duels, report.crashes);
+    for path in &report.new_cases {
+        println!("New crash: {}", path.display());
+    }
+    for rejected in &report.rejected_commands {
+        println!(
+            "Seed {} generated a command that was rejected: {:?} ({})",
+            rejected.seed, rejected.command, rejected.error
+        );
+    }
+}
+
+fn replay(path: &str) {
+    // load the crash file
     // it contains two fields: starting_duel_state and commands_list
     // parse the json, and then iterate over the commands_list, executing them all on the duel
-    let file = File::open("crashes/crash.json").expect("Unable to open file");
+    let file = File::open(path).expect("Unable to open file");
     let reader = BufReader::new(file);
     let crash_data: Crash = serde_json::from_reader(reader).expect("Unable to parse json");
 
@@ -90,81 +116,9 @@ fn main() {
         );
         println!("Executing command: {:?}", command);
 
-        command.execute(&mut duel).unwrap();
+        duel.execute(command).unwrap();
         // use random command strategy to get a command
         // let strategy = RandomCommandStrategy;
         // let _ = strategy.get_command(&duel);
     }
 }
-
-#[test]
-fn test() {
-    let mut handles = vec![];
-
-    for _ in 1..=50000 {
-        let handle = thread::spawn(|| {
-            let mut duel = fmsim::Duel::random();
-            let starting_duel_state = duel.clone();
-
-            let strategy = RandomCommandStrategy;
-
-            let mut commands_list = Vec::new();
-
-            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                while !matches!(duel.state, DuelStateEnum::EndState(_)) {
-                    let command = strategy.get_command(&duel);
-                    commands_list.push(command.clone());
-                    command.execute(&mut duel).unwrap();
-                }
-            }));
-
-            if result.is_err() {
-                // dbg print the duel state if there is a crash
-                println!("{:?}", duel.state);
-                // print the name of each card in the hand of the current player
-                println!(
-                    "{:?}",
-                    duel.get_player()
-                        .hand
-                        .iter()
-                        .map(|card| card.name.clone())
-                        .collect::<Vec<_>>()
-                );
-                // serialise the starting duel state and commands_list to json. then write to a file.
-                // the filename should be something unique, like the current timestamp.
-                // the file should be written to a folder called "crashes" in the root of the project.
-                // the file should be named "crash-<timestamp>.json"
-                // if the folder doesn't exist, create it.
-                use serde_json::json;
-                use std::fs::File;
-                use std::io::Write;
-                use std::path::Path;
-
-                let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
-                let filename = format!("crash-{}.json", timestamp);
-                let path = Path::new("crashes");
-
-                std::fs::create_dir_all(&path).unwrap();
-
-                let mut file = File::create(path.join("crash.json")).unwrap();
-                let data = json!({
-                    "starting_duel_state": starting_duel_state,
-                    "commands_list": commands_list
-                });
-
-                file.write_all(data.to_string().as_bytes()).unwrap();
-
-                // exit the entire program
-                std::process::exit(1);
-            }
-        });
-
-        handles.push(handle);
-    }
-
-    for handle in handles {
-        handle.join().unwrap();
-    }
-
-    assert!(true);
-}
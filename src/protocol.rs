@@ -0,0 +1,554 @@
+// A line-based JSON protocol for bots that play duels from another process, e.g. over stdin/stdout or TCP.
+// Every message is one JSON object on its own line.
+//
+// The server sends messages tagged with a "type":
+// - {"type": "start", "player": "Player1"} once, with the player the bot plays as.
+// - {"type": "request", "observation": {..}, "legal_commands": [..], "legal_command_count": n} whenever it is the bot's
+//   turn to pick a command. The observation is what the bot's player can see of the duel, see Observation.
+//   Large hands can have millions of legal commands, so only the first MAX_LISTED_COMMANDS are listed.
+// - {"type": "error", "message": ".."} when the bot's reply was not a valid command. The request is sent again.
+// - {"type": "enemy_command", "command": {..}} after the enemy executed a command.
+// - {"type": "result", "winner": "Player2", "win_condition": "LifePoints", "turns": 12} when the duel is over.
+// - {"type": "aborted", "message": ".."} instead of the result when the server failed to finish the duel.
+//
+// The bot replies to every request with one line: either a command in the same format as the legal commands,
+// e.g. {"FieldAttackCmd": {"monster_row_index": 0, "enemy_monster_row_index": 2}}, or {"index": n} to pick the n-th
+// (0-based) legal command, where n can be anything below legal_command_count.
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::panic::{self, AssertUnwindSafe};
+use thiserror::Error;
+
+use crate::{
+    duel::{
+        command::{CommandError, DuelCommand, DuelCommandEnum, MAX_LISTED_COMMANDS},
+        command_strategy::{Budget, BudgetedStrategy},
+        field::{CardMode, FaceDirection, MonsterRowPosition, SpellRowPosition},
+        player::Player,
+        state::{DuelStateEnum, EndState, WinCondition},
+        PlayerEnum,
+    },
+    simulate::panic_message,
+    Card, CardVariant, Duel, GuardianStarType, TerrainType,
+};
+
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Failed to talk to the bot: {0}.")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to write a message: {0}.")]
+    Json(#[from] serde_json::Error),
+    #[error("The bot disconnected.")]
+    Disconnected,
+    #[error("A local strategy picked an invalid command: {0}")]
+    Command(CommandError),
+    #[error("The duel panicked: {0}.")]
+    Panicked(String),
+}
+
+// Everything a bot needs to know about a card, so that it does not need the card database.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CardView {
+    pub id: usize,
+    pub name: String,
+    // "Monster", "Ritual", "Equip", "Magic" or "Trap".
+    pub kind: String,
+    // The current stats of a monster, including equips and the terrain.
+    pub attack: Option<i32>,
+    pub defense: Option<i32>,
+    pub guardian_stars: Option<[GuardianStarType; 2]>,
+}
+
+impl CardView {
+    pub fn new(card: &Card, duel: &Duel) -> Self {
+        let (kind, guardian_stars) = match &card.variant {
+            CardVariant::Monster {
+                guardian_star_a,
+                guardian_star_b,
+                ..
+            } => ("Monster", Some([*guardian_star_a, *guardian_star_b])),
+            CardVariant::Ritual { .. } => ("Ritual", None),
+            CardVariant::Equip { .. } => ("Equip", None),
+            CardVariant::Magic(_) => ("Magic", None),
+            CardVariant::Trap(_) => ("Trap", None),
+        };
+        let stats =
+            card.get_stats_with_terrain_bonus(duel.terrain_type, duel.ruleset.terrain_bonus);
+        Self {
+            id: card.id,
+            name: card.name.clone(),
+            kind: kind.to_string(),
+            attack: stats.map(|(attack, _)| attack),
+            defense: stats.map(|(_, defense)| defense),
+            guardian_stars,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MonsterView {
+    // None for a face-down monster of the enemy.
+    pub card: Option<CardView>,
+    pub face_direction: FaceDirection,
+    pub card_mode: CardMode,
+    pub guardian_star: Option<GuardianStarType>,
+    // Whether the monster already attacked or changed mode this turn.
+    pub disabled: bool,
+}
+
+impl MonsterView {
+    fn new(monster: &MonsterRowPosition, duel: &Duel, hidden: bool) -> Self {
+        let visible = !hidden || monster.face_direction == FaceDirection::Up;
+        Self {
+            card: visible.then(|| CardView::new(&monster.card, duel)),
+            face_direction: monster.face_direction,
+            card_mode: monster.card_mode,
+            guardian_star: visible.then(|| monster.get_selected_gs()),
+            disabled: monster.disabled,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SpellView {
+    // None for a set card of the enemy.
+    pub card: Option<CardView>,
+}
+
+impl SpellView {
+    fn new(spell: &SpellRowPosition, duel: &Duel, hidden: bool) -> Self {
+        Self {
+            card: (!hidden).then(|| CardView::new(&spell.card, duel)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PlayerView {
+    pub life_points: u32,
+    pub deck_size: usize,
+    pub hand_size: usize,
+    // None for the enemy, unless the observation has perfect information.
+    pub hand: Option<Vec<CardView>>,
+    pub monster_row: Vec<Option<MonsterView>>,
+    pub spell_row: Vec<Option<SpellView>>,
+    // The turns left until the enemy's Swords of Revealing Light stop this player's monsters from attacking.
+    pub sorl_effect_countdown: Option<u32>,
+}
+
+impl PlayerView {
+    fn new(player: &Player, duel: &Duel, hidden: bool) -> Self {
+        Self {
+            life_points: player.life_points,
+            deck_size: player.deck.len(),
+            hand_size: player.hand.len(),
+            hand: (!hidden).then(|| {
+                player
+                    .hand
+                    .iter()
+                    .map(|card| CardView::new(card, duel))
+                    .collect()
+            }),
+            monster_row: player
+                .monster_row
+                .iter()
+                .map(|monster| {
+                    monster
+                        .as_ref()
+                        .map(|monster| MonsterView::new(monster, duel, hidden))
+                })
+                .collect(),
+            spell_row: player
+                .spell_row
+                .iter()
+                .map(|spell| {
+                    spell
+                        .as_ref()
+                        .map(|spell| SpellView::new(spell, duel, hidden))
+                })
+                .collect(),
+            sorl_effect_countdown: player.sorl_effect_countdown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Phase {
+    Hand,
+    Field,
+    SetGuardianStar,
+    End,
+}
+
+// What a player can see of a duel: their own hand and field, and the face-up cards of the enemy.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Observation {
+    pub player: PlayerEnum,
+    pub turn: u32,
+    pub terrain_type: TerrainType,
+    pub phase: Phase,
+    // The monster that needs a guardian star in the SetGuardianStar phase.
+    pub pending_monster: Option<MonsterView>,
+    pub own: PlayerView,
+    pub enemy: PlayerView,
+}
+
+impl Observation {
+    // With perfect information, the enemy's hand and face-down cards are included too.
+    pub fn new(duel: &Duel, player: PlayerEnum, perfect_information: bool) -> Self {
+        let (phase, pending_monster) = match &duel.state {
+            DuelStateEnum::HandState(_) => (Phase::Hand, None),
+            DuelStateEnum::FieldState(_) => (Phase::Field, None),
+            DuelStateEnum::SetGuardianStarState(state) => (
+                Phase::SetGuardianStar,
+                Some(MonsterView::new(&state.monster_row_position, duel, false)),
+            ),
+            DuelStateEnum::EndState(_) => (Phase::End, None),
+        };
+        Self {
+            player,
+            turn: duel.turn,
+            terrain_type: duel.terrain_type,
+            phase,
+            pending_monster,
+            own: PlayerView::new(duel.get_player_by_enum(player), duel, false),
+            enemy: PlayerView::new(duel.get_enemy_by_enum(player), duel, !perfect_information),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Start {
+        player: PlayerEnum,
+    },
+    Request {
+        observation: Box<Observation>,
+        legal_commands: Vec<DuelCommandEnum>,
+        legal_command_count: usize,
+    },
+    Error {
+        message: String,
+    },
+    EnemyCommand {
+        command: DuelCommandEnum,
+    },
+    Result {
+        winner: PlayerEnum,
+        win_condition: WinCondition,
+        turns: u32,
+    },
+    Aborted {
+        message: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ClientMessage {
+    Index { index: usize },
+    Command(DuelCommandEnum),
+}
+
+// One bot on the other end of a pair of streams.
+pub struct Connection {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Connection {
+    pub fn new(reader: impl BufRead + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+        }
+    }
+
+    pub fn stdio() -> Self {
+        Self::new(BufReader::new(std::io::stdin()), std::io::stdout())
+    }
+
+    pub fn tcp(stream: TcpStream) -> Result<Self, ProtocolError> {
+        Ok(Self::new(BufReader::new(stream.try_clone()?), stream))
+    }
+
+    pub fn send(&mut self, message: &ServerMessage) -> Result<(), ProtocolError> {
+        serde_json::to_writer(&mut self.writer, message)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // Returns the reply, or an error message for a line that is not a ClientMessage.
+    pub fn receive(&mut self) -> Result<Result<ClientMessage, String>, ProtocolError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ProtocolError::Disconnected);
+        }
+        Ok(serde_json::from_str(&line).map_err(|error| error.to_string()))
+    }
+
+    // Asks for a command until the bot replies with a valid one.
+    pub fn request_command(
+        &mut self,
+        duel: &Duel,
+        player: PlayerEnum,
+        perfect_information: bool,
+    ) -> Result<DuelCommandEnum, ProtocolError> {
+        let valid = duel.valid_commands();
+        let legal_commands = valid.iter().take(MAX_LISTED_COMMANDS).collect::<Vec<_>>();
+        loop {
+            self.send(&ServerMessage::Request {
+                observation: Box::new(Observation::new(duel, player, perfect_information)),
+                legal_commands: legal_commands.clone(),
+                legal_command_count: valid.len(),
+            })?;
+            let command = match self.receive()? {
+                Ok(ClientMessage::Index { index }) => valid
+                    .get(index)
+                    .ok_or_else(|| format!("There are only {} legal commands.", valid.len())),
+                Ok(ClientMessage::Command(command)) => command
+                    .check_valid(duel)
+                    .map(|_| command)
+                    .map_err(|error| error.to_string()),
+                Err(message) => Err(message),
+            };
+            match command {
+                Ok(command) => return Ok(command),
+                Err(message) => self.send(&ServerMessage::Error { message })?,
+            }
+        }
+    }
+}
+
+// Who plays one side of a hosted duel.
+pub enum Seat {
+    Remote(Connection),
+    Local(Box<dyn BudgetedStrategy>),
+}
+
+pub struct HostConfig {
+    // Whether remote bots can see the enemy's hand and face-down cards.
+    pub perfect_information: bool,
+    // The budget of local strategies for every command.
+    pub budget: Budget,
+}
+
+fn seat_index(player: PlayerEnum) -> usize {
+    match player {
+        PlayerEnum::Player1 => 0,
+        PlayerEnum::Player2 => 1,
+    }
+}
+
+// Plays a duel to the end between the seats, where seats[0] is player 1.
+pub fn host_duel(
+    mut duel: Duel,
+    seats: &mut [Seat; 2],
+    config: &HostConfig,
+) -> Result<EndState, ProtocolError> {
+    for (player, seat) in [PlayerEnum::Player1, PlayerEnum::Player2]
+        .into_iter()
+        .zip(seats.iter_mut())
+    {
+        if let Seat::Remote(connection) = seat {
+            connection.send(&ServerMessage::Start { player })?;
+        }
+    }
+
+    loop {
+        if let DuelStateEnum::EndState(end) = &duel.state {
+            for seat in seats.iter_mut() {
+                match seat {
+                    Seat::Remote(connection) => connection.send(&ServerMessage::Result {
+                        winner: end.winner,
+                        win_condition: end.win_condition,
+                        turns: duel.turn,
+                    })?,
+                    Seat::Local(strategy) => strategy.on_duel_end(&duel),
+                }
+            }
+            return Ok(end.clone());
+        }
+
+        let player = duel.get_player_enum();
+        let command = match &mut seats[seat_index(player)] {
+            Seat::Remote(connection) => {
+                connection.request_command(&duel, player, config.perfect_information)?
+            }
+            Seat::Local(strategy) => strategy.decide(&duel, config.budget).command,
+        };
+        if let Err(error) = duel.execute(&command) {
+            // the duel is left as it was, so the same player is asked again
+            match &mut seats[seat_index(player)] {
+                Seat::Remote(connection) => {
+                    connection.send(&ServerMessage::Error {
+                        message: error.to_string(),
+                    })?;
+                    continue;
+                }
+                Seat::Local(_) => return Err(ProtocolError::Command(error)),
+            }
+        }
+
+        match &mut seats[1 - seat_index(player)] {
+            Seat::Remote(connection) => {
+                connection.send(&ServerMessage::EnemyCommand { command })?
+            }
+            Seat::Local(strategy) => {
+                if !matches!(duel.state, DuelStateEnum::EndState(_)) {
+                    strategy.on_enemy_command(&duel, &command);
+                }
+            }
+        }
+    }
+}
+
+// Hosts a duel like host_duel, but a failure, including a panic in the engine or a local strategy, is sent to the
+// remote seats before it is returned, so that a server can go on hosting other duels.
+pub fn host_duel_or_abort(
+    duel: Duel,
+    seats: &mut [Seat; 2],
+    config: &HostConfig,
+) -> Result<EndState, ProtocolError> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| host_duel(duel, seats, config)))
+        .unwrap_or_else(|payload| Err(ProtocolError::Panicked(panic_message(payload.as_ref()))));
+    if let Err(error) = &result {
+        for seat in seats.iter_mut() {
+            if let Seat::Remote(connection) = seat {
+                // the connection may be what failed, in which case there is no one left to tell
+                let _ = connection.send(&ServerMessage::Aborted {
+                    message: error.to_string(),
+                });
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::command_strategy::{
+        CommandStrategyAdapter, Decision, SeededRandomCommandStrategy,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::net::TcpListener;
+
+    // A bot that replies with a random legal command, after first sending something invalid
+    // and then a command with a hand index outside of the hand.
+    fn random_bot(stream: TcpStream) -> Vec<String> {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut received = Vec::new();
+        writeln!(writer, "hello").unwrap();
+        writeln!(
+            writer,
+            r#"{{"HandPlayMultipleCmd":{{"hand_indices":[0,99],"field_index":0}}}}"#
+        )
+        .unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+            if message["type"] == "request"
+                && received
+                    .iter()
+                    .filter(|line: &&String| line.contains("\"type\":\"error\""))
+                    .count()
+                    >= 2
+            {
+                let legal_commands = message["legal_commands"].as_array().unwrap().len();
+                let index = rng.gen_range(0..legal_commands);
+                writeln!(writer, "{{\"index\": {}}}", index).unwrap();
+            }
+            received.push(std::mem::take(&mut line));
+            if message["type"] == "result" || message["type"] == "aborted" {
+                break;
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn test_host_duel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let bot = std::thread::spawn(move || random_bot(TcpStream::connect(address).unwrap()));
+        let (stream, _) = listener.accept().unwrap();
+
+        let duel = Duel::random();
+        let mut seats = [
+            Seat::Remote(Connection::tcp(stream).unwrap()),
+            Seat::Local(Box::new(CommandStrategyAdapter(
+                SeededRandomCommandStrategy::new(0),
+            ))),
+        ];
+        let config = HostConfig {
+            perfect_information: false,
+            budget: Budget::unlimited(),
+        };
+        let end = host_duel(duel, &mut seats, &config).unwrap();
+        let received = bot.join().unwrap();
+        dbg!(&end, received.len());
+
+        assert!(received[0].contains("\"type\":\"start\""));
+        // the invalid reply gets an error, and the request is sent again
+        assert!(received[2].contains("\"type\":\"error\""));
+        assert!(received[3].contains("\"type\":\"request\""));
+        // so does a hand index outside of the hand
+        assert!(received[4].contains("Out-of-bounds Hand Selection."));
+        assert!(received[5].contains("\"type\":\"request\""));
+        assert!(received.last().unwrap().contains("\"type\":\"result\""));
+        assert!(received.iter().any(|line| line.contains("enemy_command")));
+
+        // the enemy's hand is hidden
+        let request: serde_json::Value = serde_json::from_str(&received[1]).unwrap();
+        dbg!(&request["observation"]["own"]);
+        assert!(request["observation"]["enemy"]["hand"].is_null());
+        assert_eq!(
+            request["observation"]["own"]["hand"]
+                .as_array()
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    struct PanickingStrategy;
+
+    impl BudgetedStrategy for PanickingStrategy {
+        fn decide(&mut self, _duel: &Duel, _budget: Budget) -> Decision {
+            panic!("A bug in the strategy")
+        }
+    }
+
+    #[test]
+    fn test_host_duel_or_abort() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let bot = std::thread::spawn(move || random_bot(TcpStream::connect(address).unwrap()));
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut seats = [
+            Seat::Remote(Connection::tcp(stream).unwrap()),
+            Seat::Local(Box::new(PanickingStrategy)),
+        ];
+        let config = HostConfig {
+            perfect_information: false,
+            budget: Budget::unlimited(),
+        };
+        let error = host_duel_or_abort(Duel::random(), &mut seats, &config).unwrap_err();
+        let received = bot.join().unwrap();
+        dbg!(&error, received.last());
+
+        assert!(
+            matches!(error, ProtocolError::Panicked(ref message) if message == "A bug in the strategy")
+        );
+        assert!(received.last().unwrap().contains(
+            r#"{"type":"aborted","message":"The duel panicked: A bug in the strategy."}"#
+        ));
+    }
+}
@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use thiserror::Error;
+
+use crate::{
+    duel::{
+        command::{CommandError, DuelCommandEnum},
+        field::{CardMode, FaceDirection, MonsterRowPosition},
+        state::{DuelStateEnum, WinCondition},
+        PlayerEnum,
+    },
+    fuzz::Crash,
+    simulate::panic_message,
+    Card, Duel, GuardianStarType, TerrainType,
+};
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Failed to access the replay: {0}.")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to read or write the replay: {0}.")]
+    Json(#[from] serde_json::Error),
+    #[error("Command {ply} of the replay cannot be executed: {error}")]
+    InvalidCommand { ply: usize, error: CommandError },
+}
+
+// A duel and the commands that were executed on it.
+// It has the same format as a Crash, so crash files can be loaded as replays. Their last command panics, see states().
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    pub starting_duel_state: Duel,
+    pub commands_list: Vec<DuelCommandEnum>,
+}
+
+impl Replay {
+    pub fn new(duel: Duel) -> Self {
+        Self {
+            starting_duel_state: duel,
+            commands_list: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    // The duel after every ply, starting with the duel before the first command.
+    // A command that panics ends the states, along with the panic message. The duel after it is the duel before it,
+    // since a panic can leave the duel half changed. The commands after it are never executed.
+    pub fn states(&self) -> Result<(Vec<Duel>, Option<String>), ReplayError> {
+        let mut duel = self.starting_duel_state.clone();
+        let mut states = vec![duel.clone()];
+        for (ply, command) in self.commands_list.iter().enumerate() {
+            let mut next = duel.clone();
+            match panic::catch_unwind(AssertUnwindSafe(|| next.execute(command))) {
+                Ok(result) => result.map_err(|error| ReplayError::InvalidCommand { ply, error })?,
+                Err(payload) => {
+                    states.push(duel);
+                    return Ok((states, Some(panic_message(payload.as_ref()))));
+                }
+            }
+            duel = next;
+            states.push(duel.clone());
+        }
+        Ok((states, None))
+    }
+}
+
+impl From<Crash> for Replay {
+    fn from(crash: Crash) -> Self {
+        Self {
+            starting_duel_state: crash.starting_duel_state,
+            commands_list: crash.commands_list,
+        }
+    }
+}
+
+// Something that visibly changed between two consecutive states of a duel.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum StepEvent {
+    LifePoints {
+        player: PlayerEnum,
+        before: u32,
+        after: u32,
+    },
+    Drew {
+        player: PlayerEnum,
+        cards: usize,
+    },
+    // A monster was played, fused, equipped or destroyed.
+    MonsterRow {
+        player: PlayerEnum,
+        index: usize,
+        before: Option<Card>,
+        after: Option<Card>,
+    },
+    SpellRow {
+        player: PlayerEnum,
+        index: usize,
+        before: Option<Card>,
+        after: Option<Card>,
+    },
+    Flipped {
+        player: PlayerEnum,
+        index: usize,
+    },
+    CardMode {
+        player: PlayerEnum,
+        index: usize,
+        card_mode: CardMode,
+    },
+    GuardianStar {
+        player: PlayerEnum,
+        index: usize,
+        guardian_star: GuardianStarType,
+    },
+    Terrain {
+        before: TerrainType,
+        after: TerrainType,
+    },
+    TurnStarted {
+        turn: u32,
+        player: PlayerEnum,
+    },
+    Won {
+        winner: PlayerEnum,
+        win_condition: WinCondition,
+    },
+    // The command panicked, e.g. the last command of a crash file.
+    Panicked {
+        message: String,
+    },
+}
+
+fn card_name(card: &Option<Card>) -> &str {
+    card.as_ref().map_or("nothing", |card| card.name.as_str())
+}
+
+impl fmt::Display for StepEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepEvent::LifePoints {
+                player,
+                before,
+                after,
+            } => write!(
+                f,
+                "{:?}'s life points went from {} to {}.",
+                player, before, after
+            ),
+            StepEvent::Drew { player, cards } => write!(f, "{:?} drew {} cards.", player, cards),
+            StepEvent::MonsterRow {
+                player,
+                index,
+                before,
+                after,
+            } => write!(
+                f,
+                "{:?}'s monster at position {} went from {} to {}.",
+                player,
+                index + 1,
+                card_name(before),
+                card_name(after)
+            ),
+            StepEvent::SpellRow {
+                player,
+                index,
+                before,
+                after,
+            } => write!(
+                f,
+                "{:?}'s spell at position {} went from {} to {}.",
+                player,
+                index + 1,
+                card_name(before),
+                card_name(after)
+            ),
+            StepEvent::Flipped { player, index } => write!(
+                f,
+                "{:?}'s monster at position {} was flipped face up.",
+                player,
+                index + 1
+            ),
+            StepEvent::CardMode {
+                player,
+                index,
+                card_mode,
+            } => write!(
+                f,
+                "{:?}'s monster at position {} switched to {:?} mode.",
+                player,
+                index + 1,
+                card_mode
+            ),
+            StepEvent::GuardianStar {
+                player,
+                index,
+                guardian_star,
+            } => write!(
+                f,
+                "{:?}'s monster at position {} has the guardian star {:?}.",
+                player,
+                index + 1,
+                guardian_star
+            ),
+            StepEvent::Terrain { before, after } => {
+                write!(f, "The terrain changed from {:?} to {:?}.", before, after)
+            }
+            StepEvent::TurnStarted { turn, player } => {
+                write!(f, "Turn {} started for {:?}.", turn, player)
+            }
+            StepEvent::Won {
+                winner,
+                win_condition,
+            } => write!(f, "{:?} won by {:?}.", winner, win_condition),
+            StepEvent::Panicked { message } => write!(f, "The command panicked: {}", message),
+        }
+    }
+}
+
+// What changed from one state of a duel to the next, e.g. before and after a command.
+pub fn step_events(before: &Duel, after: &Duel) -> Vec<StepEvent> {
+    let mut events = Vec::new();
+    if after.terrain_type != before.terrain_type {
+        events.push(StepEvent::Terrain {
+            before: before.terrain_type,
+            after: after.terrain_type,
+        });
+    }
+
+    for player in [PlayerEnum::Player1, PlayerEnum::Player2] {
+        let (old, new) = (
+            before.get_player_by_enum(player),
+            after.get_player_by_enum(player),
+        );
+        if new.life_points != old.life_points {
+            events.push(StepEvent::LifePoints {
+                player,
+                before: old.life_points,
+                after: new.life_points,
+            });
+        }
+        if new.deck.len() < old.deck.len() {
+            events.push(StepEvent::Drew {
+                player,
+                cards: old.deck.len() - new.deck.len(),
+            });
+        }
+
+        for (index, (old_monster, new_monster)) in
+            old.monster_row.iter().zip(&new.monster_row).enumerate()
+        {
+            let card =
+                |monster: &Option<MonsterRowPosition>| monster.as_ref().map(|monster| monster.card);
+            match (old_monster, new_monster) {
+                (Some(old_monster), Some(new_monster)) if old_monster.card == new_monster.card => {
+                    if old_monster.face_direction == FaceDirection::Down
+                        && new_monster.face_direction == FaceDirection::Up
+                    {
+                        events.push(StepEvent::Flipped { player, index });
+                    }
+                    if new_monster.card_mode != old_monster.card_mode {
+                        events.push(StepEvent::CardMode {
+                            player,
+                            index,
+                            card_mode: new_monster.card_mode,
+                        });
+                    }
+                    if new_monster.get_selected_gs() != old_monster.get_selected_gs() {
+                        events.push(StepEvent::GuardianStar {
+                            player,
+                            index,
+                            guardian_star: new_monster.get_selected_gs(),
+                        });
+                    }
+                }
+                (None, None) => {}
+                _ => events.push(StepEvent::MonsterRow {
+                    player,
+                    index,
+                    before: card(old_monster),
+                    after: card(new_monster),
+                }),
+            }
+        }
+
+        for (index, (old_spell, new_spell)) in old.spell_row.iter().zip(&new.spell_row).enumerate()
+        {
+            let (old_card, new_card) = (
+                old_spell.as_ref().map(|spell| spell.card),
+                new_spell.as_ref().map(|spell| spell.card),
+            );
+            if new_card != old_card {
+                events.push(StepEvent::SpellRow {
+                    player,
+                    index,
+                    before: old_card,
+                    after: new_card,
+                });
+            }
+        }
+    }
+
+    if after.turn != before.turn {
+        events.push(StepEvent::TurnStarted {
+            turn: after.turn,
+            player: after.get_player_enum(),
+        });
+    }
+    if let (DuelStateEnum::EndState(end), false) = (
+        &after.state,
+        matches!(before.state, DuelStateEnum::EndState(_)),
+    ) {
+        events.push(StepEvent::Won {
+            winner: end.winner,
+            win_condition: end.win_condition,
+        });
+    }
+    events
+}
+
+// A replay with the duel after every ply, so that any ply can be jumped to.
+// Executing a command anywhere but at the last ply branches off from the replay, dropping the commands after it.
+// A replay with a command that panics ends at that command, and its ply shows the panic.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    replay: Replay,
+    states: Vec<Duel>,
+    ply: usize,
+    // The message of the last command, if it panicked.
+    panic: Option<String>,
+}
+
+impl Timeline {
+    pub fn new(mut replay: Replay) -> Result<Self, ReplayError> {
+        let (states, panic) = replay.states()?;
+        replay.commands_list.truncate(states.len() - 1);
+        Ok(Self {
+            replay,
+            states,
+            ply: 0,
+            panic,
+        })
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    // The amount of commands, so the last ply is len().
+    pub fn len(&self) -> usize {
+        self.replay.commands_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replay.commands_list.is_empty()
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    // The duel after the first ply() commands.
+    pub fn duel(&self) -> &Duel {
+        &self.states[self.ply]
+    }
+
+    // Plies past the end go to the last ply.
+    pub fn seek(&mut self, ply: usize) {
+        self.ply = ply.min(self.len());
+    }
+
+    pub fn step_forward(&mut self) {
+        self.seek(self.ply + 1);
+    }
+
+    pub fn step_backward(&mut self) {
+        self.seek(self.ply.saturating_sub(1));
+    }
+
+    // The command that led to the current ply, if it is not the start of the duel.
+    pub fn command(&self) -> Option<&DuelCommandEnum> {
+        self.ply
+            .checked_sub(1)
+            .map(|index| &self.replay.commands_list[index])
+    }
+
+    // What the command that led to the current ply changed.
+    pub fn events(&self) -> Vec<StepEvent> {
+        match (self.ply, &self.panic) {
+            (0, _) => Vec::new(),
+            (ply, Some(message)) if ply == self.len() => vec![StepEvent::Panicked {
+                message: message.clone(),
+            }],
+            (ply, _) => step_events(&self.states[ply - 1], &self.states[ply]),
+        }
+    }
+
+    // Drops the commands after the current ply. At the ply of a command that panicked, that command is dropped too.
+    pub fn branch(&mut self) {
+        if self.panic.take().is_some() && self.ply == self.len() {
+            self.ply -= 1;
+        }
+        self.replay.commands_list.truncate(self.ply);
+        self.states.truncate(self.ply + 1);
+    }
+
+    // Executes a command after the current ply, and moves to it.
+    pub fn execute(&mut self, command: DuelCommandEnum) -> Result<(), CommandError> {
+        let mut duel = self.duel().clone();
+        duel.execute(&command)?;
+        self.branch();
+        self.replay.commands_list.push(command);
+        self.states.push(duel);
+        self.ply = self.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::{
+        command::{EndTurnCmd, HandPlaySingleCmd},
+        command_strategy::{CommandStrategy, SeededRandomCommandStrategy},
+    };
+
+    #[test]
+    fn test_timeline() {
+        let duel = Duel::random();
+        let strategy = SeededRandomCommandStrategy::new(0);
+        let mut timeline = Timeline::new(Replay::new(duel.clone())).unwrap();
+        while !matches!(timeline.duel().state, DuelStateEnum::EndState(_)) {
+            let command = strategy.get_command(timeline.duel());
+            timeline.execute(command).unwrap();
+        }
+        let plies = timeline.len();
+        assert_eq!(timeline.ply(), plies);
+        assert!(matches!(
+            timeline.events().last(),
+            Some(StepEvent::Won { .. })
+        ));
+
+        // crash files load as replays, and replay to the same duel
+        let crash = Crash {
+            starting_duel_state: duel,
+            commands_list: timeline.replay().commands_list.clone(),
+        };
+        let json = serde_json::to_string(&crash).unwrap();
+        let replay: Replay = serde_json::from_str(&json).unwrap();
+        let mut loaded = Timeline::new(replay).unwrap();
+        loaded.seek(usize::MAX);
+        assert_eq!(loaded.duel(), timeline.duel());
+
+        // every turn that ends is an event
+        loaded.seek(0);
+        assert!(loaded.command().is_none());
+        let mut turns = 0;
+        for _ in 0..plies {
+            loaded.step_forward();
+            let events = loaded.events();
+            turns += events
+                .iter()
+                .filter(|event| matches!(event, StepEvent::TurnStarted { .. }))
+                .count();
+            for event in events {
+                println!("{}", event);
+            }
+        }
+        assert_eq!(turns as u32, timeline.duel().turn);
+
+        // branching from an earlier ply drops the later commands
+        loaded.seek(3);
+        loaded.step_backward();
+        let command = strategy.get_command(loaded.duel());
+        loaded.execute(command).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded.ply(), 3);
+
+        // a crash file ends at the command that panicked, and that ply shows the panic
+        let mut crash = Replay::new(timeline.replay().starting_duel_state.clone());
+        crash.starting_duel_state.player1.hand[0] = Card {
+            id: 0,
+            stat_delta: 0,
+        };
+        crash.commands_list = vec![
+            HandPlaySingleCmd {
+                hand_index: 0,
+                face_direction: FaceDirection::Down,
+                field_index: Some(0),
+            }
+            .into(),
+            EndTurnCmd.into(),
+        ];
+        let mut crashed = Timeline::new(crash.clone()).unwrap();
+        assert_eq!(crashed.len(), 1);
+        crashed.seek(1);
+        dbg!(crashed.events());
+        assert!(matches!(
+            crashed.events().as_slice(),
+            [StepEvent::Panicked { .. }]
+        ));
+        assert_eq!(crashed.duel(), &crash.starting_duel_state);
+
+        // a command that cannot be executed is reported with its ply
+        let mut replay = loaded.replay().clone();
+        replay.commands_list.insert(1, EndTurnCmd.into());
+        assert!(matches!(
+            Timeline::new(replay),
+            Err(ReplayError::InvalidCommand { ply: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    duel::{
+        command::DuelCommandEnum,
+        command_strategy::{
+            Budget, BudgetedStrategy, CommandStrategyAdapter, SeededRandomCommandStrategy,
+        },
+        player::Player,
+        ruleset::Ruleset,
+        state::{DuelStateEnum, WinCondition},
+        PlayerEnum,
+    },
+    stats::{wilson_interval, ConfidenceInterval},
+    Card, Duel,
+};
+
+// Strategies are created per duel from a seed, so every duel is reproducible and no strategy state is shared between threads.
+pub type StrategyFactory = Arc<dyn Fn(u64) -> Box<dyn BudgetedStrategy> + Send + Sync>;
+
+pub fn random_strategy() -> StrategyFactory {
+    Arc::new(|seed| {
+        Box::new(CommandStrategyAdapter(SeededRandomCommandStrategy::new(
+            seed,
+        )))
+    })
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum Side {
+    A,
+    B,
+}
+
+#[derive(Clone)]
+pub struct DuelConfig {
+    pub seed: u64,
+    pub deck_a: Vec<Card>,
+    pub deck_b: Vec<Card>,
+    pub strategy_a: StrategyFactory,
+    pub strategy_b: StrategyFactory,
+    // Whether side A is Player1, who takes the first turn.
+    pub a_goes_first: bool,
+    pub ruleset: Ruleset,
+    // The budget both strategies get for every command.
+    pub budget: Budget,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DuelOutcome {
+    pub seed: u64,
+    pub winner: Side,
+    pub win_condition: WinCondition,
+    pub turns: u32,
+    // How many times each card (by ID) was played from the hand, per side.
+    pub card_usage_a: HashMap<usize, usize>,
+    pub card_usage_b: HashMap<usize, usize>,
+}
+
+// A duel that panicked instead of finishing.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FailedDuel {
+    // The position of the duel's config.
+    pub index: usize,
+    pub seed: u64,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SideSummary {
+    pub wins: usize,
+    pub win_rate: ConfidenceInterval,
+    pub wins_by_condition: HashMap<WinCondition, usize>,
+    pub card_usage: HashMap<usize, usize>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SimulationSummary {
+    // The duels that finished. The averages and win rates only count these.
+    pub duels: usize,
+    pub average_turns: f64,
+    pub a: SideSummary,
+    pub b: SideSummary,
+    // The outcome of every duel, in the same order as the configs. None for the duels in failures.
+    pub outcomes: Vec<Option<DuelOutcome>>,
+    pub failures: Vec<FailedDuel>,
+}
+
+fn played_card_ids(command: &DuelCommandEnum, duel: &Duel) -> Vec<usize> {
+    let hand = &duel.get_player().hand;
+    match command {
+        DuelCommandEnum::HandPlaySingleCmd(cmd) => vec![hand[cmd.hand_index].id],
+        DuelCommandEnum::HandPlayMultipleCmd(cmd) => cmd
+            .hand_indices
+            .iter()
+            .map(|&index| hand[index].id)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn play_duel(config: &DuelConfig) -> DuelOutcome {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut deck_a = config.deck_a.clone();
+    let mut deck_b = config.deck_b.clone();
+    deck_a.shuffle(&mut rng);
+    deck_b.shuffle(&mut rng);
+    let mut strategy_a = (config.strategy_a)(rng.gen());
+    let mut strategy_b = (config.strategy_b)(rng.gen());
+
+    let (mut duel, a_enum) = if config.a_goes_first {
+        (
+            Duel::with_ruleset(
+                Player::with_ruleset(deck_a, &config.ruleset),
+                Player::with_ruleset(deck_b, &config.ruleset),
+                config.ruleset.clone(),
+            ),
+            PlayerEnum::Player1,
+        )
+    } else {
+        (
+            Duel::with_ruleset(
+                Player::with_ruleset(deck_b, &config.ruleset),
+                Player::with_ruleset(deck_a, &config.ruleset),
+                config.ruleset.clone(),
+            ),
+            PlayerEnum::Player2,
+        )
+    };
+
+    let mut card_usage_a = HashMap::new();
+    let mut card_usage_b = HashMap::new();
+
+    loop {
+        if let DuelStateEnum::EndState(end_state) = &duel.state {
+            strategy_a.on_duel_end(&duel);
+            strategy_b.on_duel_end(&duel);
+            return DuelOutcome {
+                seed: config.seed,
+                winner: if end_state.winner == a_enum {
+                    Side::A
+                } else {
+                    Side::B
+                },
+                win_condition: end_state.win_condition,
+                turns: duel.turn,
+                card_usage_a,
+                card_usage_b,
+            };
+        }
+
+        let (strategy, enemy_strategy, card_usage) = if duel.get_player_enum() == a_enum {
+            (&mut strategy_a, &mut strategy_b, &mut card_usage_a)
+        } else {
+            (&mut strategy_b, &mut strategy_a, &mut card_usage_b)
+        };
+        let command = strategy.decide(&duel, config.budget).command;
+        for card_id in played_card_ids(&command, &duel) {
+            *card_usage.entry(card_id).or_insert(0) += 1;
+        }
+        duel.execute(&command).unwrap();
+        enemy_strategy.on_enemy_command(&duel, &command);
+    }
+}
+
+fn summarize_side(outcomes: &[DuelOutcome], side: Side) -> SideSummary {
+    let mut wins_by_condition = HashMap::new();
+    let mut card_usage = HashMap::new();
+    for outcome in outcomes {
+        if outcome.winner == side {
+            *wins_by_condition.entry(outcome.win_condition).or_insert(0) += 1;
+        }
+        let usage = match side {
+            Side::A => &outcome.card_usage_a,
+            Side::B => &outcome.card_usage_b,
+        };
+        for (&card_id, &count) in usage {
+            *card_usage.entry(card_id).or_insert(0) += count;
+        }
+    }
+    let wins = wins_by_condition.values().sum::<usize>();
+    SideSummary {
+        wins,
+        win_rate: wilson_interval(wins as f64, outcomes.len() as f64),
+        wins_by_condition,
+        card_usage,
+    }
+}
+
+pub fn summarize(results: Vec<Result<DuelOutcome, FailedDuel>>) -> SimulationSummary {
+    let mut failures = Vec::new();
+    let all_outcomes = results
+        .into_iter()
+        .map(|result| result.map_err(|failure| failures.push(failure)).ok())
+        .collect::<Vec<_>>();
+    let outcomes = all_outcomes.iter().flatten().cloned().collect::<Vec<_>>();
+    let average_turns = if outcomes.is_empty() {
+        0.0
+    } else {
+        outcomes
+            .iter()
+            .map(|outcome| outcome.turns as f64)
+            .sum::<f64>()
+            / outcomes.len() as f64
+    };
+    SimulationSummary {
+        duels: outcomes.len(),
+        average_turns,
+        a: summarize_side(&outcomes, Side::A),
+        b: summarize_side(&outcomes, Side::B),
+        outcomes: all_outcomes,
+        failures,
+    }
+}
+
+// The message a panic was started with, e.g. by panic! or unwrap.
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".to_string())
+}
+
+// Plays every duel using a pool of `workers` threads, and aggregates the results.
+// A duel that panics is reported in the summary's failures, rather than ending the whole batch.
+pub fn simulate(configs: &[DuelConfig], workers: usize) -> SimulationSummary {
+    let next_index = AtomicUsize::new(0);
+    let outcomes = Mutex::new(vec![None; configs.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| loop {
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                if index >= configs.len() {
+                    break;
+                }
+                let config = &configs[index];
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| play_duel(config))).map_err(
+                    |payload| FailedDuel {
+                        index,
+                        seed: config.seed,
+                        message: panic_message(payload.as_ref()),
+                    },
+                );
+                outcomes.lock().unwrap()[index] = Some(outcome);
+            });
+        }
+    });
+
+    summarize(
+        outcomes
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|outcome| outcome.unwrap())
+            .collect(),
+    )
+}
+
+// Builds the configs for `duels` duels between two decks, with consecutive seeds and alternating first player.
+pub fn matchup_configs(
+    deck_a: &[Card],
+    deck_b: &[Card],
+    strategy_a: StrategyFactory,
+    strategy_b: StrategyFactory,
+    ruleset: &Ruleset,
+    duels: usize,
+    seed: u64,
+) -> Vec<DuelConfig> {
+    (0..duels)
+        .map(|i| DuelConfig {
+            seed: seed.wrapping_add(i as u64),
+            deck_a: deck_a.to_vec(),
+            deck_b: deck_b.to_vec(),
+            strategy_a: strategy_a.clone(),
+            strategy_b: strategy_b.clone(),
+            a_goes_first: i % 2 == 0,
+            ruleset: ruleset.clone(),
+            budget: Budget::unlimited(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duel::deck::generate_random_deck;
+
+    #[test]
+    fn test_simulate() {
+        let configs = matchup_configs(
+            &generate_random_deck(),
+            &generate_random_deck(),
+            random_strategy(),
+            random_strategy(),
+            &Ruleset::default(),
+            20,
+            0,
+        );
+        let summary = simulate(&configs, 4);
+        assert_eq!(summary.duels, 20);
+        assert_eq!(summary.a.wins + summary.b.wins, 20);
+        assert!(summary.average_turns > 0.0);
+        assert!(summary.a.win_rate.contains(summary.a.wins as f64 / 20.0));
+
+        // the same seeds give the same results, regardless of the amount of workers
+        let again = simulate(&configs, 1);
+        assert_eq!(summary.outcomes, again.outcomes);
+
+        // a duel that panics only fails itself
+        let mut configs = configs;
+        configs[3].strategy_b = Arc::new(|_| panic!("broken strategy"));
+        let summary = simulate(&configs, 4);
+        dbg!(&summary.failures);
+        assert_eq!(summary.duels, 19);
+        assert_eq!(summary.failures[0].index, 3);
+        assert_eq!(summary.failures[0].message, "broken strategy");
+        assert!(summary.outcomes[3].is_none());
+    }
+}
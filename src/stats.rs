@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+// z-score for a two-sided 95% confidence interval
+pub const Z_95: f64 = 1.959964;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct ConfidenceInterval {
+    pub estimate: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ConfidenceInterval {
+    pub fn contains(&self, value: f64) -> bool {
+        self.lower <= value && value <= self.upper
+    }
+}
+
+// Wilson score interval for a proportion, e.g. a win rate.
+// Unlike the normal approximation, it stays within 0..=1 and behaves well for small sample sizes or rates close to 0 or 1.
+pub fn wilson_interval(successes: f64, trials: f64) -> ConfidenceInterval {
+    if trials <= 0.0 {
+        return ConfidenceInterval {
+            estimate: 0.0,
+            lower: 0.0,
+            upper: 1.0,
+        };
+    }
+    let p = successes / trials;
+    let z2 = Z_95 * Z_95;
+    let denominator = 1.0 + z2 / trials;
+    let centre = (p + z2 / (2.0 * trials)) / denominator;
+    let margin =
+        Z_95 * (p * (1.0 - p) / trials + z2 / (4.0 * trials * trials)).sqrt() / denominator;
+    ConfidenceInterval {
+        estimate: p,
+        lower: (centre - margin).max(0.0),
+        upper: (centre + margin).min(1.0),
+    }
+}
+
+// Normal approximation interval for the mean of a sample.
+pub fn mean_interval(samples: &[f64]) -> ConfidenceInterval {
+    let n = samples.len() as f64;
+    if samples.is_empty() {
+        return ConfidenceInterval {
+            estimate: 0.0,
+            lower: 0.0,
+            upper: 0.0,
+        };
+    }
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = if samples.len() > 1 {
+        samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    let margin = Z_95 * (variance / n).sqrt();
+    ConfidenceInterval {
+        estimate: mean,
+        lower: mean - margin,
+        upper: mean + margin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confidence_intervals() {
+        let interval = wilson_interval(50.0, 100.0);
+        assert_eq!(interval.estimate, 0.5);
+        assert!((interval.lower - 0.404).abs() < 0.001);
+        assert!((interval.upper - 0.596).abs() < 0.001);
+
+        // a perfect record still leaves room for losses
+        let interval = wilson_interval(10.0, 10.0);
+        assert!((interval.upper - 1.0).abs() < 1e-9);
+        assert!(interval.lower < 0.75);
+
+        let interval = mean_interval(&[1.0, 2.0, 3.0]);
+        assert_eq!(interval.estimate, 2.0);
+        assert!(interval.contains(1.0) && interval.contains(3.0));
+    }
+}
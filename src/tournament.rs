@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    duel::{command_strategy::Budget, ruleset::Ruleset},
+    simulate::{simulate, DuelConfig, Side, StrategyFactory},
+    stats::{wilson_interval, ConfidenceInterval},
+    Card,
+};
+
+// The rating of an entrant that wins exactly as often as it loses.
+pub const BASE_RATING: f64 = 1500.0;
+
+// A named strategy playing a deck.
+#[derive(Clone)]
+pub struct Entrant {
+    pub name: String,
+    pub strategy: StrategyFactory,
+    pub deck: Vec<Card>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    // Every entrant plays every other entrant once.
+    RoundRobin,
+    // Each round, entrants with similar scores are paired, avoiding rematches where possible.
+    // With an odd number of entrants, the lowest ranked one that has not had a bye yet sits out the round.
+    Swiss { rounds: usize },
+}
+
+#[derive(Clone)]
+pub struct TournamentConfig {
+    pub format: Format,
+    // Every match plays each of these seeds twice, once with each entrant going first, so neither is luckier with the draws.
+    pub seeds: Vec<u64>,
+    pub ruleset: Ruleset,
+    // The budget every strategy gets for every command.
+    pub budget: Budget,
+    pub workers: usize,
+}
+
+// One entrant's record against another.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HeadToHead {
+    pub wins: usize,
+    pub duels: usize,
+    pub win_rate: ConfidenceInterval,
+    // Whether the win rate is different from 50% with 95% confidence.
+    pub significant: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Standing {
+    pub name: String,
+    // The maximum likelihood Elo rating given every duel of the tournament, which does not depend on the order of the duels.
+    pub rating: f64,
+    pub wins: usize,
+    pub duels: usize,
+    // 1 for every match won, 0.5 for every match drawn.
+    pub match_points: f64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TournamentReport {
+    // Ordered by rating, best first.
+    pub standings: Vec<Standing>,
+    // head_to_head[i][j] is the record of entrant i against entrant j, in the order the entrants were given.
+    // It is None for entrants that did not meet.
+    pub head_to_head: Vec<Vec<Option<HeadToHead>>>,
+}
+
+// The amount of wins of each entrant against each other entrant, and the amount of duels between them.
+struct Results {
+    wins: Vec<Vec<usize>>,
+    duels: Vec<Vec<usize>>,
+}
+
+impl Results {
+    fn new(entrants: usize) -> Self {
+        Self {
+            wins: vec![vec![0; entrants]; entrants],
+            duels: vec![vec![0; entrants]; entrants],
+        }
+    }
+
+    fn match_points(&self, entrant: usize) -> f64 {
+        (0..self.wins.len())
+            .filter(|&other| self.duels[entrant][other] > 0)
+            .map(|other| {
+                let (wins, losses) = (self.wins[entrant][other], self.wins[other][entrant]);
+                match wins.cmp(&losses) {
+                    std::cmp::Ordering::Greater => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Less => 0.0,
+                }
+            })
+            .sum()
+    }
+
+    // Fits a Bradley-Terry model with the minorization-maximization algorithm, and converts the strengths to Elo.
+    // Every entrant also gets a virtual win and loss against an entrant rated BASE_RATING, so that an entrant that
+    // won or lost every duel still gets a finite rating.
+    fn ratings(&self) -> Vec<f64> {
+        let entrants = self.wins.len();
+        let mut strengths = vec![1.0; entrants];
+        for _ in 0..1000 {
+            let previous = strengths.clone();
+            for i in 0..entrants {
+                let wins = self.wins[i].iter().sum::<usize>() as f64 + 1.0;
+                let mut denominator = 2.0 / (previous[i] + 1.0);
+                for j in 0..entrants {
+                    if self.duels[i][j] > 0 {
+                        denominator += self.duels[i][j] as f64 / (previous[i] + previous[j]);
+                    }
+                }
+                strengths[i] = wins / denominator;
+            }
+            let change = strengths
+                .iter()
+                .zip(&previous)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            if change < 1e-12 {
+                break;
+            }
+        }
+        strengths
+            .into_iter()
+            .map(|strength| BASE_RATING + 400.0 * strength.log10())
+            .collect()
+    }
+}
+
+// Plays every match of a round at once, so the workers are kept busy across matches.
+fn play_round(
+    entrants: &[Entrant],
+    pairings: &[(usize, usize)],
+    config: &TournamentConfig,
+    results: &mut Results,
+) {
+    let configs = pairings
+        .iter()
+        .flat_map(|&(a, b)| {
+            config.seeds.iter().flat_map(move |&seed| {
+                [true, false].map(|a_goes_first| DuelConfig {
+                    seed,
+                    deck_a: entrants[a].deck.clone(),
+                    deck_b: entrants[b].deck.clone(),
+                    strategy_a: entrants[a].strategy.clone(),
+                    strategy_b: entrants[b].strategy.clone(),
+                    a_goes_first,
+                    ruleset: config.ruleset.clone(),
+                    budget: config.budget,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    let summary = simulate(&configs, config.workers);
+
+    let duels_per_match = config.seeds.len() * 2;
+    for (&(a, b), outcomes) in pairings
+        .iter()
+        .zip(summary.outcomes.chunks(duels_per_match.max(1)))
+    {
+        // duels that panicked are left out of the standings
+        for outcome in outcomes.iter().flatten() {
+            let (winner, loser) = match outcome.winner {
+                Side::A => (a, b),
+                Side::B => (b, a),
+            };
+            results.wins[winner][loser] += 1;
+            results.duels[a][b] += 1;
+            results.duels[b][a] += 1;
+        }
+    }
+}
+
+fn round_robin_pairings(entrants: usize) -> Vec<(usize, usize)> {
+    (0..entrants)
+        .flat_map(|a| (a + 1..entrants).map(move |b| (a, b)))
+        .collect()
+}
+
+fn swiss_pairings(results: &Results, byes: &mut [bool]) -> Vec<(usize, usize)> {
+    let entrants = results.wins.len();
+    // ties are broken by the amount of duels won, and then by the order the entrants were given
+    let mut ranking = (0..entrants).collect::<Vec<_>>();
+    ranking.sort_by(|&a, &b| {
+        let key = |entrant: usize| {
+            (
+                results.match_points(entrant),
+                results.wins[entrant].iter().sum::<usize>(),
+            )
+        };
+        key(b).partial_cmp(&key(a)).unwrap()
+    });
+
+    if entrants % 2 == 1 {
+        let bye = ranking
+            .iter()
+            .rposition(|&entrant| !byes[entrant])
+            .unwrap_or(ranking.len() - 1);
+        byes[ranking[bye]] = true;
+        ranking.remove(bye);
+    }
+
+    let mut pairings = Vec::new();
+    while let Some(a) = (!ranking.is_empty()).then(|| ranking.remove(0)) {
+        // the closest ranked opponent that has not been played yet, or the closest one if all have been played
+        let opponent = ranking
+            .iter()
+            .position(|&b| results.duels[a][b] == 0)
+            .unwrap_or(0);
+        pairings.push((a, ranking.remove(opponent)));
+    }
+    pairings
+}
+
+pub fn run_tournament(entrants: &[Entrant], config: &TournamentConfig) -> TournamentReport {
+    let mut results = Results::new(entrants.len());
+    match config.format {
+        Format::RoundRobin => play_round(
+            entrants,
+            &round_robin_pairings(entrants.len()),
+            config,
+            &mut results,
+        ),
+        Format::Swiss { rounds } => {
+            let mut byes = vec![false; entrants.len()];
+            for _ in 0..rounds {
+                let pairings = swiss_pairings(&results, &mut byes);
+                play_round(entrants, &pairings, config, &mut results);
+            }
+        }
+    }
+
+    let ratings = results.ratings();
+    let mut standings = entrants
+        .iter()
+        .enumerate()
+        .map(|(i, entrant)| Standing {
+            name: entrant.name.clone(),
+            rating: ratings[i],
+            wins: results.wins[i].iter().sum(),
+            duels: results.duels[i].iter().sum(),
+            match_points: results.match_points(i),
+        })
+        .collect::<Vec<_>>();
+    standings.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+
+    let head_to_head = (0..entrants.len())
+        .map(|i| {
+            (0..entrants.len())
+                .map(|j| {
+                    let duels = results.duels[i][j];
+                    (duels > 0).then(|| {
+                        let wins = results.wins[i][j];
+                        let win_rate = wilson_interval(wins as f64, duels as f64);
+                        HeadToHead {
+                            wins,
+                            duels,
+                            win_rate,
+                            significant: !win_rate.contains(0.5),
+                        }
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    TournamentReport {
+        standings,
+        head_to_head,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{duel::deck::generate_random_deck, simulate::random_strategy};
+
+    #[test]
+    fn test_tournament() {
+        let entrants = (0..5)
+            .map(|i| Entrant {
+                name: format!("Random {}", i),
+                strategy: random_strategy(),
+                deck: generate_random_deck(),
+            })
+            .collect::<Vec<_>>();
+        let mut config = TournamentConfig {
+            format: Format::RoundRobin,
+            seeds: (0..4).collect(),
+            ruleset: Ruleset::default(),
+            budget: Budget::unlimited(),
+            workers: 4,
+        };
+
+        let report = run_tournament(&entrants, &config);
+        dbg!(&report.standings);
+        for i in 0..entrants.len() {
+            assert!(report.head_to_head[i][i].is_none());
+            for j in 0..entrants.len() {
+                if let (Some(a), Some(b)) = (&report.head_to_head[i][j], &report.head_to_head[j][i])
+                {
+                    assert_eq!(a.duels, 8);
+                    assert_eq!(a.wins + b.wins, 8);
+                }
+            }
+        }
+        assert!(report
+            .standings
+            .windows(2)
+            .all(|pair| pair[0].rating >= pair[1].rating));
+        assert_eq!(
+            report
+                .standings
+                .iter()
+                .map(|standing| standing.duels)
+                .sum::<usize>(),
+            10 * 8 * 2
+        );
+
+        // with an odd number of entrants, every Swiss round has one bye
+        config.format = Format::Swiss { rounds: 3 };
+        let report = run_tournament(&entrants, &config);
+        assert_eq!(
+            report
+                .standings
+                .iter()
+                .map(|standing| standing.duels)
+                .sum::<usize>(),
+            3 * 2 * 8 * 2
+        );
+        assert!(report.standings.iter().all(|standing| standing.duels >= 16));
+        // the same seeds give the same results
+        assert_eq!(run_tournament(&entrants, &config), report);
+    }
+}